@@ -1,7 +1,17 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::fs;
 use std::path::PathBuf;
+use crate::error::AppError;
+use crate::ollama::{GenerationParams, ModelOptions};
+use crate::persist::{self, Schema, StorageWarning};
+use crate::provider::ProviderConfig;
+
+// agents.json format history:
+// 0 - bare list of agents (also the format of the bundled agents.json)
+// 1 - versioned envelope
+pub const AGENTS_SCHEMA: Schema = Schema {
+    migrations: &[persist::add_envelope],
+};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Agent {
@@ -11,6 +21,47 @@ pub struct Agent {
     pub description: String,
     pub capabilities: Vec<String>,
     pub model: String,
+    // Server that runs `model`; agents saved before providers existed use the local Ollama
+    #[serde(default)]
+    pub provider: ProviderConfig,
+    // Generation settings; all optional so older agents.json files still load
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    // Wraps each user message; `{input}` is replaced with the message text
+    #[serde(default)]
+    pub prompt_template: Option<String>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub num_ctx: Option<u32>,
+    #[serde(default)]
+    pub stop: Vec<String>,
+    #[serde(default)]
+    pub seed: Option<i64>,
+}
+
+impl Agent {
+    pub fn generation_params(&self) -> GenerationParams {
+        GenerationParams {
+            system: self.system_prompt.clone().filter(|s| !s.trim().is_empty()),
+            options: ModelOptions {
+                temperature: self.temperature,
+                top_p: self.top_p,
+                num_ctx: self.num_ctx,
+                stop: self.stop.clone(),
+                seed: self.seed,
+            },
+        }
+    }
+
+    pub fn render_prompt(&self, input: &str) -> String {
+        match &self.prompt_template {
+            Some(template) if template.contains("{input}") => template.replace("{input}", input),
+            _ => input.to_string(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -32,6 +83,8 @@ pub enum TaskStatus {
 
 pub struct AgentManager {
     agents: HashMap<String, Agent>,
+    // Problem found with the config file when it was loaded, if any
+    load_warning: Option<StorageWarning>,
 }
 
 impl AgentManager {
@@ -43,18 +96,20 @@ impl AgentManager {
         let mut agents = HashMap::new();
         
         // Try to load from config file
+        let mut load_warning = None;
         if let Some(path) = config_path {
-            if let Ok(contents) = fs::read_to_string(&path) {
-                if let Ok(agent_list) = serde_json::from_str::<Vec<Agent>>(&contents) {
-                    for agent in agent_list {
-                        agents.insert(agent.id.clone(), agent);
-                    }
-                    println!("Loaded {} agents from config file", agents.len());
-                    return AgentManager { agents };
-                } else {
-                    eprintln!("Failed to parse agents config file, using defaults");
+            let loaded = persist::read_json::<Vec<Agent>>(&path, &AGENTS_SCHEMA);
+            if let Some(warning) = &loaded.warning {
+                eprintln!("{}", warning.message);
+            }
+            if let Some(agent_list) = loaded.value {
+                for agent in agent_list {
+                    agents.insert(agent.id.clone(), agent);
                 }
+                println!("Loaded {} agents from config file", agents.len());
+                return AgentManager { agents, load_warning: loaded.warning };
             }
+            load_warning = loaded.warning;
         }
         
         // Fallback to default agents if config not found or failed to load
@@ -71,6 +126,14 @@ impl AgentManager {
                     "analysis".to_string(),
                 ],
                 model: "llama3.2".to_string(),
+                provider: ProviderConfig::default(),
+                system_prompt: Some("You are a helpful, knowledgeable assistant. Answer clearly and concisely.".to_string()),
+                prompt_template: None,
+                temperature: Some(0.7),
+                top_p: None,
+                num_ctx: None,
+                stop: Vec::new(),
+                seed: None,
             },
         );
 
@@ -88,6 +151,14 @@ impl AgentManager {
                     "refactoring".to_string(),
                 ],
                 model: "codellama".to_string(),
+                provider: ProviderConfig::default(),
+                system_prompt: Some("You are an expert software engineer. Give precise, working code with brief explanations, and point out bugs and edge cases.".to_string()),
+                prompt_template: None,
+                temperature: Some(0.2),
+                top_p: Some(0.9),
+                num_ctx: Some(4096),
+                stop: Vec::new(),
+                seed: None,
             },
         );
 
@@ -104,11 +175,19 @@ impl AgentManager {
                     "visualization".to_string(),
                 ],
                 model: "llama3.2".to_string(),
+                provider: ProviderConfig::default(),
+                system_prompt: Some("You are a data analyst. Be precise and structured: summarize key findings, support them with numbers from the data, and call out uncertainty.".to_string()),
+                prompt_template: None,
+                temperature: Some(0.3),
+                top_p: None,
+                num_ctx: Some(4096),
+                stop: Vec::new(),
+                seed: None,
             },
         );
 
         println!("Using {} default agents", agents.len());
-        AgentManager { agents }
+        AgentManager { agents, load_warning }
     }
 
     pub fn get_agent(&self, id: &str) -> Option<&Agent> {
@@ -136,14 +215,18 @@ impl AgentManager {
         }
     }
 
-    pub fn save_to_file(&self, path: &PathBuf) -> Result<(), String> {
+    pub fn save_to_file(&self, path: &PathBuf) -> Result<(), AppError> {
         let agents_vec: Vec<&Agent> = self.agents.values().collect();
-        let json = serde_json::to_string_pretty(&agents_vec)
-            .map_err(|e| format!("Failed to serialize agents: {}", e))?;
+        let json = persist::to_versioned_json(&AGENTS_SCHEMA, &agents_vec)
+            .map_err(|e| AppError::internal(format!("Failed to serialize agents: {}", e)))?;
         
-        fs::write(path, json)
-            .map_err(|e| format!("Failed to write agents file: {}", e))?;
+        persist::write_with_backup(path, &json)
+            .map_err(|e| AppError::io(path.display().to_string(), e))?;
         
         Ok(())
     }
+
+    pub fn load_warning(&self) -> Option<StorageWarning> {
+        self.load_warning.clone()
+    }
 }
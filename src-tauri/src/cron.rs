@@ -0,0 +1,303 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+
+// Standard 5-field cron: minute hour day-of-month month day-of-week.
+// Supports `*`, lists (`1,15`), ranges (`8-17`), steps (`*/15`, `0-30/10`), month and
+// weekday names (`JAN`, `MON-FRI`), 7 as Sunday, and the `DOW#N` extension for the
+// Nth weekday of the month (`MON#1` = first Monday).
+// As in Vixie cron, when both day fields are restricted a day matches if either does.
+#[derive(Debug, Clone)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u32,
+    days_of_month: u32,
+    months: u32,
+    days_of_week: u32,
+    // (weekday, n) pairs from `DOW#N` items
+    nth_weekdays: Vec<(u32, u32)>,
+    dom_restricted: bool,
+    dow_restricted: bool,
+}
+
+const MONTH_NAMES: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+const WEEKDAY_NAMES: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+// Five years of days covers every combination, including Feb 29
+const MAX_DAYS_SEARCHED: u32 = 366 * 5;
+
+struct FieldSpec {
+    name: &'static str,
+    min: u32,
+    max: u32,
+    names: &'static [&'static str],
+    // Value of names[0]
+    names_offset: u32,
+}
+
+const MINUTE: FieldSpec = FieldSpec { name: "minute", min: 0, max: 59, names: &[], names_offset: 0 };
+const HOUR: FieldSpec = FieldSpec { name: "hour", min: 0, max: 23, names: &[], names_offset: 0 };
+const DAY_OF_MONTH: FieldSpec = FieldSpec { name: "day-of-month", min: 1, max: 31, names: &[], names_offset: 0 };
+const MONTH: FieldSpec = FieldSpec { name: "month", min: 1, max: 12, names: &MONTH_NAMES, names_offset: 1 };
+// 7 is accepted as Sunday and folded onto 0 after parsing
+const DAY_OF_WEEK: FieldSpec = FieldSpec { name: "day-of-week", min: 0, max: 7, names: &WEEKDAY_NAMES, names_offset: 0 };
+
+impl CronSchedule {
+    pub fn parse(expr: &str) -> Result<CronSchedule, String> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!(
+                "Invalid cron expression '{}': expected 5 fields (minute hour day-of-month month day-of-week), found {}",
+                expr,
+                fields.len()
+            ));
+        }
+
+        let wrap = |e: String| format!("Invalid cron expression '{}': {}", expr, e);
+
+        let (dow_field, nth_weekdays) = split_nth_weekdays(fields[4]).map_err(wrap)?;
+        let mut days_of_week = if dow_field.is_empty() {
+            0
+        } else {
+            parse_field(&dow_field, &DAY_OF_WEEK).map_err(wrap)? as u32
+        };
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week & !(1 << 7)) | 1;
+        }
+
+        let schedule = CronSchedule {
+            minutes: parse_field(fields[0], &MINUTE).map_err(wrap)?,
+            hours: parse_field(fields[1], &HOUR).map_err(wrap)? as u32,
+            days_of_month: parse_field(fields[2], &DAY_OF_MONTH).map_err(wrap)? as u32,
+            months: parse_field(fields[3], &MONTH).map_err(wrap)? as u32,
+            days_of_week,
+            nth_weekdays,
+            dom_restricted: !fields[2].starts_with('*'),
+            dow_restricted: !fields[4].starts_with('*'),
+        };
+
+        let reference = NaiveDate::from_ymd_opt(2000, 1, 1).and_then(|d| d.and_hms_opt(0, 0, 0));
+        if reference.and_then(|t| schedule.next_after(t)).is_none() {
+            return Err(wrap("the schedule never fires (no matching date)".to_string()));
+        }
+
+        Ok(schedule)
+    }
+
+    // First fire time strictly after `after`, in the same (naive) clock the caller uses
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let mut date = start.date();
+
+        for _ in 0..MAX_DAYS_SEARCHED {
+            if self.matches_date(date) {
+                let first_day = date == start.date();
+                let from_hour = if first_day { start.hour() } else { 0 };
+                for hour in from_hour..24 {
+                    if self.hours & (1 << hour) == 0 {
+                        continue;
+                    }
+                    let from_minute = if first_day && hour == start.hour() { start.minute() } else { 0 };
+                    for minute in from_minute..60 {
+                        if self.minutes & (1 << minute) != 0 {
+                            return date.and_hms_opt(hour, minute, 0);
+                        }
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+
+    pub fn matches_date(&self, date: NaiveDate) -> bool {
+        if self.months & (1 << date.month()) == 0 {
+            return false;
+        }
+
+        let dom_match = self.days_of_month & (1 << date.day()) != 0;
+        let weekday = date.weekday().num_days_from_sunday();
+        let nth = (date.day() - 1) / 7 + 1;
+        let dow_match = self.days_of_week & (1 << weekday) != 0
+            || self.nth_weekdays.iter().any(|&(wd, n)| wd == weekday && n == nth);
+
+        match (self.dom_restricted, self.dow_restricted) {
+            (true, true) => dom_match || dow_match,
+            (true, false) => dom_match,
+            (false, true) => dow_match,
+            (false, false) => true,
+        }
+    }
+}
+
+// Pulls `DOW#N` items out of the day-of-week field, returning the remaining items
+fn split_nth_weekdays(field: &str) -> Result<(String, Vec<(u32, u32)>), String> {
+    let mut rest = Vec::new();
+    let mut nth = Vec::new();
+    for item in field.split(',') {
+        match item.split_once('#') {
+            Some((day, n)) => {
+                let weekday = parse_value(day, &DAY_OF_WEEK)? % 7;
+                let n: u32 = n
+                    .parse()
+                    .ok()
+                    .filter(|n| (1..=5).contains(n))
+                    .ok_or_else(|| format!("day-of-week field: '{}' must use #1 to #5", item))?;
+                nth.push((weekday, n));
+            }
+            None => rest.push(item),
+        }
+    }
+    Ok((rest.join(","), nth))
+}
+
+fn parse_field(field: &str, spec: &FieldSpec) -> Result<u64, String> {
+    let mut bits: u64 = 0;
+    for item in field.split(',') {
+        if item.is_empty() {
+            return Err(format!("{} field: empty list item in '{}'", spec.name, field));
+        }
+
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => {
+                // A step longer than the field can only ever hit the start value
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|s| *s > 0 && *s <= spec.max - spec.min + 1)
+                    .ok_or_else(|| {
+                        format!(
+                            "{} field: step in '{}' must be between 1 and {}",
+                            spec.name,
+                            item,
+                            spec.max - spec.min + 1
+                        )
+                    })?;
+                (range, step)
+            }
+            None => (item, 1),
+        };
+
+        let (start, end) = if range == "*" {
+            (spec.min, spec.max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (parse_value(a, spec)?, parse_value(b, spec)?)
+        } else {
+            let value = parse_value(range, spec)?;
+            // `5/15` means "from 5 to the end, every 15"
+            if item.contains('/') { (value, spec.max) } else { (value, value) }
+        };
+
+        if start > end {
+            return Err(format!("{} field: range '{}' goes backwards", spec.name, range));
+        }
+
+        let mut value = start;
+        while value <= end {
+            bits |= 1 << value;
+            value += step;
+        }
+    }
+    Ok(bits)
+}
+
+fn parse_value(value: &str, spec: &FieldSpec) -> Result<u32, String> {
+    let upper = value.to_ascii_uppercase();
+    if let Some(pos) = spec.names.iter().position(|name| *name == upper) {
+        return Ok(pos as u32 + spec.names_offset);
+    }
+
+    let number: u32 = value
+        .parse()
+        .map_err(|_| format!("{} field: '{}' is not a number or name", spec.name, value))?;
+    if number < spec.min || number > spec.max {
+        return Err(format!(
+            "{} field: value {} is out of range {}-{}",
+            spec.name, number, spec.min, spec.max
+        ));
+    }
+    Ok(number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    // The first `count` fire times after `from`
+    fn fires(expr: &str, from: &str, count: usize) -> Vec<NaiveDateTime> {
+        let schedule = CronSchedule::parse(expr).unwrap();
+        let mut times = Vec::new();
+        let mut last = at(from);
+        for _ in 0..count {
+            last = schedule.next_after(last).unwrap();
+            times.push(last);
+        }
+        times
+    }
+
+    #[test]
+    fn ranges_lists_and_steps() {
+        assert_eq!(
+            fires("0-30/10 8-9 * * *", "2024-01-01 08:15", 5),
+            vec![
+                at("2024-01-01 08:20"),
+                at("2024-01-01 08:30"),
+                at("2024-01-01 09:00"),
+                at("2024-01-01 09:10"),
+                at("2024-01-01 09:20"),
+            ]
+        );
+        // `5/20` runs from 5 to the end of the field
+        assert_eq!(
+            fires("5/20,59 12 * * *", "2024-01-01 12:00", 4),
+            vec![at("2024-01-01 12:05"), at("2024-01-01 12:25"), at("2024-01-01 12:45"), at("2024-01-01 12:59")]
+        );
+        assert_eq!(fires("0 0 1 jan-mar/2 *", "2024-01-01 00:00", 2), vec![at("2024-03-01 00:00"), at("2025-01-01 00:00")]);
+    }
+
+    #[test]
+    fn oversized_steps_are_rejected() {
+        assert!(CronSchedule::parse("5-59/4294967295 * * * *").is_err());
+        assert!(CronSchedule::parse("*/61 * * * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+        assert!(CronSchedule::parse("*/60 * * * *").is_ok());
+    }
+
+    #[test]
+    fn seven_is_sunday() {
+        // 2024-01-07 is a Sunday
+        assert_eq!(fires("0 9 * * 7", "2024-01-01 00:00", 1), fires("0 9 * * SUN", "2024-01-01 00:00", 1));
+        assert_eq!(fires("0 9 * * 5-7", "2024-01-05 10:00", 2), vec![at("2024-01-06 09:00"), at("2024-01-07 09:00")]);
+    }
+
+    #[test]
+    fn nth_weekday_of_the_month() {
+        // First Mondays of 2024: Jan 1, Feb 5; the third Friday of January is the 19th
+        assert_eq!(fires("30 8 * * MON#1", "2024-01-01 09:00", 2), vec![at("2024-02-05 08:30"), at("2024-03-04 08:30")]);
+        assert_eq!(fires("0 0 * * 5#3,SUN#5", "2024-01-01 00:00", 2), vec![at("2024-01-19 00:00"), at("2024-02-16 00:00")]);
+        assert!(CronSchedule::parse("0 0 * * MON#6").is_err());
+    }
+
+    #[test]
+    fn restricted_day_fields_match_either() {
+        // The 13th of each month, and every Friday
+        assert_eq!(
+            fires("0 0 13 * FRI", "2024-01-01 00:00", 3),
+            vec![at("2024-01-05 00:00"), at("2024-01-12 00:00"), at("2024-01-13 00:00")]
+        );
+        // With `*` in the day-of-week field only the 13th counts
+        assert_eq!(fires("0 0 13 * *", "2024-01-01 00:00", 1), vec![at("2024-01-13 00:00")]);
+    }
+
+    #[test]
+    fn schedules_that_never_fire_are_rejected() {
+        assert!(CronSchedule::parse("0 0 30 2 *").unwrap_err().contains("never fires"));
+        assert!(CronSchedule::parse("0 0 31 4,6,9,11 *").is_err());
+        // February 29 fires in leap years only
+        assert_eq!(fires("0 0 29 2 *", "2024-03-01 00:00", 1), vec![at("2028-02-29 00:00")]);
+    }
+}
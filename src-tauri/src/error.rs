@@ -0,0 +1,111 @@
+use serde::Serialize;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+use crate::permissions::PermissionDenied;
+
+// Errors reported to the frontend. Each serializes as `{ "code": "...", "details": { ... } }`.
+// The codes are stable so the frontend can branch on them; the Display text is for people.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "code", content = "details")]
+pub enum AppError {
+    // The Ollama server refused or dropped the connection
+    OllamaUnreachable { url: String, reason: String },
+    // Same for other model providers
+    ProviderUnreachable { provider: String, url: String, reason: String },
+    ModelNotFound { provider: String, model: String },
+    // The model server answered with an error or a response we couldn't read
+    ProviderError { provider: String, message: String },
+    // No data arrived from the server within the read timeout
+    Timeout { seconds: u64 },
+    AgentNotFound { agent_id: String },
+    TaskNotFound { task_id: String },
+    SessionNotFound { session_id: String },
+    // The request was refused because of what it asked for, e.g. a schedule that fails validation
+    InvalidInput { message: String },
+    // The permission policy refused a file operation
+    PathDenied(PermissionDenied),
+    PathNotFound { path: String },
+    IoError { path: String, message: String },
+    // The Microsoft Graph token is missing, expired or revoked; sign in again
+    GraphAuthExpired,
+    // `status` is absent when the request never got an answer
+    GraphError { status: Option<u16>, message: String },
+    Internal { message: String },
+}
+
+impl AppError {
+    pub fn invalid(message: impl Into<String>) -> Self {
+        AppError::InvalidInput { message: message.into() }
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        AppError::Internal { message: message.into() }
+    }
+
+    // An I/O failure on `path`, with missing files reported as PathNotFound
+    pub fn io(path: impl Into<String>, error: io::Error) -> Self {
+        let path = path.into();
+        if error.kind() == io::ErrorKind::NotFound {
+            AppError::PathNotFound { path }
+        } else {
+            AppError::IoError { path, message: error.to_string() }
+        }
+    }
+
+    // A failed request to a model server; `timeout` is the read timeout it was sent with
+    pub fn from_request(provider: &str, url: &str, timeout: Duration, error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            AppError::Timeout { seconds: timeout.as_secs() }
+        } else if error.is_connect() || error.is_request() {
+            let reason = error.to_string();
+            if provider == crate::ollama::PROVIDER_NAME {
+                AppError::OllamaUnreachable { url: url.to_string(), reason }
+            } else {
+                AppError::ProviderUnreachable {
+                    provider: provider.to_string(),
+                    url: url.to_string(),
+                    reason,
+                }
+            }
+        } else {
+            AppError::ProviderError {
+                provider: provider.to_string(),
+                message: error.to_string(),
+            }
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::OllamaUnreachable { url, reason } => write!(f, "Ollama is not reachable at {}: {}", url, reason),
+            AppError::ProviderUnreachable { provider, url, reason } => {
+                write!(f, "{} is not reachable at {}: {}", provider, url, reason)
+            }
+            AppError::ModelNotFound { provider, model } => write!(f, "{} has no model '{}'", provider, model),
+            AppError::ProviderError { provider, message } => write!(f, "{} error: {}", provider, message),
+            AppError::Timeout { seconds } => write!(f, "Timed out after {}s waiting for the model server", seconds),
+            AppError::AgentNotFound { agent_id } => write!(f, "Agent '{}' not found", agent_id),
+            AppError::TaskNotFound { task_id } => write!(f, "Task '{}' not found", task_id),
+            AppError::SessionNotFound { session_id } => write!(f, "Session '{}' not found", session_id),
+            AppError::InvalidInput { message } => write!(f, "{}", message),
+            AppError::PathDenied(denial) => write!(f, "{}", denial),
+            AppError::PathNotFound { path } => write!(f, "{} does not exist", path),
+            AppError::IoError { path, message } => write!(f, "{}: {}", path, message),
+            AppError::GraphAuthExpired => write!(f, "Microsoft sign-in expired; sign in again"),
+            AppError::GraphError { status: Some(status), message } => write!(f, "Graph API error {}: {}", status, message),
+            AppError::GraphError { status: None, message } => write!(f, "Graph API request failed: {}", message),
+            AppError::Internal { message } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<PermissionDenied> for AppError {
+    fn from(denial: PermissionDenied) -> Self {
+        AppError::PathDenied(denial)
+    }
+}
@@ -1,7 +1,7 @@
 use std::fs;
-use std::path::PathBuf;
 use serde::{Deserialize, Serialize};
-use anyhow::Result;
+use crate::error::AppError;
+use crate::permissions::{Access, PermissionPolicy};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct FileInfo {
@@ -12,13 +12,18 @@ pub struct FileInfo {
     pub modified: String,
 }
 
-pub fn read_directory(path: &str) -> Result<Vec<FileInfo>> {
-    let entries = fs::read_dir(path)?;
+// Every function checks the permission policy first; a denial is returned as
+// `AppError::PathDenied`.
+
+pub fn read_directory(policy: &PermissionPolicy, agent_id: Option<&str>, path: &str) -> Result<Vec<FileInfo>, AppError> {
+    let path = policy.check(agent_id, path, Access::Read)?;
+    let io_error = |e| AppError::io(path.display().to_string(), e);
+    let entries = fs::read_dir(&path).map_err(io_error)?;
     let mut files = Vec::new();
 
     for entry in entries {
-        let entry = entry?;
-        let metadata = entry.metadata()?;
+        let entry = entry.map_err(io_error)?;
+        let metadata = entry.metadata().map_err(io_error)?;
         let path_buf = entry.path();
         
         let file_info = FileInfo {
@@ -26,7 +31,7 @@ pub fn read_directory(path: &str) -> Result<Vec<FileInfo>> {
             path: path_buf.to_string_lossy().to_string(),
             is_dir: metadata.is_dir(),
             size: metadata.len(),
-            modified: format!("{:?}", metadata.modified()?),
+            modified: format!("{:?}", metadata.modified().map_err(io_error)?),
         };
         
         files.push(file_info);
@@ -35,26 +40,28 @@ pub fn read_directory(path: &str) -> Result<Vec<FileInfo>> {
     Ok(files)
 }
 
-pub fn read_file(path: &str) -> Result<String> {
-    let content = fs::read_to_string(path)?;
-    Ok(content)
+pub fn read_file(policy: &PermissionPolicy, agent_id: Option<&str>, path: &str) -> Result<String, AppError> {
+    let path = policy.check(agent_id, path, Access::Read)?;
+    fs::read_to_string(&path).map_err(|e| AppError::io(path.display().to_string(), e))
 }
 
-pub fn write_file(path: &str, content: &str) -> Result<()> {
-    fs::write(path, content)?;
-    Ok(())
+pub fn write_file(policy: &PermissionPolicy, agent_id: Option<&str>, path: &str, content: &str) -> Result<(), AppError> {
+    let path = policy.check(agent_id, path, Access::Write)?;
+    fs::write(&path, content).map_err(|e| AppError::io(path.display().to_string(), e))
 }
 
-pub fn create_directory(path: &str) -> Result<()> {
-    fs::create_dir_all(path)?;
-    Ok(())
+pub fn create_directory(policy: &PermissionPolicy, agent_id: Option<&str>, path: &str) -> Result<(), AppError> {
+    let path = policy.check(agent_id, path, Access::Write)?;
+    fs::create_dir_all(&path).map_err(|e| AppError::io(path.display().to_string(), e))
 }
 
-pub fn delete_file(path: &str) -> Result<()> {
-    if PathBuf::from(path).is_dir() {
-        fs::remove_dir_all(path)?;
+pub fn delete_file(policy: &PermissionPolicy, agent_id: Option<&str>, path: &str) -> Result<(), AppError> {
+    let path = policy.check(agent_id, path, Access::Delete)?;
+    let result = if path.is_dir() {
+        policy.check_tree(agent_id, &path, Access::Delete)?;
+        fs::remove_dir_all(&path)
     } else {
-        fs::remove_file(path)?;
-    }
-    Ok(())
+        fs::remove_file(&path)
+    };
+    result.map_err(|e| AppError::io(path.display().to_string(), e))
 }
@@ -1,10 +1,10 @@
-use anyhow::{Result, anyhow};
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
 use serde_json::Value;
+use crate::error::AppError;
 
 const GRAPH_BASE_URL: &str = "https://graph.microsoft.com/v1.0";
 
-pub async fn graph_request(token: &str, endpoint: &str) -> Result<Value> {
+pub async fn graph_request(token: &str, endpoint: &str) -> Result<Value, AppError> {
     let client = Client::new();
     let url = if endpoint.starts_with("http") {
         endpoint.to_string()
@@ -17,19 +17,14 @@ pub async fn graph_request(token: &str, endpoint: &str) -> Result<Value> {
         .header("Authorization", format!("Bearer {}", token))
         .header("Content-Type", "application/json")
         .send()
-        .await?;
+        .await
+        .map_err(request_error)?;
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await?;
-        return Err(anyhow!("Graph API error {}: {}", status, error_text));
-    }
-
-    let json: Value = response.json().await?;
-    Ok(json)
+    let response = check_status(response).await?;
+    response.json().await.map_err(request_error)
 }
 
-pub async fn download_file_content(token: &str, item_id: &str) -> Result<Vec<u8>> {
+pub async fn download_file_content(token: &str, item_id: &str) -> Result<Vec<u8>, AppError> {
     let client = Client::new();
     let url = format!("{}/me/drive/items/{}/content", GRAPH_BASE_URL, item_id);
 
@@ -37,12 +32,33 @@ pub async fn download_file_content(token: &str, item_id: &str) -> Result<Vec<u8>
         .get(&url)
         .header("Authorization", format!("Bearer {}", token))
         .send()
-        .await?;
+        .await
+        .map_err(request_error)?;
+
+    let response = check_status(response).await?;
+    let bytes = response.bytes().await.map_err(request_error)?;
+    Ok(bytes.to_vec())
+}
 
-    if !response.status().is_success() {
-        return Err(anyhow!("Failed to download file: {}", response.status()));
+fn request_error(error: reqwest::Error) -> AppError {
+    AppError::GraphError {
+        status: error.status().map(|status| status.as_u16()),
+        message: error.to_string(),
     }
+}
 
-    let bytes = response.bytes().await?;
-    Ok(bytes.to_vec())
+// 401 means the token expired or was revoked
+async fn check_status(response: reqwest::Response) -> Result<reqwest::Response, AppError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    if status == StatusCode::UNAUTHORIZED {
+        return Err(AppError::GraphAuthExpired);
+    }
+    let error_text = response.text().await.unwrap_or_default();
+    Err(AppError::GraphError {
+        status: Some(status.as_u16()),
+        message: error_text,
+    })
 }
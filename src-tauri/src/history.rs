@@ -0,0 +1,323 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use chrono::{DateTime, Duration, Utc};
+use crate::error::AppError;
+use crate::persist;
+use crate::scheduler::TaskResult;
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionSettings {
+    // Keep at most this many runs (oldest are dropped first)
+    #[serde(default)]
+    pub max_entries: Option<usize>,
+    // Drop runs older than this many days
+    #[serde(default)]
+    pub max_age_days: Option<u32>,
+}
+
+impl Default for RetentionSettings {
+    fn default() -> Self {
+        RetentionSettings {
+            max_entries: Some(5000),
+            max_age_days: Some(90),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HistoryQuery {
+    #[serde(default)]
+    pub task_id: Option<String>,
+    #[serde(default)]
+    pub run_id: Option<String>,
+    // Skipped runs are neither: they only match `skipped: Some(true)`
+    #[serde(default)]
+    pub success: Option<bool>,
+    #[serde(default)]
+    pub skipped: Option<bool>,
+    #[serde(default)]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub to: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub offset: Option<usize>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryPage {
+    pub results: Vec<TaskResult>,
+    // Number of runs matching the filters, before pagination
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+// Task run history, stored as one JSON object per line and appended to after every run.
+// The file is only rewritten when retention drops old entries.
+pub struct RunHistory {
+    // Also guards the file, so appends and compactions don't interleave
+    entries: Mutex<Vec<TaskResult>>,
+    retention: Mutex<RetentionSettings>,
+    history_path: PathBuf,
+    settings_path: PathBuf,
+}
+
+impl RunHistory {
+    pub fn new(history_path: PathBuf, settings_path: PathBuf) -> Self {
+        let retention = fs::read_to_string(&settings_path)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<RetentionSettings>(&contents).ok())
+            .unwrap_or_default();
+
+        let history = RunHistory {
+            entries: Mutex::new(load_entries(&history_path)),
+            retention: Mutex::new(retention),
+            history_path,
+            settings_path,
+        };
+
+        if let Err(e) = history.apply_retention() {
+            eprintln!("Failed to apply task history retention: {}", e);
+        }
+        history
+    }
+
+    pub fn append(&self, result: TaskResult) -> Result<(), AppError> {
+        let line = serde_json::to_string(&result)
+            .map_err(|e| AppError::internal(format!("Failed to serialize task result: {}", e)))?;
+
+        let mut entries = self.entries.lock().unwrap();
+        append_line(&self.history_path, &line).map_err(|e| self.io_error(e))?;
+        entries.push(result);
+
+        let retention = self.get_retention();
+        // Compact with some slack so we don't rewrite the file after every run
+        if retention.max_entries.is_some_and(|max| entries.len() > max + max / 10) {
+            self.compact(&mut entries, &retention)?;
+        }
+        Ok(())
+    }
+
+    // Matching runs, newest first
+    pub fn query(&self, query: &HistoryQuery) -> HistoryPage {
+        let entries = self.entries.lock().unwrap();
+        let matching: Vec<&TaskResult> = entries
+            .iter()
+            .rev()
+            .filter(|r| query.task_id.as_ref().map_or(true, |id| &r.task_id == id))
+            .filter(|r| query.run_id.as_ref().map_or(true, |id| &r.run_id == id))
+            .filter(|r| query.success.map_or(true, |success| !r.skipped && r.success == success))
+            .filter(|r| query.skipped.map_or(true, |skipped| r.skipped == skipped))
+            .filter(|r| query.from.map_or(true, |from| r.executed_at >= from))
+            .filter(|r| query.to.map_or(true, |to| r.executed_at <= to))
+            .collect();
+
+        let offset = query.offset.unwrap_or(0);
+        let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        HistoryPage {
+            total: matching.len(),
+            results: matching.into_iter().skip(offset).take(limit).cloned().collect(),
+            offset,
+            limit,
+        }
+    }
+
+    // Most recent run of a task that actually executed (not skipped)
+    pub fn last_completed(&self, task_id: &str) -> Option<TaskResult> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .iter()
+            .rev()
+            .find(|r| r.task_id == task_id && !r.skipped)
+            .cloned()
+    }
+
+    pub fn get_retention(&self) -> RetentionSettings {
+        self.retention.lock().unwrap().clone()
+    }
+
+    pub fn update_retention(&self, retention: RetentionSettings) -> Result<(), AppError> {
+        let json = serde_json::to_string_pretty(&retention)
+            .map_err(|e| AppError::internal(format!("Failed to serialize retention settings: {}", e)))?;
+        fs::write(&self.settings_path, json)
+            .map_err(|e| AppError::io(self.settings_path.display().to_string(), e))?;
+
+        *self.retention.lock().unwrap() = retention;
+        self.apply_retention()
+    }
+
+    fn apply_retention(&self) -> Result<(), AppError> {
+        let retention = self.get_retention();
+        let mut entries = self.entries.lock().unwrap();
+        self.compact(&mut entries, &retention)
+    }
+
+    // Drops runs outside the retention window and rewrites the file if anything changed.
+    // `entries` is the locked list, so no append can land between the read and the rewrite.
+    fn compact(&self, entries: &mut Vec<TaskResult>, retention: &RetentionSettings) -> Result<(), AppError> {
+        let before = entries.len();
+
+        if let Some(days) = retention.max_age_days {
+            let cutoff = Utc::now() - Duration::days(days as i64);
+            entries.retain(|r| r.executed_at >= cutoff);
+        }
+        if let Some(max) = retention.max_entries {
+            if entries.len() > max {
+                let excess = entries.len() - max;
+                entries.drain(0..excess);
+            }
+        }
+
+        if entries.len() == before {
+            return Ok(());
+        }
+
+        let mut contents = String::new();
+        for entry in entries.iter() {
+            let line = serde_json::to_string(entry)
+                .map_err(|e| AppError::internal(format!("Failed to serialize task result: {}", e)))?;
+            contents.push_str(&line);
+            contents.push('\n');
+        }
+        persist::write_with_backup(&self.history_path, &contents).map_err(|e| self.io_error(e))
+    }
+
+    fn io_error(&self, error: std::io::Error) -> AppError {
+        AppError::io(self.history_path.display().to_string(), error)
+    }
+}
+
+// Appends `line` on a line of its own. If a crash cut the last line short, that line is
+// ended first so the new entry isn't joined onto it and lost with it.
+fn append_line(path: &Path, line: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+    let len = file.metadata()?.len();
+    let mut last = [b'\n'];
+    if len > 0 {
+        file.seek(SeekFrom::Start(len - 1))?;
+        file.read_exact(&mut last)?;
+    }
+    if last[0] == b'\n' {
+        writeln!(file, "{}", line)
+    } else {
+        writeln!(file, "\n{}", line)
+    }
+}
+
+// Unparseable lines (e.g. a line cut short by a crash) are skipped
+fn load_entries(path: &PathBuf) -> Vec<TaskResult> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut entries: Vec<TaskResult> = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    entries.sort_by(|a, b| a.executed_at.cmp(&b.executed_at));
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn open(dir: &TempDir) -> RunHistory {
+        RunHistory::new(dir.path().join("history.jsonl"), dir.path().join("retention.json"))
+    }
+
+    // A run of task_1, `minutes_ago` minutes before now
+    fn result(minutes_ago: i64, success: bool, skipped: bool) -> TaskResult {
+        serde_json::from_value(serde_json::json!({
+            "task_id": "task_1",
+            "task_name": "Report",
+            "agent_name": "agent",
+            "executed_at": Utc::now() - Duration::minutes(minutes_ago),
+            "prompt": "",
+            "response": format!("run {}", minutes_ago),
+            "success": success,
+            "error": null,
+            "skipped": skipped,
+        }))
+        .unwrap()
+    }
+
+    fn responses(page: &HistoryPage) -> Vec<&str> {
+        page.results.iter().map(|r| r.response.as_str()).collect()
+    }
+
+    #[test]
+    fn skipped_runs_match_neither_success_filter() {
+        let dir = TempDir::new().unwrap();
+        let history = open(&dir);
+        history.append(result(3, true, false)).unwrap();
+        history.append(result(2, false, false)).unwrap();
+        history.append(result(1, false, true)).unwrap();
+
+        let query = |success, skipped| history.query(&HistoryQuery { success, skipped, ..HistoryQuery::default() });
+        assert_eq!(responses(&query(Some(true), None)), vec!["run 3"]);
+        assert_eq!(responses(&query(Some(false), None)), vec!["run 2"]);
+        assert_eq!(responses(&query(None, Some(true))), vec!["run 1"]);
+        assert_eq!(responses(&query(None, Some(false))), vec!["run 2", "run 3"]);
+        assert_eq!(query(None, None).total, 3);
+    }
+
+    #[test]
+    fn pages_are_newest_first() {
+        let dir = TempDir::new().unwrap();
+        let history = open(&dir);
+        for minutes_ago in (1..=5).rev() {
+            history.append(result(minutes_ago, true, false)).unwrap();
+        }
+
+        let page = history.query(&HistoryQuery { offset: Some(1), limit: Some(2), ..HistoryQuery::default() });
+        assert_eq!(page.total, 5);
+        assert_eq!(responses(&page), vec!["run 2", "run 3"]);
+        let last = history.query(&HistoryQuery { offset: Some(4), limit: Some(2), ..HistoryQuery::default() });
+        assert_eq!(responses(&last), vec!["run 5"]);
+    }
+
+    #[test]
+    fn retention_drops_old_and_excess_runs_from_the_file() {
+        let dir = TempDir::new().unwrap();
+        let history = open(&dir);
+        history.append(result(3 * 24 * 60, true, false)).unwrap();
+        for minutes_ago in (1..=4).rev() {
+            history.append(result(minutes_ago, true, false)).unwrap();
+        }
+
+        history
+            .update_retention(RetentionSettings { max_entries: Some(3), max_age_days: Some(2) })
+            .unwrap();
+        assert_eq!(responses(&history.query(&HistoryQuery::default())), vec!["run 1", "run 2", "run 3"]);
+
+        // The settings and the compacted file both survive a restart
+        let reopened = open(&dir);
+        assert_eq!(reopened.get_retention().max_entries, Some(3));
+        assert_eq!(reopened.query(&HistoryQuery::default()).total, 3);
+    }
+
+    #[test]
+    fn append_after_a_truncated_line_keeps_the_new_run() {
+        let dir = TempDir::new().unwrap();
+        let good = serde_json::to_string(&result(2, true, false)).unwrap();
+        fs::write(dir.path().join("history.jsonl"), format!("{}\n{{\"task_id\":\"task_1\",\"task_na", good)).unwrap();
+
+        let history = open(&dir);
+        assert_eq!(history.query(&HistoryQuery::default()).total, 1);
+        history.append(result(1, true, false)).unwrap();
+
+        let reopened = open(&dir);
+        assert_eq!(responses(&reopened.query(&HistoryQuery::default())), vec!["run 1", "run 2"]);
+    }
+}
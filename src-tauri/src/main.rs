@@ -5,22 +5,55 @@ mod storage;
 mod graph_api;
 mod analyzer;
 mod ollama;
+mod provider;
+mod openai;
 mod agent;
 mod filesystem;
 mod scheduler;
+mod session;
+mod tools;
+mod permissions;
+mod cron;
+mod history;
+mod workflow;
+mod template;
+mod watcher;
+mod sinks;
+mod persist;
+mod settings;
+mod error;
+#[cfg(test)]
+mod testing;
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use tauri::State;
-use chrono::Utc;
+use tauri::{Manager, State};
+use chrono::{DateTime, Utc};
+use provider::LlmProvider;
+use error::AppError;
 
-// Global agent manager and task scheduler
+// Numbers streamed chat requests, so requests started in the same millisecond get distinct ids
+static NEXT_REQUEST: AtomicU64 = AtomicU64::new(1);
+
+// Global agent manager, task scheduler and session storage
 struct AppState {
     agent_manager: Mutex<agent::AgentManager>,
     task_scheduler: Arc<scheduler::TaskScheduler>,
     agents_config_path: std::path::PathBuf,
+    session_manager: Arc<session::SessionManager>,
+    // Session that chat_with_agent reads history from and appends messages to
+    active_session_id: Mutex<Option<String>>,
+    permission_policy: Arc<permissions::PermissionPolicy>,
+    // Data files found damaged at startup
+    startup_warnings: Vec<persist::StorageWarning>,
+    // App settings and the HTTP client all outgoing requests share
+    settings: Arc<settings::SettingsManager>,
 }
 
+// On failure, `error` is a message for the user and `data` holds the AppError as
+// `{ "code": ..., "details": ... }` for commands that report one
 #[derive(Debug, Serialize, Deserialize)]
 struct CommandResponse {
     success: bool,
@@ -28,6 +61,40 @@ struct CommandResponse {
     error: Option<String>,
 }
 
+fn error_response(error: AppError) -> CommandResponse {
+    CommandResponse {
+        success: false,
+        data: Some(serde_json::to_value(&error).unwrap()),
+        error: Some(error.to_string()),
+    }
+}
+
+// Payload of the `chat-token` event, emitted once per streamed token
+#[derive(Debug, Clone, Serialize)]
+struct ChatTokenEvent {
+    request_id: String,
+    token: String,
+}
+
+// Payload of the `chat-done` event, emitted once when a streamed response finishes or fails
+#[derive(Debug, Clone, Serialize)]
+struct ChatDoneEvent {
+    request_id: String,
+    done: bool,
+    error: Option<String>,
+    // The failure as `{ "code": ..., "details": ... }`
+    error_details: Option<AppError>,
+    stats: Option<ollama::GenerationStats>,
+}
+
+// Payload of the `model-pull-progress` event, emitted for every status update of a pull
+#[derive(Debug, Clone, Serialize)]
+struct ModelPullProgressEvent {
+    model: String,
+    #[serde(flatten)]
+    progress: ollama::PullProgress,
+}
+
 // Secure token storage commands
 #[tauri::command]
 async fn store_token_secure(token: String) -> Result<CommandResponse, String> {
@@ -96,11 +163,7 @@ async fn fetch_drive_items(token: String, item_id: Option<String>) -> Result<Com
             data: Some(data),
             error: None,
         }),
-        Err(e) => Ok(CommandResponse {
-            success: false,
-            data: None,
-            error: Some(e.to_string()),
-        }),
+        Err(e) => Ok(error_response(e)),
     }
 }
 
@@ -112,11 +175,7 @@ async fn fetch_user_profile(token: String) -> Result<CommandResponse, String> {
             data: Some(data),
             error: None,
         }),
-        Err(e) => Ok(CommandResponse {
-            success: false,
-            data: None,
-            error: Some(e.to_string()),
-        }),
+        Err(e) => Ok(error_response(e)),
     }
 }
 
@@ -130,11 +189,7 @@ async fn search_files(token: String, query: String) -> Result<CommandResponse, S
             data: Some(data),
             error: None,
         }),
-        Err(e) => Ok(CommandResponse {
-            success: false,
-            data: None,
-            error: Some(e.to_string()),
-        }),
+        Err(e) => Ok(error_response(e)),
     }
 }
 
@@ -209,11 +264,7 @@ async fn download_file(
                 error: None,
             })
         }
-        Err(e) => Ok(CommandResponse {
-            success: false,
-            data: None,
-            error: Some(e.to_string()),
-        }),
+        Err(e) => Ok(error_response(e)),
     }
 }
 
@@ -221,42 +272,277 @@ async fn download_file(
 
 #[tauri::command]
 async fn chat_with_agent(
+    app: tauri::AppHandle,
     agent_id: String,
     message: String,
     model_override: Option<String>,
+    stream: Option<bool>,
+    history: Option<Vec<session::SessionMessage>>,
     state: State<'_, AppState>,
 ) -> Result<CommandResponse, String> {
     // Use model_override if provided, otherwise use agent's default model
-    let model = if let Some(override_model) = model_override {
-        override_model
-    } else {
+    let (model, provider, params, prompt) = {
         let agent_manager = state.agent_manager.lock().unwrap();
-        match agent_manager.get_agent(&agent_id) {
-            Some(agent) => agent.model.clone(),
-            None => {
-                return Ok(CommandResponse {
-                    success: false,
-                    data: None,
-                    error: Some("Agent not found".to_string()),
-                });
+        match (agent_manager.get_agent(&agent_id), model_override) {
+            (Some(agent), model_override) => (
+                model_override.unwrap_or_else(|| agent.model.clone()),
+                agent.provider.clone(),
+                agent.generation_params(),
+                agent.render_prompt(&message),
+            ),
+            (None, Some(override_model)) => (
+                override_model,
+                provider::ProviderConfig::default(),
+                ollama::GenerationParams::default(),
+                message.clone(),
+            ),
+            (None, None) => {
+                return Ok(error_response(AppError::AgentNotFound { agent_id }));
             }
         }
     };
-    
-    match ollama::chat_completion(&model, &message).await {
-        Ok(response) => Ok(CommandResponse {
+
+    // Fall back to the active session's messages when the caller doesn't pass a history
+    let session_id = state.active_session_id.lock().unwrap().clone();
+    let history = history.or_else(|| {
+        session_id
+            .as_ref()
+            .and_then(|id| state.session_manager.load_session(id).ok())
+            .map(|session| session.messages)
+    });
+
+    let turn = ChatTurn {
+        model,
+        provider,
+        // With a session history, send the whole conversation as a chat instead of a bare prompt
+        chat_messages: history.map(|history| build_chat_messages(&history, &prompt)),
+        message,
+        prompt,
+        params,
+    };
+
+    // In streaming mode, return the request id right away and deliver tokens via events
+    if stream.unwrap_or(false) {
+        let request_id = format!(
+            "req_{}_{}",
+            Utc::now().timestamp_millis(),
+            NEXT_REQUEST.fetch_add(1, Ordering::Relaxed)
+        );
+        let stream_request_id = request_id.clone();
+        let stream_agent_id = agent_id.clone();
+        tauri::async_runtime::spawn(async move {
+            stream_agent_response(
+                app,
+                stream_request_id,
+                turn,
+                session_id.map(|id| (id, stream_agent_id)),
+            ).await;
+        });
+
+        return Ok(CommandResponse {
             success: true,
             data: Some(serde_json::json!({
                 "agent_id": agent_id,
-                "message": response
+                "request_id": request_id
             })),
             error: None,
-        }),
-        Err(e) => Ok(CommandResponse {
-            success: false,
-            data: None,
-            error: Some(format!("Ollama error: {}", e)),
-        }),
+        });
+    }
+
+    let provider = turn.provider.build(&state.settings);
+    let result = match turn.chat_messages {
+        Some(messages) => provider
+            .chat(&turn.model, messages, &[], &turn.params)
+            .await
+            .map(|reply| reply.content),
+        None => provider.generate(&turn.model, &turn.prompt, &turn.params).await,
+    };
+
+    match result {
+        Ok(response) => {
+            if let Some(session_id) = &session_id {
+                record_exchange(&state.session_manager, session_id, &agent_id, &turn.message, &response);
+            }
+            Ok(CommandResponse {
+                success: true,
+                data: Some(serde_json::json!({
+                    "agent_id": agent_id,
+                    "message": response
+                })),
+                error: None,
+            })
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+// A single chat turn, resolved against the agent's settings
+struct ChatTurn {
+    model: String,
+    provider: provider::ProviderConfig,
+    // What the user typed; this is what gets recorded in the session
+    message: String,
+    // The message after applying the agent's prompt template
+    prompt: String,
+    params: ollama::GenerationParams,
+    chat_messages: Option<Vec<ollama::ChatMessage>>,
+}
+
+// Appends a completed user/assistant exchange to the session
+fn record_exchange(
+    session_manager: &session::SessionManager,
+    session_id: &str,
+    agent_id: &str,
+    message: &str,
+    response: &str,
+) {
+    let messages = vec![
+        session::SessionMessage::new("user", message, None),
+        session::SessionMessage::new("assistant", response, Some(agent_id.to_string())),
+    ];
+    if let Err(e) = session_manager.append_messages(session_id, messages) {
+        eprintln!("Failed to record messages in session {}: {}", session_id, e);
+    }
+}
+
+// Converts the user/assistant turns of a session into chat messages and appends the new message
+fn build_chat_messages(history: &[session::SessionMessage], message: &str) -> Vec<ollama::ChatMessage> {
+    let mut messages: Vec<ollama::ChatMessage> = history
+        .iter()
+        .filter(|m| m.role == "user" || m.role == "assistant")
+        .map(|m| ollama::ChatMessage::new(&m.role, &m.content))
+        .collect();
+
+    messages.push(ollama::ChatMessage::new("user", message));
+    messages
+}
+
+async fn stream_agent_response(
+    app: tauri::AppHandle,
+    request_id: String,
+    turn: ChatTurn,
+    session: Option<(String, String)>,
+) {
+    let mut full_response = String::new();
+    let emit_token = |token: &str| {
+        if !token.is_empty() {
+            let _ = app.emit_all("chat-token", ChatTokenEvent {
+                request_id: request_id.clone(),
+                token: token.to_string(),
+            });
+        }
+    };
+
+    let provider = turn.provider.build(&app.state::<AppState>().settings);
+    let messages = turn
+        .chat_messages
+        .unwrap_or_else(|| vec![ollama::ChatMessage::new("user", &turn.prompt)]);
+    let result = provider.stream(&turn.model, messages, &turn.params, &mut |token: &str| {
+        full_response.push_str(token);
+        emit_token(token);
+    }).await;
+
+    let done_event = match result {
+        Ok(stats) => {
+            if let Some((session_id, agent_id)) = &session {
+                let state = app.state::<AppState>();
+                record_exchange(&state.session_manager, session_id, agent_id, &turn.message, &full_response);
+            }
+            ChatDoneEvent {
+                request_id,
+                done: true,
+                error: None,
+                error_details: None,
+                stats: Some(stats),
+            }
+        }
+        Err(e) => ChatDoneEvent {
+            request_id,
+            done: true,
+            error: Some(e.to_string()),
+            error_details: Some(e),
+            stats: None,
+        },
+    };
+    let _ = app.emit_all("chat-done", done_event);
+}
+
+// Runs the agent with the filesystem tools until it gives a final answer or hits the step limit.
+// The user message, every tool step and the final answer are recorded in the active session.
+#[tauri::command]
+async fn run_agent_with_tools(
+    agent_id: String,
+    message: String,
+    max_steps: Option<usize>,
+    state: State<'_, AppState>,
+) -> Result<CommandResponse, String> {
+    let (model, provider, params, prompt) = {
+        let agent_manager = state.agent_manager.lock().unwrap();
+        match agent_manager.get_agent(&agent_id) {
+            Some(agent) => (
+                agent.model.clone(),
+                agent.provider.build(&state.settings),
+                agent.generation_params(),
+                agent.render_prompt(&message),
+            ),
+            None => {
+                return Ok(error_response(AppError::AgentNotFound { agent_id }));
+            }
+        }
+    };
+
+    let session_id = state.active_session_id.lock().unwrap().clone();
+    let history = session_id
+        .as_ref()
+        .and_then(|id| state.session_manager.load_session(id).ok())
+        .map(|session| session.messages)
+        .unwrap_or_default();
+    let messages = build_chat_messages(&history, &prompt);
+
+    let record = |message: session::SessionMessage| {
+        if let Some(session_id) = &session_id {
+            if let Err(e) = state.session_manager.append_messages(session_id, vec![message]) {
+                eprintln!("Failed to record messages in session {}: {}", session_id, e);
+            }
+        }
+    };
+    record(session::SessionMessage::new("user", &message, None));
+
+    let max_steps = max_steps.unwrap_or(tools::DEFAULT_MAX_STEPS);
+    let result = tools::run_agent_loop(
+        &agent_id,
+        provider.as_ref(),
+        &model,
+        messages,
+        &params,
+        &state.permission_policy,
+        max_steps,
+        |step| {
+        let content = format!(
+            "{}({}) -> {}",
+            step.tool,
+            step.arguments,
+            tools::truncate_output(&step.output, 2000),
+        );
+        record(session::SessionMessage::new("tool", &content, Some(agent_id.clone())));
+        },
+    ).await;
+
+    match result {
+        Ok(run) => {
+            record(session::SessionMessage::new("assistant", &run.response, Some(agent_id.clone())));
+            Ok(CommandResponse {
+                success: true,
+                data: Some(serde_json::json!({
+                    "agent_id": agent_id,
+                    "message": run.response,
+                    "steps": run.steps,
+                    "completed": run.completed
+                })),
+                error: None,
+            })
+        }
+        Err(e) => Ok(error_response(e)),
     }
 }
 
@@ -272,10 +558,34 @@ async fn list_agents(state: State<'_, AppState>) -> Result<CommandResponse, Stri
     })
 }
 
-#[tauri::command]async fn save_agents(
+#[tauri::command]
+async fn save_agents(
     agents: Vec<agent::Agent>,
     state: State<'_, AppState>,
 ) -> Result<CommandResponse, String> {
+    for agent in &agents {
+        if let Err(e) = agent.provider.validate() {
+            return Ok(error_response(AppError::invalid(format!("Agent '{}': {}", agent.name, e))));
+        }
+    }
+
+    // Only new agents and agents whose model or provider changed are checked, so agents can
+    // still be removed or renamed while their server is down
+    let changed: Vec<&agent::Agent> = {
+        let agent_manager = state.agent_manager.lock().unwrap();
+        agents
+            .iter()
+            .filter(|agent| {
+                agent_manager.get_agent(&agent.id).map_or(true, |current| {
+                    current.model != agent.model || current.provider != agent.provider
+                })
+            })
+            .collect()
+    };
+    if let Err(e) = check_agent_models(&changed, &state.settings).await {
+        return Ok(error_response(e));
+    }
+
     let mut agent_manager = state.agent_manager.lock().unwrap();
     
     // Clear existing agents and add new ones
@@ -291,14 +601,44 @@ async fn list_agents(state: State<'_, AppState>) -> Result<CommandResponse, Stri
             data: None,
             error: None,
         }),
-        Err(e) => Ok(CommandResponse {
-            success: false,
-            data: None,
-            error: Some(e),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+#[tauri::command]
+async fn get_settings(state: State<'_, AppState>) -> Result<CommandResponse, String> {
+    Ok(CommandResponse {
+        success: true,
+        data: Some(serde_json::to_value(state.settings.settings()).unwrap()),
+        error: None,
+    })
+}
+
+// Takes effect for the next request; requests already running finish with the old settings
+#[tauri::command]
+async fn update_settings(
+    settings: settings::AppSettings,
+    state: State<'_, AppState>,
+) -> Result<CommandResponse, String> {
+    match state.settings.update(settings) {
+        Ok(()) => Ok(CommandResponse {
+            success: true,
+            data: Some(serde_json::to_value(state.settings.settings()).unwrap()),
+            error: None,
         }),
+        Err(e) => Ok(error_response(e)),
     }
 }
 
+#[tauri::command]
+async fn get_startup_warnings(state: State<'_, AppState>) -> Result<CommandResponse, String> {
+    Ok(CommandResponse {
+        success: true,
+        data: Some(serde_json::to_value(&state.startup_warnings).unwrap()),
+        error: None,
+    })
+}
+
 #[tauri::command]
 async fn reload_agents(state: State<'_, AppState>) -> Result<CommandResponse, String> {
     let new_manager = agent::AgentManager::load_from_config(Some(state.agents_config_path.clone()));
@@ -312,79 +652,221 @@ async fn reload_agents(state: State<'_, AppState>) -> Result<CommandResponse, St
     })
 }
 
-#[tauri::command]async fn list_ollama_models() -> Result<CommandResponse, String> {
-    match ollama::list_models().await {
+#[tauri::command]async fn list_ollama_models(state: State<'_, AppState>) -> Result<CommandResponse, String> {
+    match ollama::OllamaProvider::new(None, &state.settings).list_models().await {
         Ok(models) => Ok(CommandResponse {
             success: true,
             data: Some(serde_json::to_value(models).unwrap()),
             error: None,
         }),
-        Err(e) => Ok(CommandResponse {
-            success: false,
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+// Fails with ModelNotFound if an Ollama agent's model isn't installed on its server
+async fn check_agent_models(agents: &[&agent::Agent], settings: &settings::SettingsManager) -> Result<(), AppError> {
+    // One model list per Ollama server
+    let mut models_by_server: HashMap<Option<&str>, Vec<&str>> = HashMap::new();
+    for agent in agents {
+        if let provider::ProviderConfig::Ollama { base_url } = &agent.provider {
+            models_by_server.entry(base_url.as_deref()).or_default().push(&agent.model);
+        }
+    }
+    for (base_url, models) in models_by_server {
+        ollama::OllamaProvider::new(base_url, settings).require_models(&models).await?;
+    }
+    Ok(())
+}
+
+// Shows a model's details, parameters, template, license and capabilities
+#[tauri::command]
+async fn show_ollama_model(model: String, state: State<'_, AppState>) -> Result<CommandResponse, String> {
+    match ollama::OllamaProvider::new(None, &state.settings).show_model(&model).await {
+        Ok(description) => Ok(CommandResponse {
+            success: true,
+            data: Some(serde_json::to_value(description).unwrap()),
+            error: None,
+        }),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+// Downloads a model, emitting `model-pull-progress` events; returns once the pull is done
+#[tauri::command]
+async fn pull_ollama_model(
+    app: tauri::AppHandle,
+    model: String,
+    state: State<'_, AppState>,
+) -> Result<CommandResponse, String> {
+    let ollama = ollama::OllamaProvider::new(None, &state.settings);
+    let result = ollama
+        .pull_model(&model, |progress| {
+            let _ = app.emit_all("model-pull-progress", ModelPullProgressEvent {
+                model: model.clone(),
+                progress: progress.clone(),
+            });
+        })
+        .await;
+
+    match result {
+        Ok(()) => Ok(CommandResponse {
+            success: true,
+            data: Some(serde_json::json!({ "model": model })),
+            error: None,
+        }),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+#[tauri::command]
+async fn delete_ollama_model(model: String, state: State<'_, AppState>) -> Result<CommandResponse, String> {
+    match ollama::OllamaProvider::new(None, &state.settings).delete_model(&model).await {
+        Ok(()) => Ok(CommandResponse {
+            success: true,
             data: None,
-            error: Some(format!("Failed to list models: {}", e)),
+            error: None,
         }),
+        Err(e) => Ok(error_response(e)),
     }
 }
 
+// Health check of the configured Ollama server. Succeeds even when Ollama is down; the
+// returned status says why.
 #[tauri::command]
-async fn check_ollama() -> Result<CommandResponse, String> {
-    let is_running = ollama::check_ollama_status().await;
-    
+async fn check_ollama(state: State<'_, AppState>) -> Result<CommandResponse, String> {
+    let status = ollama::OllamaProvider::new(None, &state.settings).diagnose().await;
+
     Ok(CommandResponse {
         success: true,
-        data: Some(serde_json::json!({ "running": is_running })),
+        data: Some(serde_json::to_value(status).unwrap()),
         error: None,
     })
 }
 
+// Lists the models of any provider, e.g. to fill the model picker while an agent is edited
+#[tauri::command]
+async fn list_provider_models(
+    provider: provider::ProviderConfig,
+    state: State<'_, AppState>,
+) -> Result<CommandResponse, String> {
+    if let Err(e) = provider.validate() {
+        return Ok(error_response(AppError::invalid(e)));
+    }
+
+    let provider = provider.build(&state.settings);
+    match provider.list_models().await {
+        Ok(models) => Ok(CommandResponse {
+            success: true,
+            data: Some(serde_json::to_value(models).unwrap()),
+            error: None,
+        }),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
 // ============ FILE SYSTEM COMMANDS ============
 
+// Like error_response, with the message prefixed by what was being attempted
+fn filesystem_error_response(context: &str, error: AppError) -> CommandResponse {
+    let message = format!("{}: {}", context, error);
+    CommandResponse {
+        error: Some(message),
+        ..error_response(error)
+    }
+}
+
 #[tauri::command]
-async fn read_directory(path: String) -> Result<CommandResponse, String> {
-    match filesystem::read_directory(&path) {
+async fn read_directory(
+    state: State<'_, AppState>,
+    path: String,
+    agent_id: Option<String>,
+) -> Result<CommandResponse, String> {
+    match filesystem::read_directory(&state.permission_policy, agent_id.as_deref(), &path) {
         Ok(files) => Ok(CommandResponse {
             success: true,
             data: Some(serde_json::to_value(files).unwrap()),
             error: None,
         }),
-        Err(e) => Ok(CommandResponse {
-            success: false,
-            data: None,
-            error: Some(format!("Failed to read directory: {}", e)),
-        }),
+        Err(e) => Ok(filesystem_error_response("Failed to read directory", e)),
     }
 }
 
 #[tauri::command]
-async fn read_file_content(path: String) -> Result<CommandResponse, String> {
-    match filesystem::read_file(&path) {
+async fn read_file_content(
+    state: State<'_, AppState>,
+    path: String,
+    agent_id: Option<String>,
+) -> Result<CommandResponse, String> {
+    match filesystem::read_file(&state.permission_policy, agent_id.as_deref(), &path) {
         Ok(content) => Ok(CommandResponse {
             success: true,
             data: Some(serde_json::json!({ "content": content })),
             error: None,
         }),
-        Err(e) => Ok(CommandResponse {
-            success: false,
-            data: None,
-            error: Some(format!("Failed to read file: {}", e)),
-        }),
+        Err(e) => Ok(filesystem_error_response("Failed to read file", e)),
     }
 }
 
 #[tauri::command]
-async fn write_file_content(path: String, content: String) -> Result<CommandResponse, String> {
-    match filesystem::write_file(&path, &content) {
+async fn write_file_content(
+    state: State<'_, AppState>,
+    path: String,
+    content: String,
+    agent_id: Option<String>,
+) -> Result<CommandResponse, String> {
+    match filesystem::write_file(&state.permission_policy, agent_id.as_deref(), &path, &content) {
         Ok(_) => Ok(CommandResponse {
             success: true,
             data: Some(serde_json::json!({ "message": "File written successfully" })),
             error: None,
         }),
-        Err(e) => Ok(CommandResponse {
-            success: false,
+        Err(e) => Ok(filesystem_error_response("Failed to write file", e)),
+    }
+}
+
+// Permission policy commands
+#[tauri::command]
+async fn get_permission_policy(state: State<'_, AppState>) -> Result<CommandResponse, String> {
+    let config = state.permission_policy.get_config();
+    Ok(CommandResponse {
+        success: true,
+        data: Some(serde_json::to_value(config).unwrap()),
+        error: None,
+    })
+}
+
+// Approves a denied call with the `grant_token` from its PathDenied details. `always: false`
+// is "allow once": the grant covers the next matching call only.
+#[tauri::command]
+async fn grant_permission(
+    state: State<'_, AppState>,
+    token: String,
+    always: bool,
+) -> Result<CommandResponse, String> {
+    match state.permission_policy.grant(&token, always) {
+        Ok(grant) => Ok(CommandResponse {
+            success: true,
+            data: Some(serde_json::to_value(grant).unwrap()),
+            error: None,
+        }),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+#[tauri::command]
+async fn revoke_permission(
+    state: State<'_, AppState>,
+    path: String,
+    access: permissions::Access,
+    agent_id: Option<String>,
+) -> Result<CommandResponse, String> {
+    match state.permission_policy.revoke(agent_id.as_deref(), &path, access) {
+        Ok(_) => Ok(CommandResponse {
+            success: true,
             data: None,
-            error: Some(format!("Failed to write file: {}", e)),
+            error: None,
         }),
+        Err(e) => Ok(error_response(e)),
     }
 }
 
@@ -396,6 +878,12 @@ async fn create_task(
     agent_id: String,
     prompt_template: String,
     schedule_type: scheduler::ScheduleType,
+    timezone: Option<String>,
+    misfire_policy: Option<scheduler::MisfirePolicy>,
+    timeout_seconds: Option<u64>,
+    retry_policy: Option<scheduler::RetryPolicy>,
+    workflow: Option<workflow::Workflow>,
+    sinks: Option<Vec<sinks::OutputSink>>,
 ) -> Result<CommandResponse, String> {
     let task = scheduler::Task {
         id: format!("task_{}", Utc::now().timestamp_millis()),
@@ -408,6 +896,13 @@ async fn create_task(
         last_run: None,
         next_run: None,
         run_count: 0,
+        timezone: timezone.unwrap_or_else(scheduler::system_timezone),
+        misfire_policy: misfire_policy.unwrap_or_default(),
+        timeout_seconds: timeout_seconds.unwrap_or_else(scheduler::default_timeout_seconds),
+        retry_policy: retry_policy.unwrap_or_default(),
+        workflow,
+        paused_until: None,
+        sinks: sinks.unwrap_or_default(),
     };
 
     match state.task_scheduler.add_task(task).await {
@@ -416,11 +911,7 @@ async fn create_task(
             data: Some(serde_json::to_value(task).unwrap()),
             error: None,
         }),
-        Err(e) => Ok(CommandResponse {
-            success: false,
-            data: None,
-            error: Some(e),
-        }),
+        Err(e) => Ok(error_response(e)),
     }
 }
 
@@ -442,11 +933,7 @@ async fn delete_task(state: State<'_, AppState>, task_id: String) -> Result<Comm
             data: None,
             error: None,
         }),
-        Err(e) => Ok(CommandResponse {
-            success: false,
-            data: None,
-            error: Some(e),
-        }),
+        Err(e) => Ok(error_response(e)),
     }
 }
 
@@ -462,27 +949,270 @@ async fn toggle_task(
             data: Some(serde_json::to_value(task).unwrap()),
             error: None,
         }),
-        Err(e) => Ok(CommandResponse {
-            success: false,
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+#[tauri::command]
+async fn update_task(
+    state: State<'_, AppState>,
+    task_id: String,
+    update: scheduler::TaskUpdate,
+) -> Result<CommandResponse, String> {
+    match state.task_scheduler.update_task(&task_id, update).await {
+        Ok(task) => Ok(CommandResponse {
+            success: true,
+            data: Some(serde_json::to_value(task).unwrap()),
+            error: None,
+        }),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+#[tauri::command]
+async fn run_task_now(
+    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
+    task_id: String,
+) -> Result<CommandResponse, String> {
+    match state.task_scheduler.run_task_now(&task_id, scheduled_executor(app_handle)).await {
+        Ok(run_id) => Ok(CommandResponse {
+            success: true,
+            data: Some(serde_json::json!({ "run_id": run_id })),
+            error: None,
+        }),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+#[tauri::command]
+async fn pause_task_until(
+    state: State<'_, AppState>,
+    task_id: String,
+    until: Option<DateTime<Utc>>,
+) -> Result<CommandResponse, String> {
+    match state.task_scheduler.pause_task_until(&task_id, until).await {
+        Ok(task) => Ok(CommandResponse {
+            success: true,
+            data: Some(serde_json::to_value(task).unwrap()),
+            error: None,
+        }),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+#[tauri::command]
+async fn cancel_task_run(
+    state: State<'_, AppState>,
+    task_id: String,
+) -> Result<CommandResponse, String> {
+    match state.task_scheduler.cancel_run(&task_id).await {
+        Ok(()) => Ok(CommandResponse {
+            success: true,
             data: None,
-            error: Some(e),
+            error: None,
         }),
+        Err(e) => Ok(error_response(e)),
     }
 }
 
+#[tauri::command]
+async fn list_running_tasks(state: State<'_, AppState>) -> Result<CommandResponse, String> {
+    let task_ids = state.task_scheduler.running_task_ids();
+    Ok(CommandResponse {
+        success: true,
+        data: Some(serde_json::to_value(task_ids).unwrap()),
+        error: None,
+    })
+}
+
 #[tauri::command]
 async fn get_task_results(
     state: State<'_, AppState>,
+    task_id: Option<String>,
+    run_id: Option<String>,
+    success: Option<bool>,
+    skipped: Option<bool>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    offset: Option<usize>,
     limit: Option<usize>,
 ) -> Result<CommandResponse, String> {
-    let results = state.task_scheduler.get_results(limit).await;
+    let query = history::HistoryQuery {
+        task_id,
+        run_id,
+        success,
+        skipped,
+        from,
+        to,
+        offset,
+        limit,
+    };
+    let page = state.task_scheduler.get_results(&query).await;
+    Ok(CommandResponse {
+        success: true,
+        data: Some(serde_json::to_value(page).unwrap()),
+        error: None,
+    })
+}
+
+#[tauri::command]
+async fn get_history_retention(state: State<'_, AppState>) -> Result<CommandResponse, String> {
+    let retention = state.task_scheduler.get_retention();
     Ok(CommandResponse {
         success: true,
-        data: Some(serde_json::to_value(results).unwrap()),
+        data: Some(serde_json::to_value(retention).unwrap()),
         error: None,
     })
 }
 
+#[tauri::command]
+async fn update_history_retention(
+    state: State<'_, AppState>,
+    retention: history::RetentionSettings,
+) -> Result<CommandResponse, String> {
+    match state.task_scheduler.update_retention(retention.clone()) {
+        Ok(()) => Ok(CommandResponse {
+            success: true,
+            data: Some(serde_json::to_value(retention).unwrap()),
+            error: None,
+        }),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+// Session commands
+#[tauri::command]
+async fn list_sessions(state: State<'_, AppState>) -> Result<CommandResponse, String> {
+    match state.session_manager.list_sessions() {
+        Ok(sessions) => Ok(CommandResponse {
+            success: true,
+            data: Some(serde_json::to_value(sessions).unwrap()),
+            error: None,
+        }),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+#[tauri::command]
+async fn save_session(
+    state: State<'_, AppState>,
+    session: session::Session,
+) -> Result<CommandResponse, String> {
+    let session_id = session.id.clone();
+    match state.session_manager.save_session(session) {
+        Ok(_) => {
+            *state.active_session_id.lock().unwrap() = Some(session_id.clone());
+            Ok(CommandResponse {
+                success: true,
+                data: Some(serde_json::json!({ "session_id": session_id })),
+                error: None,
+            })
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+#[tauri::command]
+async fn load_session(
+    state: State<'_, AppState>,
+    session_id: String,
+) -> Result<CommandResponse, String> {
+    match state.session_manager.load_session(&session_id) {
+        Ok(session) => {
+            *state.active_session_id.lock().unwrap() = Some(session.id.clone());
+            Ok(CommandResponse {
+                success: true,
+                data: Some(serde_json::to_value(session).unwrap()),
+                error: None,
+            })
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+#[tauri::command]
+async fn delete_session(
+    state: State<'_, AppState>,
+    session_id: String,
+) -> Result<CommandResponse, String> {
+    match state.session_manager.delete_session(&session_id) {
+        Ok(_) => {
+            let mut active_session_id = state.active_session_id.lock().unwrap();
+            if active_session_id.as_deref() == Some(session_id.as_str()) {
+                *active_session_id = None;
+            }
+            Ok(CommandResponse {
+                success: true,
+                data: None,
+                error: None,
+            })
+        }
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+#[tauri::command]
+async fn export_session(
+    state: State<'_, AppState>,
+    session_id: String,
+    export_path: String,
+) -> Result<CommandResponse, String> {
+    match state.session_manager.export_session(&state.permission_policy, &session_id, &export_path) {
+        Ok(_) => Ok(CommandResponse {
+            success: true,
+            data: None,
+            error: None,
+        }),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+#[tauri::command]
+async fn import_session(
+    state: State<'_, AppState>,
+    import_path: String,
+) -> Result<CommandResponse, String> {
+    match state.session_manager.import_session(&state.permission_policy, &import_path) {
+        Ok(session) => Ok(CommandResponse {
+            success: true,
+            data: Some(serde_json::to_value(session).unwrap()),
+            error: None,
+        }),
+        Err(e) => Ok(error_response(e)),
+    }
+}
+
+type ExecutorFuture = std::pin::Pin<Box<dyn std::future::Future<Output = Result<String, AppError>> + Send>>;
+
+// Executor handed to the scheduler, for both scheduled and manual runs
+fn scheduled_executor(
+    app_handle: tauri::AppHandle,
+) -> impl Fn(String, String, String) -> ExecutorFuture + Clone + Send + Sync + 'static {
+    move |agent_id, prompt, _| {
+        let app_handle = app_handle.clone();
+        Box::pin(async move { run_scheduled_prompt(&app_handle, &agent_id, &prompt).await })
+    }
+}
+
+// Executor for scheduled tasks: resolves the agent's model and runs the prompt through its provider
+async fn run_scheduled_prompt(
+    app_handle: &tauri::AppHandle,
+    agent_id: &str,
+    prompt: &str,
+) -> Result<String, AppError> {
+    let (model, provider, params) = {
+        let state = app_handle.state::<AppState>();
+        let agent_manager = state.agent_manager.lock().unwrap();
+        match agent_manager.get_agent(agent_id) {
+            Some(agent) => (agent.model.clone(), agent.provider.build(&state.settings), agent.generation_params()),
+            None => return Err(AppError::AgentNotFound { agent_id: agent_id.to_string() }),
+        }
+    };
+
+    provider.generate(&model, prompt, &params).await
+}
+
 fn main() {
     let app_data_dir = tauri::api::path::app_data_dir(&tauri::Config::default())
         .unwrap_or_else(|| std::env::current_dir().unwrap());
@@ -492,6 +1222,11 @@ fn main() {
     // Setup paths for config files
     let tasks_file = app_data_dir.join("tasks.json");
     let agents_config = app_data_dir.join("agents.json");
+    let sessions_dir = app_data_dir.join("sessions");
+    let permissions_file = app_data_dir.join("permissions.json");
+    let history_file = app_data_dir.join("task_history.jsonl");
+    let retention_file = app_data_dir.join("history_retention.json");
+    let settings_file = app_data_dir.join("settings.json");
     
     // Try to copy default agents.json if it doesn't exist in app data dir
     if !agents_config.exists() {
@@ -507,7 +1242,31 @@ fn main() {
         }
     }
     
-    let task_scheduler = Arc::new(scheduler::TaskScheduler::new(tasks_file));
+    let context = tauri::generate_context!();
+    let identifier = context.config().tauri.bundle.identifier.clone();
+
+    let run_history = history::RunHistory::new(history_file, retention_file);
+    let permission_policy = Arc::new(permissions::PermissionPolicy::new(permissions_file));
+    let session_manager = Arc::new(session::SessionManager::new(sessions_dir));
+    let settings = Arc::new(settings::SettingsManager::load(settings_file));
+    let sink_handles = sinks::SinkHandles {
+        permissions: permission_policy.clone(),
+        sessions: session_manager.clone(),
+        settings: settings.clone(),
+        notifier: Arc::new(move |title: &str, body: &str| {
+            tauri::api::notification::Notification::new(&identifier)
+                .title(title)
+                .body(body)
+                .show()
+                .map_err(|e| format!("Failed to show notification: {}", e))
+        }),
+    };
+    let task_scheduler = Arc::new(scheduler::TaskScheduler::new(
+        tasks_file,
+        run_history,
+        permission_policy.clone(),
+        sink_handles,
+    ));
     
     // Load agents from config file (falls back to defaults if not found)
     let agent_manager = if agents_config.exists() {
@@ -516,24 +1275,45 @@ fn main() {
         println!("No agents.json found, using default agents");
         agent::AgentManager::new()
     };
+
+    let mut startup_warnings: Vec<persist::StorageWarning> = task_scheduler
+        .load_warning()
+        .into_iter()
+        .chain(agent_manager.load_warning())
+        .chain(settings.load_warning())
+        .collect();
+    startup_warnings.extend(session_manager.check_sessions());
     
     tauri::Builder::default()
         .manage(AppState {
             agent_manager: Mutex::new(agent_manager),
             task_scheduler: task_scheduler.clone(),
             agents_config_path: agents_config,
+            session_manager,
+            active_session_id: Mutex::new(None),
+            permission_policy,
+            startup_warnings: startup_warnings.clone(),
+            settings,
         })
-        .setup(move |_app| {
+        .setup(move |app| {
+            for warning in &startup_warnings {
+                let _ = app.emit_all("startup-warning", warning);
+            }
+
+            // Tell the UI whenever Ollama goes up or down or its loaded models change
+            let status_handle = app.handle();
+            let status_settings = status_handle.state::<AppState>().settings.clone();
+            tauri::async_runtime::spawn(ollama::monitor_status(status_settings, move |status| {
+                let _ = status_handle.emit_all("ollama-status", status);
+            }));
+
             // Start background task checker
             let scheduler_clone = task_scheduler.clone();
+            let app_handle = app.handle();
             tauri::async_runtime::spawn(async move {
-                let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
                 loop {
-                    interval.tick().await;
-                    scheduler_clone.check_and_run_tasks(|agent_id, prompt, _| {
-                        // This is a simplified executor - in production, this would need proper async handling
-                        Ok(format!("Task executed for agent {} with prompt: {}", agent_id, prompt))
-                    }).await;
+                    scheduler_clone.check_and_run_tasks(scheduled_executor(app_handle.clone())).await;
+                    scheduler_clone.wait_for_next_check().await;
                 }
             });
             Ok(())
@@ -551,22 +1331,48 @@ fn main() {
             download_file,
             // AI Agent commands
             chat_with_agent,
+            run_agent_with_tools,
             list_agents,
             save_agents,
             reload_agents,
+            get_startup_warnings,
+            get_settings,
+            update_settings,
             list_ollama_models,
+            show_ollama_model,
+            pull_ollama_model,
+            delete_ollama_model,
             check_ollama,
+            list_provider_models,
             // File System commands
             read_directory,
             read_file_content,
             write_file_content,
+            // Permission policy commands
+            get_permission_policy,
+            grant_permission,
+            revoke_permission,
             // Task Scheduler commands
             create_task,
             list_tasks,
             delete_task,
             toggle_task,
+            update_task,
+            run_task_now,
+            pause_task_until,
+            cancel_task_run,
+            list_running_tasks,
             get_task_results,
+            get_history_retention,
+            update_history_retention,
+            // Session commands
+            list_sessions,
+            save_session,
+            load_session,
+            delete_session,
+            export_session,
+            import_session,
         ])
-        .run(tauri::generate_context!())
+        .run(context)
         .expect("error while running tauri application");
 }
@@ -1,20 +1,182 @@
-use reqwest;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use anyhow::Result;
+use crate::error::AppError;
+use crate::provider::{self, LlmProvider};
+use crate::settings::SettingsManager;
+
+pub const DEFAULT_BASE_URL: &str = "http://localhost:11434";
+
+pub const PROVIDER_NAME: &str = "Ollama";
+
+// Status checks give up sooner than generation requests, so a hung server is reported quickly
+const STATUS_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How often the background monitor checks the server
+const STATUS_INTERVAL: Duration = Duration::from_secs(15);
+
+// Ollama's default context window when the model options don't override it
+const DEFAULT_NUM_CTX: usize = 2048;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct OllamaRequest {
     pub model: String,
     pub prompt: String,
     pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    #[serde(skip_serializing_if = "ModelOptions::is_empty")]
+    pub options: ModelOptions,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+// Sampling options forwarded in the request's `options` object; unset fields use the model defaults
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ModelOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+}
+
+impl ModelOptions {
+    pub fn is_empty(&self) -> bool {
+        self.temperature.is_none()
+            && self.top_p.is_none()
+            && self.num_ctx.is_none()
+            && self.stop.is_empty()
+            && self.seed.is_none()
+    }
+}
+
+// System prompt and options for a request, usually taken from the agent's settings
+#[derive(Debug, Clone, Default)]
+pub struct GenerationParams {
+    pub system: Option<String>,
+    pub options: ModelOptions,
+}
+
+impl GenerationParams {
+    // Context window these params will run with
+    pub fn num_ctx(&self) -> usize {
+        self.options.num_ctx.map(|n| n as usize).unwrap_or(DEFAULT_NUM_CTX)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OllamaResponse {
     #[serde(default)]
     pub model: Option<String>,
     pub response: String,
     pub done: bool,
+    #[serde(flatten)]
+    pub stats: GenerationStats,
+}
+
+// Timing stats Ollama reports on the final frame (durations are in nanoseconds)
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct GenerationStats {
+    #[serde(default)]
+    pub total_duration: Option<u64>,
+    #[serde(default)]
+    pub load_duration: Option<u64>,
+    #[serde(default)]
+    pub prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    pub prompt_eval_duration: Option<u64>,
+    #[serde(default)]
+    pub eval_count: Option<u64>,
+    #[serde(default)]
+    pub eval_duration: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatMessage {
+    pub role: String,
+    #[serde(default)]
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    // Set on `tool` messages to say which tool produced the content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>,
+    // Set on `tool` messages to the id of the call they answer, if the call had one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl ChatMessage {
+    pub fn new(role: &str, content: &str) -> Self {
+        ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+            tool_calls: Vec::new(),
+            tool_name: None,
+            tool_call_id: None,
+        }
+    }
+}
+
+// A function the model may call, advertised in the chat request's `tools` list
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ToolDefinition {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub function: ToolFunction,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ToolFunction {
+    pub name: String,
+    pub description: String,
+    // JSON schema of the arguments object
+    pub parameters: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ToolCall {
+    // Assigned by OpenAI-compatible servers; Ollama doesn't send one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub function: ToolCallFunction,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ToolCallFunction {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    pub stream: bool,
+    #[serde(skip_serializing_if = "ModelOptions::is_empty")]
+    pub options: ModelOptions,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ToolDefinition>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatResponse {
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub message: Option<ChatMessage>,
+    pub done: bool,
+    #[serde(flatten)]
+    pub stats: GenerationStats,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -24,6 +186,25 @@ pub struct ModelInfo {
     pub size: u64,
     #[serde(default)]
     pub modified_at: String,
+    // Only Ollama reports these
+    #[serde(default)]
+    pub digest: Option<String>,
+    #[serde(default)]
+    pub details: ModelDetails,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ModelDetails {
+    #[serde(default)]
+    pub format: Option<String>,
+    #[serde(default)]
+    pub family: Option<String>,
+    // e.g. "8.0B"
+    #[serde(default)]
+    pub parameter_size: Option<String>,
+    // e.g. "Q4_K_M"
+    #[serde(default)]
+    pub quantization_level: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -31,42 +212,597 @@ pub struct ModelsResponse {
     pub models: Vec<ModelInfo>,
 }
 
-pub async fn chat_completion(model: &str, prompt: &str) -> Result<String> {
-    let client = reqwest::Client::new();
-    let request = OllamaRequest {
-        model: model.to_string(),
-        prompt: prompt.to_string(),
-        stream: false,
+// What /api/show reports about an installed model
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ModelDescription {
+    #[serde(default)]
+    pub details: ModelDetails,
+    #[serde(default)]
+    pub parameters: String,
+    #[serde(default)]
+    pub template: String,
+    #[serde(default)]
+    pub license: String,
+    // Architecture facts such as context length and embedding size, keyed like "llama.context_length"
+    #[serde(default)]
+    pub model_info: serde_json::Map<String, serde_json::Value>,
+    // e.g. "completion", "tools", "vision"
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct VersionResponse {
+    version: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct LoadedModelsResponse {
+    #[serde(default)]
+    models: Vec<LoadedModel>,
+}
+
+// A model Ollama currently holds in memory (/api/ps)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LoadedModel {
+    pub name: String,
+    #[serde(default)]
+    pub size: u64,
+    // Bytes of the model held in GPU memory; less than `size` means part of it runs on the CPU
+    #[serde(default)]
+    pub size_vram: u64,
+    // When Ollama will unload the model if it isn't used again
+    #[serde(default)]
+    pub expires_at: Option<String>,
+    #[serde(default)]
+    pub details: ModelDetails,
+}
+
+// Result of a health check against the Ollama server
+#[derive(Serialize, Debug, Clone)]
+pub struct OllamaStatus {
+    // True when the server answered /api/version
+    pub running: bool,
+    pub endpoint: String,
+    pub version: Option<String>,
+    // Round trip of the version request
+    pub latency_ms: Option<u64>,
+    pub loaded_models: Vec<LoadedModel>,
+    // Why the server isn't running, or why its loaded models couldn't be read
+    pub error: Option<AppError>,
+}
+
+impl OllamaStatus {
+    // Whether the UI needs to hear about this status; latency alone doesn't count
+    fn differs_from(&self, other: &OllamaStatus) -> bool {
+        let loaded_names = |status: &OllamaStatus| -> Vec<String> {
+            status.loaded_models.iter().map(|model| model.name.clone()).collect()
+        };
+        self.running != other.running
+            || self.endpoint != other.endpoint
+            || self.version != other.version
+            || loaded_names(self) != loaded_names(other)
+            || self.error.as_ref().map(|e| e.to_string()) != other.error.as_ref().map(|e| e.to_string())
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct ModelRequest<'a> {
+    model: &'a str,
+}
+
+#[derive(Serialize, Debug)]
+struct PullRequest<'a> {
+    model: &'a str,
+    stream: bool,
+}
+
+// One frame of a streamed pull. `total` and `completed` are byte counts for the layer
+// named by `digest`, and are absent for steps like "verifying sha256 digest".
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PullProgress {
+    #[serde(default)]
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed: Option<u64>,
+    // Set when the pull failed part-way
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// Ollama's native API (/api/generate, /api/chat, /api/tags) and its model management
+#[derive(Clone)]
+pub struct OllamaProvider {
+    base_url: String,
+    auth_header: Option<(HeaderName, HeaderValue)>,
+    read_timeout: Duration,
+    client: reqwest::Client,
+}
+
+impl OllamaProvider {
+    // `base_url` overrides the Ollama URL from the app settings
+    pub fn new(base_url: Option<&str>, settings: &SettingsManager) -> Self {
+        let current = settings.settings();
+        let configured = current.ollama_url.trim_end_matches('/');
+        let base_url = base_url.unwrap_or(configured).trim_end_matches('/');
+        // The header is a credential for the configured server; an agent pointing somewhere
+        // else must not receive it
+        let auth_header = if base_url == configured {
+            current.auth_header.as_ref().and_then(|header| header.parse().ok())
+        } else {
+            None
+        };
+        OllamaProvider {
+            base_url: base_url.to_string(),
+            auth_header,
+            read_timeout: current.read_timeout(),
+            client: settings.client(),
+        }
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let builder = self.client.request(method, format!("{}{}", self.base_url, path));
+        match &self.auth_header {
+            Some((name, value)) => builder.header(name.clone(), value.clone()),
+            None => builder,
+        }
+    }
+
+    fn request_error(&self, error: reqwest::Error) -> AppError {
+        AppError::from_request(PROVIDER_NAME, &self.base_url, self.read_timeout, error)
+    }
+
+    // Sends a request and checks its status. `model` is the model the request is for,
+    // so a 404 can be reported as a missing model.
+    async fn send(&self, request: reqwest::RequestBuilder, model: Option<&str>) -> Result<reqwest::Response, AppError> {
+        let response = request
+            .timeout(self.read_timeout)
+            .send()
+            .await
+            .map_err(|e| self.request_error(e))?;
+        check_status(response, model).await
+    }
+
+    // Like send, and parses the JSON answer
+    async fn send_json<T: DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+        model: Option<&str>,
+    ) -> Result<T, AppError> {
+        let response = self.send(request, model).await?;
+        let text = response.text().await.map_err(|e| self.request_error(e))?;
+        serde_json::from_str(&text)
+            .map_err(|e| ollama_error(format!("Failed to parse Ollama response: {}. Response: {}", e, text)))
+    }
+
+    // Checks that the server answers with its version, then reads which models are loaded.
+    // Any error status counts as a failure, not just a refused connection.
+    pub async fn diagnose(&self) -> OllamaStatus {
+        let probe = OllamaProvider {
+            read_timeout: self.read_timeout.min(STATUS_TIMEOUT),
+            ..self.clone()
+        };
+        let mut status = OllamaStatus {
+            running: false,
+            endpoint: self.base_url.clone(),
+            version: None,
+            latency_ms: None,
+            loaded_models: Vec::new(),
+            error: None,
+        };
+
+        let started = Instant::now();
+        let version = probe
+            .send_json::<VersionResponse>(probe.request(reqwest::Method::GET, "/api/version"), None)
+            .await;
+        match version {
+            Ok(version) => {
+                status.running = true;
+                status.version = Some(version.version);
+                status.latency_ms = Some(started.elapsed().as_millis() as u64);
+            }
+            Err(error) => {
+                status.error = Some(error);
+                return status;
+            }
+        }
+
+        match probe
+            .send_json::<LoadedModelsResponse>(probe.request(reqwest::Method::GET, "/api/ps"), None)
+            .await
+        {
+            Ok(loaded) => status.loaded_models = loaded.models,
+            Err(error) => status.error = Some(error),
+        }
+        status
+    }
+
+    pub async fn show_model(&self, model: &str) -> Result<ModelDescription, AppError> {
+        let request = self.request(reqwest::Method::POST, "/api/show").json(&ModelRequest { model });
+        self.send_json(request, Some(model)).await
+    }
+
+    pub async fn delete_model(&self, model: &str) -> Result<(), AppError> {
+        let request = self.request(reqwest::Method::DELETE, "/api/delete").json(&ModelRequest { model });
+        self.send(request, Some(model)).await.map(|_| ())
+    }
+
+    // Downloads a model from the Ollama library, calling `on_progress` for every status
+    // update. Pulls can take a long time, so only the gaps between updates are timed.
+    pub async fn pull_model(&self, model: &str, mut on_progress: impl FnMut(&PullProgress)) -> Result<(), AppError> {
+        let send = self
+            .request(reqwest::Method::POST, "/api/pull")
+            .json(&PullRequest { model, stream: true })
+            .send();
+        let response = provider::read_within(self.read_timeout, send)
+            .await?
+            .map_err(|e| self.request_error(e))?;
+        let response = check_status(response, None).await?;
+
+        let last: PullProgress = read_ndjson_stream(self, response, |frame: &PullProgress| {
+            on_progress(frame);
+            frame.error.is_some() || frame.status == "success"
+        }).await?;
+        match last.error {
+            Some(error) => Err(ollama_error(format!("Failed to pull '{}': {}", model, error))),
+            None => Ok(()),
+        }
+    }
+
+    // Fails with ModelNotFound for the first of `models` that isn't installed
+    pub async fn require_models(&self, models: &[&str]) -> Result<(), AppError> {
+        let installed = self.list_models().await?;
+        match models.iter().find(|model| find_model(&installed, model).is_none()) {
+            Some(missing) => Err(AppError::ModelNotFound {
+                provider: PROVIDER_NAME.to_string(),
+                model: missing.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+// Checks the Ollama server from the app settings every STATUS_INTERVAL and calls `on_change`
+// with the first status and whenever it changes. Runs until the app exits.
+pub async fn monitor_status(settings: Arc<SettingsManager>, mut on_change: impl FnMut(&OllamaStatus)) {
+    let mut last: Option<OllamaStatus> = None;
+    loop {
+        // Built each time so changes to the settings are picked up
+        let status = OllamaProvider::new(None, &settings).diagnose().await;
+        if last.as_ref().map_or(true, |last| status.differs_from(last)) {
+            on_change(&status);
+        }
+        last = Some(status);
+        tokio::time::sleep(STATUS_INTERVAL).await;
+    }
+}
+
+// Looks up a model by name. A name without a tag means ":latest", as in the Ollama CLI.
+pub fn find_model<'a>(models: &'a [ModelInfo], name: &str) -> Option<&'a ModelInfo> {
+    let with_tag = |name: &str| {
+        if name.contains(':') {
+            name.to_string()
+        } else {
+            format!("{}:latest", name)
+        }
     };
+    let wanted = with_tag(name);
+    models.iter().find(|model| with_tag(&model.name) == wanted)
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    async fn generate(&self, model: &str, prompt: &str, params: &GenerationParams) -> Result<String, AppError> {
+        let request = OllamaRequest {
+            model: model.to_string(),
+            prompt: prompt.to_string(),
+            stream: false,
+            system: params.system.clone(),
+            options: params.options.clone(),
+        };
+
+        let request = self.request(reqwest::Method::POST, "/api/generate").json(&request);
+        let ollama_response: OllamaResponse = self.send_json(request, Some(model)).await?;
+        Ok(ollama_response.response)
+    }
 
-    let response = client
-        .post("http://localhost:11434/api/generate")
-        .json(&request)
-        .send()
-        .await?;
+    async fn chat(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDefinition],
+        params: &GenerationParams,
+    ) -> Result<ChatMessage, AppError> {
+        let request = ChatRequest {
+            model: model.to_string(),
+            messages: provider::prepare_chat_messages(messages, params),
+            stream: false,
+            options: params.options.clone(),
+            tools: tools.to_vec(),
+        };
 
-    let text = response.text().await?;
-    let ollama_response: OllamaResponse = serde_json::from_str(&text)
-        .map_err(|e| anyhow::anyhow!("Failed to parse Ollama response: {}. Response: {}", e, text))?;
-    
-    Ok(ollama_response.response)
+        let request = self.request(reqwest::Method::POST, "/api/chat").json(&request);
+        let chat_response: ChatResponse = self.send_json(request, Some(model)).await?;
+        Ok(chat_response.message.unwrap_or_else(|| ChatMessage::new("assistant", "")))
+    }
+
+    async fn stream(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        params: &GenerationParams,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<GenerationStats, AppError> {
+        let request = ChatRequest {
+            model: model.to_string(),
+            messages: provider::prepare_chat_messages(messages, params),
+            stream: true,
+            options: params.options.clone(),
+            tools: Vec::new(),
+        };
+
+        let send = self.request(reqwest::Method::POST, "/api/chat").json(&request).send();
+        let response = provider::read_within(self.read_timeout, send)
+            .await?
+            .map_err(|e| self.request_error(e))?;
+        let response = check_status(response, Some(model)).await?;
+
+        let last: ChatResponse = read_ndjson_stream(self, response, |frame: &ChatResponse| {
+            if let Some(message) = &frame.message {
+                on_token(&message.content);
+            }
+            frame.done
+        }).await?;
+        Ok(last.stats)
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, AppError> {
+        let request = self.request(reqwest::Method::GET, "/api/tags");
+        let models_response: ModelsResponse = self.send_json(request, None).await?;
+        Ok(models_response.models)
+    }
+}
+
+fn ollama_error(message: String) -> AppError {
+    AppError::ProviderError {
+        provider: PROVIDER_NAME.to_string(),
+        message,
+    }
+}
+
+// Turns an error status into an AppError. Ollama explains errors as `{"error": "..."}` and
+// answers 404 for models that aren't installed.
+async fn check_status(response: reqwest::Response, model: Option<&str>) -> Result<reqwest::Response, AppError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    if let (StatusCode::NOT_FOUND, Some(model)) = (status, model) {
+        return Err(AppError::ModelNotFound {
+            provider: PROVIDER_NAME.to_string(),
+            model: model.to_string(),
+        });
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|value| value.get("error").and_then(|e| e.as_str()).map(str::to_string))
+        .unwrap_or(body);
+    Err(ollama_error(format!("{}: {}", status, message)))
+}
+
+// Reads an NDJSON body frame by frame until `on_frame` reports the final frame
+async fn read_ndjson_stream<T, F>(
+    ollama: &OllamaProvider,
+    mut response: reqwest::Response,
+    mut on_frame: F,
+) -> Result<T, AppError>
+where
+    T: DeserializeOwned,
+    F: FnMut(&T) -> bool,
+{
+    // Frames can be split across network chunks, so buffer until a full line arrives
+    let mut buffer: Vec<u8> = Vec::new();
+    while let Some(chunk) = provider::read_within(ollama.read_timeout, response.chunk())
+        .await?
+        .map_err(|e| ollama.request_error(e))?
+    {
+        buffer.extend_from_slice(&chunk);
+        while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=pos).collect();
+            if let Some(frame) = parse_stream_line::<T>(&line)? {
+                if on_frame(&frame) {
+                    return Ok(frame);
+                }
+            }
+        }
+    }
+
+    if let Some(frame) = parse_stream_line::<T>(&buffer)? {
+        if on_frame(&frame) {
+            return Ok(frame);
+        }
+    }
+
+    Err(ollama_error("Ollama stream ended before the response was done".to_string()))
 }
 
-pub async fn list_models() -> Result<Vec<ModelInfo>> {
-    let client = reqwest::Client::new();
-    let response = client
-        .get("http://localhost:11434/api/tags")
-        .send()
-        .await?;
+fn parse_stream_line<T: DeserializeOwned>(line: &[u8]) -> Result<Option<T>, AppError> {
+    let text = String::from_utf8_lossy(line);
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
 
-    let models_response: ModelsResponse = response.json().await?;
-    Ok(models_response.models)
+    let frame: T = serde_json::from_str(text)
+        .map_err(|e| ollama_error(format!("Failed to parse Ollama stream chunk: {}. Chunk: {}", e, text)))?;
+    Ok(Some(frame))
 }
 
-pub async fn check_ollama_status() -> bool {
-    let client = reqwest::Client::new();
-    match client.get("http://localhost:11434/api/tags").send().await {
-        Ok(_) => true,
-        Err(_) => false,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::{AppSettings, AuthHeader};
+    use crate::testing::{self, MockServer, Reply};
+    use serde_json::json;
+
+    const NDJSON: &str = "application/x-ndjson";
+
+    fn params() -> GenerationParams {
+        GenerationParams {
+            system: Some("Be brief.".to_string()),
+            options: ModelOptions {
+                temperature: Some(0.5),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn chat_sends_history_and_reads_tool_calls() {
+        let server = MockServer::start(vec![(
+            "/api/chat",
+            Reply::json(200, json!({
+                "model": "llama3.2",
+                "message": {
+                    "role": "assistant",
+                    "content": "",
+                    "tool_calls": [{ "function": { "name": "read_file", "arguments": { "path": "/tmp/notes.md" } } }]
+                },
+                "done": true
+            })),
+        )]).await;
+        let (_dir, settings) = testing::settings(AppSettings::default());
+        let ollama = OllamaProvider::new(Some(&server.url), &settings);
+
+        let reply = ollama
+            .chat("llama3.2", vec![ChatMessage::new("user", "Read my notes")], &[], &params())
+            .await
+            .unwrap();
+        assert_eq!(reply.tool_calls.len(), 1);
+        assert_eq!(reply.tool_calls[0].id, None);
+        assert_eq!(reply.tool_calls[0].function.name, "read_file");
+        assert_eq!(reply.tool_calls[0].function.arguments, json!({ "path": "/tmp/notes.md" }));
+
+        let request = server.requests().remove(0).json();
+        assert_eq!(request["model"], "llama3.2");
+        assert_eq!(request["stream"], false);
+        assert_eq!(request["options"]["temperature"], 0.5);
+        assert_eq!(request["messages"][0], json!({ "role": "system", "content": "Be brief." }));
+        assert_eq!(request["messages"][1], json!({ "role": "user", "content": "Read my notes" }));
+    }
+
+    #[tokio::test]
+    async fn stream_reads_frames_split_across_chunks() {
+        let server = MockServer::start(vec![(
+            "/api/chat",
+            Reply::stream(NDJSON, &[
+                "{\"message\":{\"role\":\"assistant\",\"content\":\"Hel\"},\"done\":false}\n{\"message\":{\"role\":\"assi",
+                "stant\",\"content\":\"lo\"},\"done\":false}\n",
+                "{\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true,\"eval_count\":2}",
+            ]),
+        )]).await;
+        let (_dir, settings) = testing::settings(AppSettings::default());
+        let ollama = OllamaProvider::new(Some(&server.url), &settings);
+
+        let mut text = String::new();
+        let stats = ollama
+            .stream("llama3.2", vec![ChatMessage::new("user", "Hi")], &params(), &mut |token| text.push_str(token))
+            .await
+            .unwrap();
+        assert_eq!(text, "Hello");
+        assert_eq!(stats.eval_count, Some(2));
+        assert_eq!(server.requests()[0].json()["stream"], true);
+    }
+
+    #[tokio::test]
+    async fn stream_ending_early_is_an_error() {
+        let server = MockServer::start(vec![(
+            "/api/chat",
+            Reply::stream(NDJSON, &["{\"message\":{\"role\":\"assistant\",\"content\":\"Hel\"},\"done\":false}\n"]),
+        )]).await;
+        let (_dir, settings) = testing::settings(AppSettings::default());
+        let ollama = OllamaProvider::new(Some(&server.url), &settings);
+
+        let result = ollama.stream("llama3.2", vec![ChatMessage::new("user", "Hi")], &params(), &mut |_| {}).await;
+        assert!(matches!(result, Err(AppError::ProviderError { .. })), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn list_models_reads_tags() {
+        let server = MockServer::start(vec![(
+            "/api/tags",
+            Reply::json(200, json!({
+                "models": [{
+                    "name": "llama3.2:latest",
+                    "size": 2019393189u64,
+                    "modified_at": "2024-10-01T12:00:00Z",
+                    "digest": "a80c4f17acd5",
+                    "details": { "family": "llama", "parameter_size": "3.2B", "quantization_level": "Q4_K_M" }
+                }]
+            })),
+        )]).await;
+        let (_dir, settings) = testing::settings(AppSettings::default());
+        let ollama = OllamaProvider::new(Some(&server.url), &settings);
+
+        let models = ollama.list_models().await.unwrap();
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].details.parameter_size.as_deref(), Some("3.2B"));
+        assert!(find_model(&models, "llama3.2").is_some());
+        assert!(ollama.require_models(&["llama3.2", "mistral"]).await.is_err());
+        assert_eq!(server.requests()[0].method, "GET");
+    }
+
+    #[tokio::test]
+    async fn auth_header_is_only_sent_to_the_configured_server() {
+        let tags = || Reply::json(200, json!({ "models": [] }));
+        let configured = MockServer::start(vec![("/api/tags", tags()), ("/api/tags", tags())]).await;
+        let other = MockServer::start(vec![("/api/tags", tags())]).await;
+        let (_dir, settings) = testing::settings(AppSettings {
+            ollama_url: format!("{}/", configured.url),
+            auth_header: Some(AuthHeader { name: "Authorization".to_string(), value: "Bearer secret".to_string() }),
+            ..AppSettings::default()
+        });
+
+        OllamaProvider::new(None, &settings).list_models().await.unwrap();
+        OllamaProvider::new(Some(&configured.url), &settings).list_models().await.unwrap();
+        OllamaProvider::new(Some(&other.url), &settings).list_models().await.unwrap();
+
+        for request in configured.requests() {
+            assert_eq!(request.header("authorization"), Some("Bearer secret"));
+        }
+        assert_eq!(other.requests()[0].header("authorization"), None);
+    }
+
+    #[tokio::test]
+    async fn error_statuses_become_app_errors() {
+        let server = MockServer::start(vec![
+            ("/api/chat", Reply::json(404, json!({ "error": "model 'missing' not found" }))),
+            ("/api/chat", Reply::json(500, json!({ "error": "out of memory" }))),
+        ]).await;
+        let (_dir, settings) = testing::settings(AppSettings::default());
+        let ollama = OllamaProvider::new(Some(&server.url), &settings);
+        let messages = vec![ChatMessage::new("user", "Hi")];
+
+        let missing = ollama.chat("missing", messages.clone(), &[], &params()).await;
+        assert!(matches!(&missing, Err(AppError::ModelNotFound { model, .. }) if model == "missing"), "{:?}", missing);
+
+        let failed = ollama.chat("llama3.2", messages, &[], &params()).await;
+        assert!(matches!(&failed, Err(AppError::ProviderError { message, .. }) if message.contains("out of memory")), "{:?}", failed);
+    }
+
+    #[tokio::test]
+    async fn closed_port_is_unreachable() {
+        let url = testing::closed_url().await;
+        let (_dir, settings) = testing::settings(AppSettings::default());
+        let ollama = OllamaProvider::new(Some(&url), &settings);
+
+        let result = ollama.list_models().await;
+        assert!(matches!(result, Err(AppError::OllamaUnreachable { .. })), "{:?}", result);
     }
 }
@@ -0,0 +1,609 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use reqwest::StatusCode;
+use crate::error::AppError;
+use crate::ollama::{
+    ChatMessage, GenerationParams, GenerationStats, ModelDetails, ModelInfo, ToolCall, ToolCallFunction,
+    ToolDefinition,
+};
+use crate::provider::{self, LlmProvider};
+use crate::settings::SettingsManager;
+
+const PROVIDER_NAME: &str = "OpenAI-compatible server";
+
+#[derive(Serialize, Debug)]
+struct CompletionRequest {
+    model: String,
+    messages: Vec<WireMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<ToolDefinition>,
+}
+
+// A chat message in the OpenAI format. Unlike Ollama's, tool calls carry ids that the
+// `tool` messages answering them refer back to.
+#[derive(Serialize, Deserialize, Debug)]
+struct WireMessage {
+    role: String,
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<WireToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct WireToolCall {
+    #[serde(default)]
+    id: String,
+    #[serde(rename = "type", default = "function_type")]
+    kind: String,
+    function: WireFunction,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct WireFunction {
+    name: String,
+    // JSON-encoded arguments object
+    #[serde(default)]
+    arguments: String,
+}
+
+fn function_type() -> String {
+    "function".to_string()
+}
+
+#[derive(Deserialize, Debug)]
+struct CompletionResponse {
+    choices: Vec<Choice>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Choice {
+    message: WireMessage,
+}
+
+#[derive(Deserialize, Debug)]
+struct Usage {
+    #[serde(default)]
+    prompt_tokens: Option<u64>,
+    #[serde(default)]
+    completion_tokens: Option<u64>,
+}
+
+impl Usage {
+    fn stats(&self) -> GenerationStats {
+        GenerationStats {
+            prompt_eval_count: self.prompt_tokens,
+            eval_count: self.completion_tokens,
+            ..Default::default()
+        }
+    }
+}
+
+// One server-sent event of a streamed completion
+#[derive(Deserialize, Debug)]
+struct StreamChunk {
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+    #[serde(default)]
+    usage: Option<Usage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct StreamChoice {
+    #[serde(default)]
+    delta: StreamDelta,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct StreamDelta {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ModelList {
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ModelEntry {
+    id: String,
+}
+
+// Servers speaking the OpenAI chat completions API, such as llama.cpp server and vLLM
+pub struct OpenAiProvider {
+    base_url: String,
+    api_key: Option<String>,
+    read_timeout: Duration,
+    client: reqwest::Client,
+}
+
+impl OpenAiProvider {
+    pub fn new(base_url: &str, api_key: Option<String>, settings: &SettingsManager) -> Self {
+        OpenAiProvider {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.filter(|key| !key.is_empty()),
+            read_timeout: settings.settings().read_timeout(),
+            client: settings.client(),
+        }
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let builder = self.client.request(method, format!("{}{}", self.base_url, path));
+        match &self.api_key {
+            Some(key) => builder.bearer_auth(key),
+            None => builder,
+        }
+    }
+
+    fn request_error(&self, error: reqwest::Error) -> AppError {
+        AppError::from_request(PROVIDER_NAME, &self.base_url, self.read_timeout, error)
+    }
+
+    // Streamed requests have no overall timeout; the stream reader times out each chunk instead
+    async fn send(&self, request: &CompletionRequest) -> Result<reqwest::Response, AppError> {
+        let mut builder = self.request(reqwest::Method::POST, "/chat/completions").json(request);
+        if !request.stream {
+            builder = builder.timeout(self.read_timeout);
+        }
+        let response = provider::read_within(self.read_timeout, builder.send())
+            .await?
+            .map_err(|e| self.request_error(e))?;
+        check_status(response, Some(&request.model)).await
+    }
+
+    fn completion_request(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDefinition],
+        params: &GenerationParams,
+        stream: bool,
+    ) -> CompletionRequest {
+        // There is no `num_ctx`; the server's own context size applies, but the history is
+        // still trimmed to the agent's setting
+        let options = &params.options;
+        CompletionRequest {
+            model: model.to_string(),
+            messages: to_wire_messages(provider::prepare_chat_messages(messages, params)),
+            stream,
+            temperature: options.temperature,
+            top_p: options.top_p,
+            stop: options.stop.clone(),
+            seed: options.seed,
+            tools: tools.to_vec(),
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    async fn generate(&self, model: &str, prompt: &str, params: &GenerationParams) -> Result<String, AppError> {
+        let reply = self.chat(model, vec![ChatMessage::new("user", prompt)], &[], params).await?;
+        Ok(reply.content)
+    }
+
+    async fn chat(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDefinition],
+        params: &GenerationParams,
+    ) -> Result<ChatMessage, AppError> {
+        let request = self.completion_request(model, messages, tools, params, false);
+        let text = self
+            .send(&request)
+            .await?
+            .text()
+            .await
+            .map_err(|e| self.request_error(e))?;
+        let completion: CompletionResponse = serde_json::from_str(&text)
+            .map_err(|e| server_error(format!("Failed to parse completion response: {}. Response: {}", e, text)))?;
+
+        let message = completion
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message)
+            .ok_or_else(|| server_error(format!("Completion response has no choices. Response: {}", text)))?;
+        Ok(from_wire_message(message))
+    }
+
+    async fn stream(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        params: &GenerationParams,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<GenerationStats, AppError> {
+        let request = self.completion_request(model, messages, &[], params, true);
+        let mut response = self.send(&request).await?;
+
+        // Events can be split across network chunks, so buffer until a full line arrives
+        let mut stats = GenerationStats::default();
+        let mut buffer: Vec<u8> = Vec::new();
+        while let Some(chunk) = provider::read_within(self.read_timeout, response.chunk())
+            .await?
+            .map_err(|e| self.request_error(e))?
+        {
+            buffer.extend_from_slice(&chunk);
+            while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=pos).collect();
+                match parse_event_line(&line)? {
+                    Some(Event::Done) => return Ok(stats),
+                    Some(Event::Chunk(chunk)) => {
+                        for choice in &chunk.choices {
+                            if let Some(content) = &choice.delta.content {
+                                on_token(content);
+                            }
+                        }
+                        if let Some(usage) = &chunk.usage {
+                            stats = usage.stats();
+                        }
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        Err(server_error("Completion stream ended before [DONE]".to_string()))
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, AppError> {
+        let response = self
+            .request(reqwest::Method::GET, "/models")
+            .timeout(self.read_timeout)
+            .send()
+            .await
+            .map_err(|e| self.request_error(e))?;
+        let models: ModelList = check_status(response, None)
+            .await?
+            .json()
+            .await
+            .map_err(|e| server_error(format!("Failed to parse model list: {}", e)))?;
+        Ok(models
+            .data
+            .into_iter()
+            .map(|model| ModelInfo {
+                name: model.id,
+                size: 0,
+                modified_at: String::new(),
+                digest: None,
+                details: ModelDetails::default(),
+            })
+            .collect())
+    }
+}
+
+enum Event {
+    Chunk(StreamChunk),
+    Done,
+}
+
+// Parses one line of a server-sent event stream; only `data:` lines carry anything
+fn parse_event_line(line: &[u8]) -> Result<Option<Event>, AppError> {
+    let text = String::from_utf8_lossy(line);
+    let data = match text.trim().strip_prefix("data:") {
+        Some(data) => data.trim(),
+        None => return Ok(None),
+    };
+    if data == "[DONE]" {
+        return Ok(Some(Event::Done));
+    }
+
+    let chunk: StreamChunk = serde_json::from_str(data)
+        .map_err(|e| server_error(format!("Failed to parse completion stream chunk: {}. Chunk: {}", e, data)))?;
+    Ok(Some(Event::Chunk(chunk)))
+}
+
+fn server_error(message: String) -> AppError {
+    AppError::ProviderError {
+        provider: PROVIDER_NAME.to_string(),
+        message,
+    }
+}
+
+// Turns an error status into an error carrying the server's explanation. `model` is the
+// model the request is for, so a 404 can be reported as a missing model.
+async fn check_status(response: reqwest::Response, model: Option<&str>) -> Result<reqwest::Response, AppError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    if let (StatusCode::NOT_FOUND, Some(model)) = (status, model) {
+        return Err(AppError::ModelNotFound {
+            provider: PROVIDER_NAME.to_string(),
+            model: model.to_string(),
+        });
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(server_error(format!("Server returned {}: {}", status, body)))
+}
+
+// Tool calls keep the id the server gave them. Calls without one, e.g. from a history
+// produced by Ollama, get an id here. A `tool` message without an id answers the oldest
+// call that hasn't been answered yet.
+fn to_wire_messages(messages: Vec<ChatMessage>) -> Vec<WireMessage> {
+    let mut next_id = 0;
+    let mut unanswered: VecDeque<String> = VecDeque::new();
+    messages
+        .into_iter()
+        .map(|message| {
+            let tool_calls: Vec<WireToolCall> = message
+                .tool_calls
+                .into_iter()
+                .map(|call| {
+                    let id = call.id.filter(|id| !id.is_empty()).unwrap_or_else(|| {
+                        next_id += 1;
+                        format!("call_{}", next_id)
+                    });
+                    unanswered.push_back(id.clone());
+                    WireToolCall {
+                        id,
+                        kind: function_type(),
+                        function: WireFunction {
+                            name: call.function.name,
+                            arguments: call.function.arguments.to_string(),
+                        },
+                    }
+                })
+                .collect();
+            let tool_call_id = match (message.role.as_str(), message.tool_call_id) {
+                ("tool", Some(id)) => {
+                    unanswered.retain(|call| *call != id);
+                    Some(id)
+                }
+                ("tool", None) => unanswered.pop_front(),
+                _ => None,
+            };
+            WireMessage {
+                role: message.role,
+                content: Some(message.content),
+                tool_calls,
+                tool_call_id,
+            }
+        })
+        .collect()
+}
+
+fn from_wire_message(message: WireMessage) -> ChatMessage {
+    let tool_calls = message
+        .tool_calls
+        .into_iter()
+        .map(|call| ToolCall {
+            id: Some(call.id).filter(|id| !id.is_empty()),
+            function: ToolCallFunction {
+                // Some servers send arguments that aren't valid JSON; pass them on as a string
+                arguments: serde_json::from_str(&call.function.arguments)
+                    .unwrap_or(serde_json::Value::String(call.function.arguments)),
+                name: call.function.name,
+            },
+        })
+        .collect();
+    ChatMessage {
+        role: message.role,
+        content: message.content.unwrap_or_default(),
+        tool_calls,
+        tool_name: None,
+        tool_call_id: message.tool_call_id,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::AppSettings;
+    use crate::testing::{self, MockServer, Reply};
+    use serde_json::json;
+
+    const SSE: &str = "text/event-stream";
+
+    fn provider(url: &str, settings: &SettingsManager) -> OpenAiProvider {
+        OpenAiProvider::new(&format!("{}/v1", url), Some("sk-test".to_string()), settings)
+    }
+
+    fn completion(message: serde_json::Value) -> Reply {
+        Reply::json(200, json!({ "choices": [{ "index": 0, "message": message, "finish_reason": "stop" }] }))
+    }
+
+    #[tokio::test]
+    async fn chat_sends_options_and_reads_reply() {
+        let server = MockServer::start(vec![(
+            "/v1/chat/completions",
+            completion(json!({ "role": "assistant", "content": "Hello" })),
+        )]).await;
+        let (_dir, settings) = testing::settings(AppSettings::default());
+        let params = GenerationParams {
+            system: Some("Be brief.".to_string()),
+            options: crate::ollama::ModelOptions {
+                temperature: Some(0.5),
+                stop: vec!["END".to_string()],
+                ..Default::default()
+            },
+        };
+
+        let reply = provider(&server.url, &settings)
+            .chat("qwen", vec![ChatMessage::new("user", "Hi")], &[], &params)
+            .await
+            .unwrap();
+        assert_eq!(reply.content, "Hello");
+
+        let request = &server.requests()[0];
+        assert_eq!(request.header("authorization"), Some("Bearer sk-test"));
+        let body = request.json();
+        assert_eq!(body["model"], "qwen");
+        assert_eq!(body["stream"], false);
+        assert_eq!(body["temperature"], 0.5);
+        assert_eq!(body["stop"], json!(["END"]));
+        assert_eq!(body["messages"][0], json!({ "role": "system", "content": "Be brief." }));
+    }
+
+    #[tokio::test]
+    async fn tool_call_ids_round_trip() {
+        let server = MockServer::start(vec![
+            (
+                "/v1/chat/completions",
+                completion(json!({
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_abc123",
+                        "type": "function",
+                        "function": { "name": "list_directory", "arguments": "{\"path\":\"/tmp\"}" }
+                    }]
+                })),
+            ),
+            ("/v1/chat/completions", completion(json!({ "role": "assistant", "content": "Two files." }))),
+        ]).await;
+        let (_dir, settings) = testing::settings(AppSettings::default());
+        let openai = provider(&server.url, &settings);
+        let params = GenerationParams::default();
+
+        let mut messages = vec![ChatMessage::new("user", "What's in /tmp?")];
+        let call = openai.chat("qwen", messages.clone(), &[], &params).await.unwrap();
+        assert_eq!(call.tool_calls[0].id.as_deref(), Some("call_abc123"));
+        assert_eq!(call.tool_calls[0].function.arguments, json!({ "path": "/tmp" }));
+
+        let mut result = ChatMessage::new("tool", "a.txt\nb.txt");
+        result.tool_call_id = call.tool_calls[0].id.clone();
+        messages.push(call);
+        messages.push(result);
+        let reply = openai.chat("qwen", messages, &[], &params).await.unwrap();
+        assert_eq!(reply.content, "Two files.");
+
+        let sent = server.requests()[1].json();
+        assert_eq!(sent["messages"][1]["tool_calls"][0]["id"], "call_abc123");
+        assert_eq!(sent["messages"][1]["tool_calls"][0]["function"]["arguments"], "{\"path\":\"/tmp\"}");
+        assert_eq!(sent["messages"][2]["tool_call_id"], "call_abc123");
+    }
+
+    #[test]
+    fn calls_without_ids_are_answered_in_order() {
+        let call = |name: &str| ToolCall {
+            id: None,
+            function: ToolCallFunction { name: name.to_string(), arguments: json!({}) },
+        };
+        let mut assistant = ChatMessage::new("assistant", "");
+        assistant.tool_calls = vec![call("read_file"), call("list_directory")];
+        let messages = vec![assistant, ChatMessage::new("tool", "one"), ChatMessage::new("tool", "two")];
+
+        let wire = to_wire_messages(messages);
+        let ids: Vec<&str> = wire[0].tool_calls.iter().map(|call| call.id.as_str()).collect();
+        assert_eq!(ids, ["call_1", "call_2"]);
+        assert_eq!(wire[1].tool_call_id.as_deref(), Some("call_1"));
+        assert_eq!(wire[2].tool_call_id.as_deref(), Some("call_2"));
+    }
+
+    #[tokio::test]
+    async fn stream_reads_events_split_across_chunks() {
+        let server = MockServer::start(vec![(
+            "/v1/chat/completions",
+            Reply::stream(SSE, &[
+                ": keep-alive\n\ndata: {\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"Hel\"}}]}\n\ndata: {\"choi",
+                "ces\":[{\"delta\":{\"content\":\"lo\"}}]}\n",
+                "\ndata: {\"choices\":[],\"usage\":{\"prompt_tokens\":5,\"completion_tokens\":2}}\n\ndata: [DO",
+                "NE]\n\n",
+            ]),
+        )]).await;
+        let (_dir, settings) = testing::settings(AppSettings::default());
+
+        let mut text = String::new();
+        let stats = provider(&server.url, &settings)
+            .stream("qwen", vec![ChatMessage::new("user", "Hi")], &GenerationParams::default(), &mut |token| {
+                text.push_str(token)
+            })
+            .await
+            .unwrap();
+        assert_eq!(text, "Hello");
+        assert_eq!(stats.prompt_eval_count, Some(5));
+        assert_eq!(stats.eval_count, Some(2));
+        assert_eq!(server.requests()[0].json()["stream"], true);
+    }
+
+    #[tokio::test]
+    async fn stream_without_done_is_an_error() {
+        let server = MockServer::start(vec![(
+            "/v1/chat/completions",
+            Reply::stream(SSE, &["data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\n"]),
+        )]).await;
+        let (_dir, settings) = testing::settings(AppSettings::default());
+
+        let result = provider(&server.url, &settings)
+            .stream("qwen", vec![ChatMessage::new("user", "Hi")], &GenerationParams::default(), &mut |_| {})
+            .await;
+        assert!(matches!(result, Err(AppError::ProviderError { .. })), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn stalled_stream_times_out() {
+        let server = MockServer::start(vec![(
+            "/v1/chat/completions",
+            Reply::stalled(SSE, &["data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\n"]),
+        )]).await;
+        let (_dir, settings) = testing::settings(AppSettings {
+            read_timeout_seconds: 1,
+            ..AppSettings::default()
+        });
+
+        let result = provider(&server.url, &settings)
+            .stream("qwen", vec![ChatMessage::new("user", "Hi")], &GenerationParams::default(), &mut |_| {})
+            .await;
+        assert!(matches!(result, Err(AppError::Timeout { seconds: 1 })), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn list_models_reads_model_ids() {
+        let server = MockServer::start(vec![(
+            "/v1/models",
+            Reply::json(200, json!({ "object": "list", "data": [{ "id": "qwen2.5-7b", "object": "model" }] })),
+        )]).await;
+        let (_dir, settings) = testing::settings(AppSettings::default());
+
+        let models = provider(&server.url, &settings).list_models().await.unwrap();
+        assert_eq!(models.iter().map(|m| m.name.as_str()).collect::<Vec<_>>(), ["qwen2.5-7b"]);
+        assert_eq!(server.requests()[0].header("authorization"), Some("Bearer sk-test"));
+    }
+
+    #[tokio::test]
+    async fn error_statuses_become_app_errors() {
+        let server = MockServer::start(vec![
+            ("/v1/chat/completions", Reply::json(404, json!({ "error": { "message": "model not found" } }))),
+            ("/v1/chat/completions", Reply::json(401, json!({ "error": { "message": "invalid api key" } }))),
+        ]).await;
+        let (_dir, settings) = testing::settings(AppSettings::default());
+        let openai = provider(&server.url, &settings);
+        let messages = vec![ChatMessage::new("user", "Hi")];
+
+        let missing = openai.chat("missing", messages.clone(), &[], &GenerationParams::default()).await;
+        assert!(matches!(&missing, Err(AppError::ModelNotFound { model, .. }) if model == "missing"), "{:?}", missing);
+
+        let denied = openai.chat("qwen", messages, &[], &GenerationParams::default()).await;
+        assert!(
+            matches!(&denied, Err(AppError::ProviderError { message, .. }) if message.contains("401") && message.contains("invalid api key")),
+            "{:?}",
+            denied
+        );
+    }
+
+    #[tokio::test]
+    async fn closed_port_is_unreachable() {
+        let url = testing::closed_url().await;
+        let (_dir, settings) = testing::settings(AppSettings::default());
+
+        let result = provider(&url, &settings).list_models().await;
+        assert!(matches!(result, Err(AppError::ProviderUnreachable { .. })), "{:?}", result);
+    }
+}
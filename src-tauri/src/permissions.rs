@@ -0,0 +1,430 @@
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use chrono::{DateTime, Duration, Utc};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use crate::error::AppError;
+use crate::persist;
+
+// How long the user has to answer a permission prompt, and how many may be open at once
+const GRANT_TOKEN_LIFETIME_MINUTES: i64 = 10;
+const MAX_PENDING_GRANTS: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+    Read,
+    Write,
+    Delete,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+            Access::Delete => write!(f, "delete"),
+        }
+    }
+}
+
+// Directories an agent may touch without asking, and what it may do inside them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AgentScope {
+    #[serde(default)]
+    pub roots: Vec<PathBuf>,
+    #[serde(default)]
+    pub access: Vec<Access>,
+}
+
+// A user approval for a path (and everything below it). `agent_id: None` applies to every caller.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Grant {
+    pub agent_id: Option<String>,
+    pub path: PathBuf,
+    pub access: Access,
+    pub granted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyConfig {
+    // Scope used for callers without an agent and for agents without their own entry
+    #[serde(default)]
+    pub default_scope: AgentScope,
+    #[serde(default)]
+    pub agents: HashMap<String, AgentScope>,
+    // Glob patterns that are always denied, even with a grant
+    #[serde(default = "default_deny_patterns")]
+    pub deny_patterns: Vec<String>,
+    // "Allow always" grants; "allow once" grants are kept in memory only
+    #[serde(default)]
+    pub grants: Vec<Grant>,
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        PolicyConfig {
+            default_scope: AgentScope::default(),
+            agents: HashMap::new(),
+            deny_patterns: default_deny_patterns(),
+            grants: Vec::new(),
+        }
+    }
+}
+
+fn default_deny_patterns() -> Vec<String> {
+    vec![
+        "**/.ssh".to_string(),
+        "**/.ssh/**".to_string(),
+        "**/.gnupg/**".to_string(),
+        "**/.aws/**".to_string(),
+        "**/.env".to_string(),
+        "**/.env.*".to_string(),
+        "**/id_rsa*".to_string(),
+        "**/id_ed25519*".to_string(),
+    ]
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum DenialReason {
+    InvalidPath,
+    DenyListed { pattern: String },
+    OutsideAllowedRoots,
+    AccessNotAllowed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PermissionDenied {
+    pub path: String,
+    pub access: Access,
+    pub agent_id: Option<String>,
+    pub reason: DenialReason,
+    // Set when the user may approve the call: passing it to `grant` allows exactly this path,
+    // access and agent. Deny-listed and invalid paths get none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grant_token: Option<String>,
+}
+
+impl fmt::Display for PermissionDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match &self.reason {
+            DenialReason::InvalidPath => "path must be absolute and resolvable".to_string(),
+            DenialReason::DenyListed { pattern } => format!("path matches deny pattern '{}'", pattern),
+            DenialReason::OutsideAllowedRoots => "path is outside the allowed directories and has no grant".to_string(),
+            DenialReason::AccessNotAllowed => format!("{} access is not allowed here", self.access),
+        };
+        write!(f, "Permission denied for {} on {}: {}", self.access, self.path, reason)
+    }
+}
+
+impl std::error::Error for PermissionDenied {}
+
+// A denial the user hasn't answered yet
+struct PendingGrant {
+    token: String,
+    agent_id: Option<String>,
+    path: PathBuf,
+    access: Access,
+    issued_at: DateTime<Utc>,
+}
+
+pub struct PermissionPolicy {
+    config: Mutex<PolicyConfig>,
+    once_grants: Mutex<Vec<Grant>>,
+    pending_grants: Mutex<Vec<PendingGrant>>,
+    storage_path: PathBuf,
+}
+
+impl PermissionPolicy {
+    pub fn new(storage_path: PathBuf) -> Self {
+        let config = fs::read_to_string(&storage_path)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<PolicyConfig>(&contents).ok())
+            .unwrap_or_default();
+
+        PermissionPolicy {
+            config: Mutex::new(config),
+            once_grants: Mutex::new(Vec::new()),
+            pending_grants: Mutex::new(Vec::new()),
+            storage_path,
+        }
+    }
+
+    // Checks `access` to `path` and returns the resolved path to operate on
+    pub fn check(&self, agent_id: Option<&str>, path: &str, access: Access) -> Result<PathBuf, PermissionDenied> {
+        let deny = |reason: DenialReason| PermissionDenied {
+            path: path.to_string(),
+            access,
+            agent_id: agent_id.map(|id| id.to_string()),
+            reason,
+            grant_token: None,
+        };
+
+        let resolved = normalize_path(Path::new(path)).ok_or_else(|| deny(DenialReason::InvalidPath))?;
+        let config = self.config.lock().unwrap();
+
+        if let Some(pattern) = matching_deny_pattern(&config.deny_patterns, &resolved) {
+            return Err(deny(DenialReason::DenyListed { pattern }));
+        }
+
+        let grant_matches = |grant: &Grant| {
+            grant.access == access
+                && (grant.agent_id.is_none() || grant.agent_id.as_deref() == agent_id)
+                && resolved.starts_with(&grant.path)
+        };
+
+        // "Allow once" grants are consumed by the first call they cover
+        {
+            let mut once_grants = self.once_grants.lock().unwrap();
+            if let Some(pos) = once_grants.iter().position(&grant_matches) {
+                once_grants.remove(pos);
+                return Ok(resolved);
+            }
+        }
+
+        if config.grants.iter().any(grant_matches) {
+            return Ok(resolved);
+        }
+
+        let scope = agent_id
+            .and_then(|id| config.agents.get(id))
+            .unwrap_or(&config.default_scope);
+        let in_root = scope
+            .roots
+            .iter()
+            .filter_map(|root| normalize_path(root))
+            .any(|root| resolved.starts_with(root));
+
+        let reason = if !in_root {
+            DenialReason::OutsideAllowedRoots
+        } else if !scope.access.contains(&access) {
+            DenialReason::AccessNotAllowed
+        } else {
+            return Ok(resolved);
+        };
+        Err(PermissionDenied {
+            grant_token: Some(self.issue_grant_token(agent_id, resolved, access)),
+            ..deny(reason)
+        })
+    }
+
+    // Remembers a denial the user may approve and returns the token that approves it
+    fn issue_grant_token(&self, agent_id: Option<&str>, path: PathBuf, access: Access) -> String {
+        let token = new_token();
+        let mut pending = self.pending_grants.lock().unwrap();
+        let cutoff = Utc::now() - Duration::minutes(GRANT_TOKEN_LIFETIME_MINUTES);
+        pending.retain(|p| p.issued_at > cutoff);
+        if pending.len() >= MAX_PENDING_GRANTS {
+            pending.remove(0);
+        }
+        pending.push(PendingGrant {
+            token: token.clone(),
+            agent_id: agent_id.map(|id| id.to_string()),
+            path,
+            access,
+            issued_at: Utc::now(),
+        });
+        token
+    }
+
+    // Checks every entry below `dir` against the deny-list, so deleting a directory can't
+    // take a protected file inside it along. Symlinks aren't followed: removing a link
+    // leaves its target alone.
+    pub fn check_tree(&self, agent_id: Option<&str>, dir: &Path, access: Access) -> Result<(), PermissionDenied> {
+        let patterns = self.config.lock().unwrap().deny_patterns.clone();
+        let set = build_glob_set(&patterns).ok();
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(current) = pending.pop() {
+            // An unreadable directory can't be removed either, so there's nothing to protect
+            let entries = match fs::read_dir(&current) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let candidate = path.to_string_lossy().replace('\\', "/");
+                let pattern = match &set {
+                    Some(set) => set.matches(&candidate).first().map(|&i| patterns[i].clone()),
+                    None => Some("<invalid deny pattern>".to_string()),
+                };
+                if let Some(pattern) = pattern {
+                    return Err(PermissionDenied {
+                        path: path.to_string_lossy().to_string(),
+                        access,
+                        agent_id: agent_id.map(|id| id.to_string()),
+                        reason: DenialReason::DenyListed { pattern },
+                        grant_token: None,
+                    });
+                }
+                if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                    pending.push(path);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Approves the denial `token` was issued with. Each token works once, so a grant can only
+    // follow a call the policy actually refused.
+    pub fn grant(&self, token: &str, always: bool) -> Result<Grant, AppError> {
+        let pending = {
+            let mut pending = self.pending_grants.lock().unwrap();
+            let cutoff = Utc::now() - Duration::minutes(GRANT_TOKEN_LIFETIME_MINUTES);
+            pending.retain(|p| p.issued_at > cutoff);
+            pending
+                .iter()
+                .position(|p| p.token == token)
+                .map(|pos| pending.remove(pos))
+                .ok_or_else(|| AppError::invalid("Unknown or expired permission token"))?
+        };
+        let grant = Grant {
+            agent_id: pending.agent_id,
+            path: pending.path,
+            access: pending.access,
+            granted_at: Utc::now(),
+        };
+
+        if always {
+            self.config.lock().unwrap().grants.push(grant.clone());
+            self.save()?;
+        } else {
+            self.once_grants.lock().unwrap().push(grant.clone());
+        }
+        Ok(grant)
+    }
+
+    pub fn revoke(&self, agent_id: Option<&str>, path: &str, access: Access) -> Result<(), AppError> {
+        let resolved = normalize_path(Path::new(path))
+            .ok_or_else(|| AppError::invalid(format!("Invalid path '{}': must be absolute", path)))?;
+        let keep = |g: &Grant| !(g.access == access && g.agent_id.as_deref() == agent_id && g.path == resolved);
+
+        self.once_grants.lock().unwrap().retain(keep);
+        self.config.lock().unwrap().grants.retain(keep);
+        self.save()
+    }
+
+    pub fn get_config(&self) -> PolicyConfig {
+        self.config.lock().unwrap().clone()
+    }
+
+    fn save(&self) -> Result<(), AppError> {
+        let config = self.config.lock().unwrap();
+        let json = serde_json::to_string_pretty(&*config)
+            .map_err(|e| AppError::internal(format!("Failed to serialize permissions: {}", e)))?;
+
+        persist::write_with_backup(&self.storage_path, &json)
+            .map_err(|e| AppError::io(self.storage_path.display().to_string(), e))
+    }
+}
+
+// 128 bits from std's randomly keyed hasher; enough that a token can't be guessed
+fn new_token() -> String {
+    let half = || RandomState::new().build_hasher().finish();
+    format!("{:016x}{:016x}", half(), half())
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|e| format!("Invalid deny pattern '{}': {}", pattern, e))?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|e| format!("Invalid deny patterns: {}", e))
+}
+
+fn matching_deny_pattern(patterns: &[String], path: &Path) -> Option<String> {
+    // Patterns are written with `/`, so match against a forward-slash form of the path
+    let candidate = path.to_string_lossy().replace('\\', "/");
+    match build_glob_set(patterns) {
+        Ok(set) => set.matches(&candidate).first().map(|&i| patterns[i].clone()),
+        // A broken deny-list must not open everything up
+        Err(_) => Some("<invalid deny pattern>".to_string()),
+    }
+}
+
+// Makes `path` absolute and resolves symlinks on its longest existing prefix, so neither
+// `..` nor a link can step outside an allowed root. Missing trailing components (e.g. a
+// file about to be written) are appended as-is.
+fn normalize_path(path: &Path) -> Option<PathBuf> {
+    if !path.is_absolute() {
+        return None;
+    }
+
+    let mut existing = path.to_path_buf();
+    let mut missing = Vec::new();
+    while !existing.exists() {
+        missing.push(existing.file_name()?.to_os_string());
+        existing = existing.parent()?.to_path_buf();
+    }
+
+    let mut resolved = existing.canonicalize().ok()?;
+    for part in missing.iter().rev() {
+        resolved.push(part);
+    }
+    Some(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn policy(dir: &TempDir) -> PermissionPolicy {
+        PermissionPolicy::new(dir.path().join("permissions.json"))
+    }
+
+    #[test]
+    fn a_grant_token_approves_only_the_denied_call_and_only_once() {
+        let dir = TempDir::new().unwrap();
+        let policy = policy(&dir);
+        let file = dir.path().join("notes.txt");
+        let path = file.to_str().unwrap();
+
+        let denied = policy.check(Some("agent"), path, Access::Read).unwrap_err();
+        let token = denied.grant_token.expect("denial should carry a grant token");
+
+        let grant = policy.grant(&token, false).unwrap();
+        assert_eq!(grant.agent_id.as_deref(), Some("agent"));
+        assert_eq!(grant.access, Access::Read);
+        assert!(policy.grant(&token, false).is_err());
+
+        // The once-grant covers the denied call and nothing else
+        assert!(policy.check(Some("agent"), path, Access::Write).is_err());
+        assert!(policy.check(Some("other"), path, Access::Read).is_err());
+        assert!(policy.check(Some("agent"), path, Access::Read).is_ok());
+        assert!(policy.check(Some("agent"), path, Access::Read).is_err());
+    }
+
+    #[test]
+    fn unknown_tokens_and_deny_listed_paths_cannot_be_granted() {
+        let dir = TempDir::new().unwrap();
+        let policy = policy(&dir);
+        assert!(matches!(policy.grant("made-up", true), Err(AppError::InvalidInput { .. })));
+
+        let secret = dir.path().join(".env");
+        let denied = policy.check(None, secret.to_str().unwrap(), Access::Read).unwrap_err();
+        assert!(matches!(denied.reason, DenialReason::DenyListed { .. }));
+        assert!(denied.grant_token.is_none());
+    }
+
+    #[test]
+    fn always_grants_are_saved_and_reloaded() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("notes.txt");
+        let path = file.to_str().unwrap();
+
+        let first = policy(&dir);
+        let token = first.check(None, path, Access::Write).unwrap_err().grant_token.unwrap();
+        first.grant(&token, true).unwrap();
+
+        let reloaded = policy(&dir);
+        assert!(reloaded.check(None, path, Access::Write).is_ok());
+        assert!(reloaded.check(None, path, Access::Write).is_ok());
+    }
+}
@@ -0,0 +1,395 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+// Keeps temp files of concurrent writes to the same file apart
+static NEXT_TEMP: AtomicUsize = AtomicUsize::new(0);
+
+// Files found to be written by a newer version of the app. They are never written to,
+// so that version can still read them.
+static NEWER_FILES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+// A data file that couldn't be loaded when the app started and what was done about it
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageWarning {
+    pub file: String,
+    pub message: String,
+    pub kind: WarningKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    // Unreadable; moved aside and replaced by the backup, if there was a usable one
+    Damaged,
+    // Written by a newer version of the app; left in place and not saved over
+    NewerFormat,
+}
+
+// Why a file's contents couldn't be loaded
+enum Unreadable {
+    Damaged(String),
+    NewerFormat(String),
+}
+
+impl Unreadable {
+    fn into_message(self) -> String {
+        match self {
+            Unreadable::Damaged(message) | Unreadable::NewerFormat(message) => message,
+        }
+    }
+}
+
+// Upgrades a file's data from one version to the next
+pub type Migration = fn(Value) -> Result<Value, String>;
+
+// How one kind of data file is versioned. Files are stored as `{ "version": n, "data": ... }`;
+// files from before versioning hold the bare data and count as version 0.
+pub struct Schema {
+    // `migrations[n]` turns version n data into version n + 1, so the current version is
+    // the number of migrations
+    pub migrations: &'static [Migration],
+}
+
+impl Schema {
+    pub fn version(&self) -> u64 {
+        self.migrations.len() as u64
+    }
+}
+
+// Version 0 -> 1 for files whose data kept its shape when the envelope was introduced
+pub fn add_envelope(data: Value) -> Result<Value, String> {
+    Ok(data)
+}
+
+pub struct Loaded<T> {
+    // None if the file doesn't exist or neither it nor its backup could be read
+    pub value: Option<T>,
+    pub warning: Option<StorageWarning>,
+}
+
+// Replaces `path` with `contents` so that a crash leaves either the old or the new file,
+// never a partial one. The previous version is kept as `<name>.bak`. Fails for files
+// written by a newer version of the app.
+pub fn write_with_backup(path: &Path, contents: &str) -> io::Result<()> {
+    if NEWER_FILES.lock().unwrap().iter().any(|newer| newer == path) {
+        return Err(io::Error::other("it was written by a newer version of the app; not overwriting it"));
+    }
+
+    let temp = sibling(path, &format!("{}.tmp", NEXT_TEMP.fetch_add(1, Ordering::Relaxed)));
+    {
+        let mut file = File::create(&temp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+    }
+
+    if path.exists() {
+        let backup = sibling(path, "bak");
+        fs::copy(path, &backup)?;
+        File::open(&backup)?.sync_all()?;
+    }
+
+    if let Err(e) = fs::rename(&temp, path) {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    sync_parent(path)
+}
+
+// Serializes `value` in the current version's envelope
+pub fn to_versioned_json<T: Serialize>(schema: &Schema, value: &T) -> serde_json::Result<String> {
+    let data = serde_json::to_value(value)?;
+    serde_json::to_string_pretty(&envelope(schema, data))
+}
+
+// Parses file contents of any known version, migrating them to the current one
+pub fn from_versioned_str<T: DeserializeOwned>(schema: &Schema, contents: &str) -> Result<T, String> {
+    let (data, _) = upgrade(schema, contents).map_err(Unreadable::into_message)?;
+    serde_json::from_value(data).map_err(|e| format!("failed to parse it: {}", e))
+}
+
+// Reads a versioned JSON file written by write_with_backup. Files from older versions are
+// migrated and written back in the current version, with the original kept as the backup.
+// If the file can't be read or parsed, it is moved aside to `<name>.corrupt` and the
+// backup is restored in its place. A file from a newer version of the app is left as it
+// is and later writes to it fail.
+pub fn read_json<T: DeserializeOwned>(path: &Path, schema: &Schema) -> Loaded<T> {
+    if !path.exists() {
+        return Loaded { value: None, warning: None };
+    }
+
+    let error = match parse_file::<T>(path, schema) {
+        Ok((value, None)) => return Loaded { value: Some(value), warning: None },
+        Ok((value, Some(upgraded))) => {
+            if let Err(e) = write_with_backup(path, &upgraded) {
+                eprintln!("Failed to save upgraded {}: {}", path.display(), e);
+            }
+            return Loaded { value: Some(value), warning: None };
+        }
+        Err(Unreadable::NewerFormat(error)) => {
+            NEWER_FILES.lock().unwrap().push(path.to_path_buf());
+            return Loaded {
+                value: None,
+                warning: Some(warning(
+                    path,
+                    WarningKind::NewerFormat,
+                    format!("{}; it was left unchanged and won't be saved over", error),
+                )),
+            };
+        }
+        Err(Unreadable::Damaged(error)) => error,
+    };
+
+    let corrupt = sibling(path, "corrupt");
+    if let Err(e) = fs::rename(path, &corrupt) {
+        eprintln!("Failed to move aside {}: {}", path.display(), e);
+    }
+
+    let backup = sibling(path, "bak");
+    match parse_file::<T>(&backup, schema) {
+        Ok((value, upgraded)) => {
+            let restored = match upgraded {
+                Some(contents) => write_with_backup(path, &contents),
+                None => fs::read_to_string(&backup).and_then(|contents| write_with_backup(path, &contents)),
+            };
+            if let Err(e) = restored {
+                eprintln!("Failed to restore {} from backup: {}", path.display(), e);
+            }
+            Loaded {
+                value: Some(value),
+                warning: Some(warning(path, WarningKind::Damaged, format!("{}; restored the last backup", error))),
+            }
+        }
+        Err(backup_error) => Loaded {
+            value: None,
+            warning: Some(warning(
+                path,
+                WarningKind::Damaged,
+                format!(
+                    "{}, and the backup is unusable ({}); the damaged file was kept as {}",
+                    error,
+                    backup_error.into_message(),
+                    corrupt.display()
+                ),
+            )),
+        },
+    }
+}
+
+// Deletes a file written by write_with_backup together with its backup
+pub fn remove_with_backup(path: &Path) -> io::Result<()> {
+    fs::remove_file(path)?;
+    match fs::remove_file(sibling(path, "bak")) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+// The parsed value, plus the file's new contents if it had to be migrated
+fn parse_file<T: DeserializeOwned>(path: &Path, schema: &Schema) -> Result<(T, Option<String>), Unreadable> {
+    let contents = fs::read_to_string(path).map_err(|e| Unreadable::Damaged(format!("failed to read it: {}", e)))?;
+    let (data, migrated) = upgrade(schema, &contents)?;
+    let upgraded = if migrated {
+        let json = serde_json::to_string_pretty(&envelope(schema, data.clone()))
+            .map_err(|e| Unreadable::Damaged(format!("failed to serialize it: {}", e)))?;
+        Some(json)
+    } else {
+        None
+    };
+    let value = serde_json::from_value(data).map_err(|e| Unreadable::Damaged(format!("failed to parse it: {}", e)))?;
+    Ok((value, upgraded))
+}
+
+// Unwraps the envelope and runs the migrations the data needs. Returns the data at the
+// current version and whether anything was migrated.
+fn upgrade(schema: &Schema, contents: &str) -> Result<(Value, bool), Unreadable> {
+    let value: Value =
+        serde_json::from_str(contents).map_err(|e| Unreadable::Damaged(format!("failed to parse it: {}", e)))?;
+    let (version, mut data) = match value {
+        Value::Object(mut map) if map.len() == 2 && map.contains_key("data") && map.get("version").map_or(false, Value::is_u64) => {
+            let version = map.get("version").and_then(Value::as_u64).unwrap_or_default();
+            (version, map.remove("data").unwrap_or_default())
+        }
+        legacy => (0, legacy),
+    };
+
+    if version > schema.version() {
+        return Err(Unreadable::NewerFormat(format!(
+            "it was written by a newer version of the app (format {}, this version reads up to {})",
+            version,
+            schema.version()
+        )));
+    }
+    for (from, migrate) in schema.migrations.iter().enumerate().skip(version as usize) {
+        data = migrate(data)
+            .map_err(|e| Unreadable::Damaged(format!("failed to upgrade it from format {}: {}", from, e)))?;
+    }
+    Ok((data, version < schema.version()))
+}
+
+fn envelope(schema: &Schema, data: Value) -> Value {
+    serde_json::json!({ "version": schema.version(), "data": data })
+}
+
+fn warning(path: &Path, kind: WarningKind, message: String) -> StorageWarning {
+    let file = path.display().to_string();
+    StorageWarning {
+        message: format!("{}: {}", file, message),
+        file,
+        kind,
+    }
+}
+
+// `tasks.json` -> `tasks.json.<suffix>`
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+// Makes the rename itself durable
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => File::open(parent)?.sync_all(),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{Agent, AGENTS_SCHEMA};
+    use crate::provider::ProviderConfig;
+    use crate::scheduler::{ScheduleType, Task, TASKS_SCHEMA};
+    use crate::session::{Session, SESSION_SCHEMA};
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn fixture(name: &str) -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/persist").join(name);
+        fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+    }
+
+    // Copies a fixture into a fresh directory as `name`
+    fn install(dir: &TempDir, fixture_name: &str, name: &str) -> PathBuf {
+        let path = dir.path().join(name);
+        fs::write(&path, fixture(fixture_name)).unwrap();
+        path
+    }
+
+    fn stored_version(path: &Path) -> Option<u64> {
+        let value: Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        value.get("version").and_then(Value::as_u64)
+    }
+
+    #[test]
+    fn bare_agent_list_is_upgraded() {
+        let dir = TempDir::new().unwrap();
+        let path = install(&dir, "agents_v0.json", "agents.json");
+
+        let loaded = read_json::<Vec<Agent>>(&path, &AGENTS_SCHEMA);
+        assert!(loaded.warning.is_none());
+        let agents = loaded.value.unwrap();
+        assert_eq!(agents.iter().map(|a| a.id.as_str()).collect::<Vec<_>>(), ["general", "coder"]);
+        assert!(matches!(agents[0].provider, ProviderConfig::Ollama { base_url: None }));
+
+        assert_eq!(stored_version(&path), Some(AGENTS_SCHEMA.version()));
+        assert_eq!(fs::read_to_string(sibling(&path, "bak")).unwrap(), fixture("agents_v0.json"));
+    }
+
+    #[test]
+    fn bare_task_map_without_timezone_becomes_utc() {
+        let dir = TempDir::new().unwrap();
+        let path = install(&dir, "tasks_v0.json", "tasks.json");
+
+        let loaded = read_json::<HashMap<String, Task>>(&path, &TASKS_SCHEMA);
+        assert!(loaded.warning.is_none());
+        let tasks = loaded.value.unwrap();
+        let task = &tasks["task_1700000000000"];
+        assert_eq!(task.timezone, "UTC");
+        assert_eq!(task.schedule_type, ScheduleType::Daily { at_hour: 9, at_minute: 0 });
+        assert_eq!(task.prompt_template, "Summarize yesterday's notes");
+        assert_eq!(stored_version(&path), Some(TASKS_SCHEMA.version()));
+    }
+
+    #[test]
+    fn bare_session_is_upgraded() {
+        let dir = TempDir::new().unwrap();
+        let path = install(&dir, "session_v0.json", "session_1700000000000.json");
+
+        let loaded = read_json::<Session>(&path, &SESSION_SCHEMA);
+        assert!(loaded.warning.is_none());
+        let session = loaded.value.unwrap();
+        assert_eq!(session.name, "Planning");
+        assert_eq!(session.messages.len(), 2);
+        assert_eq!(session.messages[1].agent_id.as_deref(), Some("general"));
+        assert_eq!(stored_version(&path), Some(SESSION_SCHEMA.version()));
+    }
+
+    #[test]
+    fn current_envelope_is_read_as_is() {
+        let dir = TempDir::new().unwrap();
+        let path = install(&dir, "agents_v1.json", "agents.json");
+
+        let loaded = read_json::<Vec<Agent>>(&path, &AGENTS_SCHEMA);
+        assert!(loaded.warning.is_none());
+        let agents = loaded.value.unwrap();
+        assert_eq!(agents.len(), 1);
+        assert!(matches!(&agents[0].provider, ProviderConfig::OpenAiCompatible { base_url, api_key: None }
+            if base_url == "http://localhost:8080/v1"));
+
+        // Nothing to migrate, so the file isn't rewritten
+        assert_eq!(fs::read_to_string(&path).unwrap(), fixture("agents_v1.json"));
+        assert!(!sibling(&path, "bak").exists());
+    }
+
+    #[test]
+    fn newer_version_is_left_alone_and_not_saved_over() {
+        let dir = TempDir::new().unwrap();
+        let path = install(&dir, "agents_future.json", "agents.json");
+        install(&dir, "agents_v0.json", "agents.json.bak");
+
+        let loaded = read_json::<Vec<Agent>>(&path, &AGENTS_SCHEMA);
+        assert!(loaded.value.is_none());
+        let warning = loaded.warning.unwrap();
+        assert_eq!(warning.kind, WarningKind::NewerFormat);
+        assert!(warning.message.contains("format 99"), "{}", warning.message);
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), fixture("agents_future.json"));
+        assert_eq!(fs::read_to_string(sibling(&path, "bak")).unwrap(), fixture("agents_v0.json"));
+        assert!(!sibling(&path, "corrupt").exists());
+
+        assert!(write_with_backup(&path, "[]").is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), fixture("agents_future.json"));
+    }
+
+    #[test]
+    fn corrupt_file_is_replaced_by_its_backup() {
+        let dir = TempDir::new().unwrap();
+        let path = install(&dir, "agents_corrupt.json", "agents.json");
+        install(&dir, "agents_v0.json", "agents.json.bak");
+
+        let loaded = read_json::<Vec<Agent>>(&path, &AGENTS_SCHEMA);
+        assert_eq!(loaded.warning.unwrap().kind, WarningKind::Damaged);
+        assert_eq!(loaded.value.unwrap().len(), 2);
+
+        assert_eq!(fs::read_to_string(sibling(&path, "corrupt")).unwrap(), fixture("agents_corrupt.json"));
+        // The restored backup is saved in the current format
+        assert_eq!(stored_version(&path), Some(AGENTS_SCHEMA.version()));
+        let restored = read_json::<Vec<Agent>>(&path, &AGENTS_SCHEMA);
+        assert!(restored.warning.is_none());
+        assert_eq!(restored.value.unwrap().len(), 2);
+    }
+}
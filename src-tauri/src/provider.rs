@@ -0,0 +1,226 @@
+use std::future::Future;
+use std::time::Duration;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use reqwest::Url;
+use crate::error::AppError;
+use crate::ollama::{ChatMessage, GenerationParams, GenerationStats, ModelInfo, OllamaProvider, ToolDefinition};
+use crate::openai::OpenAiProvider;
+use crate::settings::SettingsManager;
+
+// A model server the agents can talk to
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    // Single prompt with the params' system prompt; returns the reply text
+    async fn generate(&self, model: &str, prompt: &str, params: &GenerationParams) -> Result<String, AppError>;
+
+    // Multi-turn chat. `tools` are advertised to the model; the whole assistant message is
+    // returned so callers can inspect its `tool_calls`.
+    async fn chat(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDefinition],
+        params: &GenerationParams,
+    ) -> Result<ChatMessage, AppError>;
+
+    // Streaming chat, calling `on_token` for every piece of the reply as it arrives.
+    // Returns whatever usage stats the server reported.
+    async fn stream(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        params: &GenerationParams,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<GenerationStats, AppError>;
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, AppError>;
+}
+
+// Which provider an agent uses, as stored in agents.json
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum ProviderConfig {
+    // `base_url` defaults to the Ollama URL in the app settings
+    Ollama {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        base_url: Option<String>,
+    },
+    // Any server with the OpenAI chat completions API, e.g. llama.cpp server or vLLM.
+    // `base_url` includes the API prefix, e.g. "http://localhost:8080/v1".
+    OpenAiCompatible {
+        base_url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        api_key: Option<String>,
+    },
+}
+
+impl Default for ProviderConfig {
+    fn default() -> Self {
+        ProviderConfig::Ollama { base_url: None }
+    }
+}
+
+impl ProviderConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        let base_url = match self {
+            ProviderConfig::Ollama { base_url } => base_url.as_deref(),
+            ProviderConfig::OpenAiCompatible { base_url, .. } => Some(base_url.as_str()),
+        };
+        if let Some(base_url) = base_url {
+            let parsed = Url::parse(base_url)
+                .map_err(|e| format!("Invalid provider URL '{}': {}", base_url, e))?;
+            if parsed.scheme() != "http" && parsed.scheme() != "https" {
+                return Err(format!("Invalid provider URL '{}': must use http or https", base_url));
+            }
+        }
+        Ok(())
+    }
+
+    // The provider shares the app's HTTP client and timeouts
+    pub fn build(&self, settings: &SettingsManager) -> Box<dyn LlmProvider> {
+        match self {
+            ProviderConfig::Ollama { base_url } => Box::new(OllamaProvider::new(base_url.as_deref(), settings)),
+            ProviderConfig::OpenAiCompatible { base_url, api_key } => {
+                Box::new(OpenAiProvider::new(base_url, api_key.clone(), settings))
+            }
+        }
+    }
+}
+
+// Awaits one read from the server, failing with a Timeout if nothing arrives within `timeout`.
+// Used for streamed responses, where an overall request timeout would cut off long generations.
+pub async fn read_within<T>(timeout: Duration, read: impl Future<Output = T>) -> Result<T, AppError> {
+    tokio::time::timeout(timeout, read)
+        .await
+        .map_err(|_| AppError::Timeout { seconds: timeout.as_secs() })
+}
+
+// Chat APIs have no separate system field, so the system prompt goes in as the first message.
+// The result is trimmed to the context window the request will run with.
+pub fn prepare_chat_messages(messages: Vec<ChatMessage>, params: &GenerationParams) -> Vec<ChatMessage> {
+    let messages = match &params.system {
+        Some(system) => {
+            let mut with_system = vec![ChatMessage::new("system", system)];
+            with_system.extend(messages);
+            with_system
+        }
+        None => messages,
+    };
+    fit_to_context(messages, params.num_ctx())
+}
+
+// Rough token estimate (~4 characters per token plus a little per-message overhead)
+fn estimate_tokens(message: &ChatMessage) -> usize {
+    message.content.chars().count() / 4 + 4
+}
+
+// Drops the oldest turns so the history fits in `num_ctx`, keeping a quarter of the
+// window free for the reply. System messages and the latest turn are always kept. An
+// assistant message that calls tools is kept or dropped together with the tool results
+// that answer it, since servers reject results whose call is missing.
+fn fit_to_context(messages: Vec<ChatMessage>, num_ctx: usize) -> Vec<ChatMessage> {
+    let budget = num_ctx - num_ctx / 4;
+    let (mut fitted, turns): (Vec<ChatMessage>, Vec<ChatMessage>) =
+        messages.into_iter().partition(|m| m.role == "system");
+
+    let mut used: usize = fitted.iter().map(estimate_tokens).sum();
+    let total_turns = turns.len();
+    let mut kept_groups = Vec::new();
+    for group in turn_groups(turns).into_iter().rev() {
+        let cost: usize = group.iter().map(estimate_tokens).sum();
+        if used + cost > budget && !kept_groups.is_empty() {
+            break;
+        }
+        used += cost;
+        kept_groups.push(group);
+    }
+    kept_groups.reverse();
+    let kept: Vec<ChatMessage> = kept_groups.into_iter().flatten().collect();
+
+    let dropped = total_turns - kept.len();
+    if dropped > 0 {
+        fitted.push(ChatMessage::new(
+            "system",
+            &format!("{} earlier messages were omitted to fit the context window.", dropped),
+        ));
+    }
+    fitted.extend(kept);
+    fitted
+}
+
+// Splits messages into the units fit_to_context keeps or drops: an assistant message
+// with tool calls plus the tool messages after it, or any other single message
+fn turn_groups(messages: Vec<ChatMessage>) -> Vec<Vec<ChatMessage>> {
+    let mut groups: Vec<Vec<ChatMessage>> = Vec::new();
+    for message in messages {
+        let answers_call = message.role == "tool"
+            && groups.last().is_some_and(|group| !group[0].tool_calls.is_empty());
+        match groups.last_mut() {
+            Some(group) if answers_call => group.push(message),
+            _ => groups.push(vec![message]),
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ollama::{ToolCall, ToolCallFunction};
+
+    fn message(role: &str, chars: usize) -> ChatMessage {
+        ChatMessage::new(role, &"x".repeat(chars))
+    }
+
+    fn calling(tool: &str, chars: usize) -> ChatMessage {
+        let mut message = message("assistant", chars);
+        message.tool_calls.push(ToolCall {
+            id: None,
+            function: ToolCallFunction {
+                name: tool.to_string(),
+                arguments: serde_json::json!({ "path": "/tmp" }),
+            },
+        });
+        message
+    }
+
+    fn roles(messages: &[ChatMessage]) -> Vec<&str> {
+        messages.iter().map(|m| m.role.as_str()).collect()
+    }
+
+    #[test]
+    fn tool_results_are_dropped_with_their_call() {
+        // Budget is 75 tokens: the last user message (14) fits, the tool result alone (44)
+        // would too, but not together with the call (24)
+        let messages = vec![
+            message("user", 40),
+            calling("list_directory", 80),
+            message("tool", 160),
+            message("user", 40),
+        ];
+        let fitted = fit_to_context(messages, 100);
+        assert_eq!(roles(&fitted), ["system", "user"]);
+        assert!(fitted[0].content.starts_with("3 earlier messages"));
+    }
+
+    #[test]
+    fn tool_call_is_kept_with_all_its_results() {
+        let messages = vec![
+            message("user", 400),
+            calling("read_file", 8),
+            message("tool", 40),
+            message("tool", 40),
+            message("user", 40),
+        ];
+        let fitted = fit_to_context(messages, 100);
+        assert_eq!(roles(&fitted), ["system", "assistant", "tool", "tool", "user"]);
+    }
+
+    #[test]
+    fn latest_turn_is_kept_even_if_too_large() {
+        let messages = vec![message("system", 40), calling("read_file", 8), message("tool", 4000)];
+        let fitted = fit_to_context(messages, 100);
+        assert_eq!(roles(&fitted), ["system", "assistant", "tool"]);
+    }
+}
@@ -1,10 +1,24 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use chrono::{DateTime, Utc, Duration, Datelike, Timelike};
+use std::time::Instant;
+use tokio::sync::{watch, Mutex, Semaphore};
+use chrono::{DateTime, Utc, Duration, Datelike, Timelike, NaiveDateTime, LocalResult, Offset, TimeZone};
+use chrono_tz::Tz;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use crate::cron::CronSchedule;
+use crate::error::AppError;
+use crate::filesystem;
+use crate::permissions::{Access, PermissionPolicy};
+use crate::template::{self, Context, Value};
+use crate::workflow::{StepResult, Workflow};
+use crate::history::{HistoryPage, HistoryQuery, RetentionSettings, RunHistory};
+use crate::watcher::{FileWatcher, WatchSpec};
+use crate::sinks::{self, OutputSink, SinkError, SinkHandles};
+use crate::persist::{self, Schema, StorageWarning};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Task {
@@ -18,15 +32,301 @@ pub struct Task {
     pub last_run: Option<DateTime<Utc>>,
     pub next_run: Option<DateTime<Utc>>,
     pub run_count: u32,
+    // IANA zone the schedule's wall-clock times are in, e.g. "Europe/Berlin"
+    #[serde(default = "system_timezone")]
+    pub timezone: String,
+    // What to do with runs that came due while the app wasn't running
+    #[serde(default)]
+    pub misfire_policy: MisfirePolicy,
+    // A run still going after this many seconds is stopped and recorded as timed out
+    #[serde(default = "default_timeout_seconds")]
+    pub timeout_seconds: u64,
+    // How failed runs are retried before giving up until the next scheduled slot
+    #[serde(default)]
+    pub retry_policy: RetryPolicy,
+    // When set, each run executes these steps instead of sending prompt_template directly.
+    // The rendered prompt_template is available to the steps as `{input}`.
+    #[serde(default)]
+    pub workflow: Option<Workflow>,
+    // The task doesn't run before this time; the first slot after it is its next run
+    #[serde(default)]
+    pub paused_until: Option<DateTime<Utc>>,
+    // Where each run's final result is delivered besides the history
+    #[serde(default)]
+    pub sinks: Vec<OutputSink>,
+}
+
+// Changes to apply to a task. Fields left out keep their current value; `workflow: null`
+// removes the workflow.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TaskUpdate {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub agent_id: Option<String>,
+    #[serde(default)]
+    pub prompt_template: Option<String>,
+    #[serde(default)]
+    pub schedule_type: Option<ScheduleType>,
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub misfire_policy: Option<MisfirePolicy>,
+    #[serde(default)]
+    pub timeout_seconds: Option<u64>,
+    #[serde(default)]
+    pub retry_policy: Option<RetryPolicy>,
+    #[serde(default, deserialize_with = "present")]
+    pub workflow: Option<Option<Workflow>>,
+    #[serde(default)]
+    pub sinks: Option<Vec<OutputSink>>,
+}
+
+// Tells a field set to null (Some(None)) apart from a missing one (None)
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+// tasks.json format history:
+// 0 - bare map of task id to task
+// 1 - versioned envelope
+// 2 - prompts from before templating that don't parse as templates are escaped
+pub const TASKS_SCHEMA: Schema = Schema {
+    migrations: &[tasks_v0_to_v1, tasks_v1_to_v2],
+};
+
+// Tasks saved before time zones were supported ran on UTC wall-clock times; keep them there
+fn tasks_v0_to_v1(mut data: serde_json::Value) -> Result<serde_json::Value, String> {
+    let tasks = data.as_object_mut().ok_or("expected a map of tasks")?;
+    for task in tasks.values_mut() {
+        let task = task.as_object_mut().ok_or("expected each task to be an object")?;
+        task.entry("timezone").or_insert_with(|| "UTC".into());
+    }
+    Ok(data)
+}
+
+// Prompts written before templating can hold braces that aren't template syntax, such as
+// JSON examples, and would fail every run. Those are escaped so they render as the plain
+// text they were, keeping the {date}, {time} and {datetime} placeholders they could use.
+fn tasks_v1_to_v2(mut data: serde_json::Value) -> Result<serde_json::Value, String> {
+    let tasks = data.as_object_mut().ok_or("expected a map of tasks")?;
+    for task in tasks.values_mut() {
+        escape_legacy_prompt(task.get_mut("prompt_template"));
+        let steps = task
+            .get_mut("workflow")
+            .and_then(|workflow| workflow.get_mut("steps"))
+            .and_then(|steps| steps.as_array_mut());
+        for step in steps.into_iter().flatten() {
+            escape_legacy_prompt(step.get_mut("prompt_template"));
+        }
+    }
+    Ok(data)
+}
+
+// Only syntax errors count; a prompt that parses was written for the template engine
+fn escape_legacy_prompt(prompt: Option<&mut serde_json::Value>) {
+    if let Some(serde_json::Value::String(prompt)) = prompt {
+        if template::variable_paths(prompt).is_err() {
+            let mut escaped = prompt.replace('{', "{{").replace('}', "}}");
+            for name in ["date", "time", "datetime"] {
+                escaped = escaped.replace(&format!("{{{{{}}}}}", name), &format!("{{{}}}", name));
+            }
+            *prompt = escaped;
+        }
+    }
+}
+
+pub fn default_timeout_seconds() -> u64 {
+    600
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorClass {
+    Timeout,     // the run hit the task's timeout
+    Unreachable, // the model server couldn't be reached or dropped the connection
+    Ollama,      // the model server answered with an error, e.g. an unknown model
+    Other,
+}
+
+impl ErrorClass {
+    // Class of an error from the model server. Runs stopped by the task's own timeout are
+    // classed as Timeout without going through here.
+    pub fn classify(error: &AppError) -> ErrorClass {
+        match error {
+            AppError::Timeout { .. } => ErrorClass::Timeout,
+            AppError::OllamaUnreachable { .. } | AppError::ProviderUnreachable { .. } => ErrorClass::Unreachable,
+            AppError::ModelNotFound { .. } | AppError::ProviderError { .. } => ErrorClass::Ollama,
+            _ => ErrorClass::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    // Total attempts per run, including the first; 1 disables retries
+    pub max_attempts: u32,
+    // Delay before retry n is backoff_base_seconds * 2^(n-1), capped at backoff_cap_seconds
+    pub backoff_base_seconds: u64,
+    pub backoff_cap_seconds: u64,
+    pub retry_on: Vec<ErrorClass>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            backoff_base_seconds: 30,
+            backoff_cap_seconds: 600,
+            retry_on: vec![ErrorClass::Timeout, ErrorClass::Unreachable],
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_attempts == 0 {
+            return Err("Invalid retry policy: max_attempts must be at least 1".to_string());
+        }
+        if self.backoff_cap_seconds < self.backoff_base_seconds {
+            return Err("Invalid retry policy: backoff_cap_seconds must not be below backoff_base_seconds".to_string());
+        }
+        Ok(())
+    }
+
+    // Delay after failed attempt `attempt` (1-based)
+    pub fn backoff(&self, attempt: u32) -> std::time::Duration {
+        let factor = 1u64.checked_shl(attempt.saturating_sub(1)).unwrap_or(u64::MAX);
+        let seconds = self.backoff_base_seconds.saturating_mul(factor).min(self.backoff_cap_seconds);
+        std::time::Duration::from_secs(seconds)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
+pub enum MisfirePolicy {
+    Skip,                     // record missed runs as skipped, wait for the next slot
+    RunOnce,                  // run once to catch up, record the other missed runs as skipped
+    RunAll { max_runs: u32 }, // run the most recent `max_runs` missed runs, skip the rest
+}
+
+impl Default for MisfirePolicy {
+    fn default() -> Self {
+        MisfirePolicy::RunOnce
+    }
+}
+
+impl MisfirePolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if let MisfirePolicy::RunAll { max_runs: 0 } = self {
+            return Err("Invalid misfire policy: max_runs must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+// A run is missed when it is this late by the time the scheduler sees it
+const MISFIRE_GRACE_SECONDS: i64 = 300;
+
+// Upper bound on missed runs enumerated for one task, e.g. a 1-minute interval after a long shutdown
+const MAX_MISSED_OCCURRENCES: usize = 1000;
+
+// Number of tasks that may talk to the model at the same time
+const MAX_CONCURRENT_RUNS: usize = 4;
+
+// How often scheduled tasks are checked for being due
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+// Reason recorded for due runs that overlap a run still in progress
+const STILL_RUNNING: &str = "because the previous run was still in progress";
+
+pub fn system_timezone() -> String {
+    iana_time_zone::get_timezone()
+        .ok()
+        .filter(|name| name.parse::<Tz>().is_ok())
+        .unwrap_or_else(|| "UTC".to_string())
+}
+
+pub fn parse_timezone(name: &str) -> Result<Tz, String> {
+    name.parse::<Tz>()
+        .map_err(|_| format!("Unknown time zone '{}': expected an IANA name such as 'Europe/Berlin'", name))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
 pub enum ScheduleType {
     Interval { minutes: u32 },
     Hourly { at_minute: u32 },
     Daily { at_hour: u32, at_minute: u32 },
     Weekly { day: u32, at_hour: u32, at_minute: u32 }, // 0=Sunday, 6=Saturday
+    Cron { expr: String }, // standard 5-field cron, e.g. "0 9 * * MON-FRI"
+    // Runs when files under `path` change. `patterns` are globs relative to `path`
+    // (empty matches everything); changes are batched until none arrive for
+    // `debounce_seconds`.
+    FileWatch {
+        path: String,
+        #[serde(default)]
+        patterns: Vec<String>,
+        #[serde(default = "default_true")]
+        recursive: bool,
+        #[serde(default = "default_debounce_seconds")]
+        debounce_seconds: u64,
+    },
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_debounce_seconds() -> u64 {
+    5
+}
+
+impl ScheduleType {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ScheduleType::Interval { minutes } => {
+                if *minutes == 0 {
+                    return Err("Interval must be at least 1 minute".to_string());
+                }
+            }
+            ScheduleType::Hourly { at_minute } => validate_time(0, *at_minute)?,
+            ScheduleType::Daily { at_hour, at_minute } => validate_time(*at_hour, *at_minute)?,
+            ScheduleType::Weekly { day, at_hour, at_minute } => {
+                if *day > 6 {
+                    return Err(format!("Invalid day {}: expected 0 (Sunday) to 6 (Saturday)", day));
+                }
+                validate_time(*at_hour, *at_minute)?;
+            }
+            ScheduleType::Cron { expr } => {
+                CronSchedule::parse(expr)?;
+            }
+            ScheduleType::FileWatch { path, patterns, .. } => {
+                let dir = Path::new(path);
+                if !dir.is_absolute() {
+                    return Err(format!("Invalid watch path '{}': must be absolute", path));
+                }
+                if !dir.is_dir() {
+                    return Err(format!("Invalid watch path '{}': not an existing directory", path));
+                }
+                FileWatcher::validate_patterns(patterns)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn validate_time(hour: u32, minute: u32) -> Result<(), String> {
+    if hour > 23 {
+        return Err(format!("Invalid hour {}: expected 0-23", hour));
+    }
+    if minute > 59 {
+        return Err(format!("Invalid minute {}: expected 0-59", minute));
+    }
+    Ok(())
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,96 +339,311 @@ pub struct TaskResult {
     pub response: String,
     pub success: bool,
     pub error: Option<String>,
+    // Slot this run was scheduled for; differs from executed_at for catch-up runs
+    #[serde(default)]
+    pub scheduled_for: Option<DateTime<Utc>>,
+    // True for missed runs that weren't executed. Skipped entries are neither successes nor
+    // failures: `success` is false and `error` empty, and `skip_reason` says why.
+    #[serde(default)]
+    pub skipped: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip_reason: Option<String>,
+    // Consecutive slots one skipped entry stands for, the first being `scheduled_for`
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub skipped_runs: u32,
+    // Wall time of the executor call, 0 for skipped runs
+    #[serde(default)]
+    pub duration_ms: u64,
+    #[serde(default)]
+    pub timed_out: bool,
+    // Attempts of one scheduled run share a run_id; attempt counts from 1
+    #[serde(default)]
+    pub run_id: String,
+    #[serde(default = "first_attempt")]
+    pub attempt: u32,
+    #[serde(default)]
+    pub error_class: Option<ErrorClass>,
+    // Per-step results for workflow tasks
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<StepResult>,
+    // Output sinks that failed to take this result
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sink_errors: Vec<SinkError>,
+}
+
+fn first_attempt() -> u32 {
+    1
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
 }
 
 pub struct TaskScheduler {
     tasks: Arc<Mutex<HashMap<String, Task>>>,
-    results: Arc<Mutex<Vec<TaskResult>>>,
+    history: Arc<RunHistory>,
     storage_path: PathBuf,
+    // Tasks with a run queued or in progress, with the sender that cancels it
+    running: Arc<std::sync::Mutex<HashMap<String, watch::Sender<bool>>>>,
+    workers: Arc<Semaphore>,
+    // Decides which files templates may include with file()
+    permissions: Arc<PermissionPolicy>,
+    // Change tracking for FileWatch tasks
+    watcher: FileWatcher,
+    sinks: Arc<SinkHandles>,
+    load_warning: Option<StorageWarning>,
 }
 
+// Variables available to task prompt templates
+pub const TEMPLATE_VARIABLES: &[&str] = &["now", "date", "time", "datetime", "task", "previous", "changed_paths"];
+
+// Extra variables available to workflow step templates
+pub const STEP_TEMPLATE_VARIABLES: &[&str] = &["input", "steps"];
+
 impl TaskScheduler {
-    pub fn new(storage_path: PathBuf) -> Self {
-        let scheduler = TaskScheduler {
+    pub fn new(storage_path: PathBuf, history: RunHistory, permissions: Arc<PermissionPolicy>, sinks: SinkHandles) -> Self {
+        let mut scheduler = TaskScheduler {
             tasks: Arc::new(Mutex::new(HashMap::new())),
-            results: Arc::new(Mutex::new(Vec::new())),
+            history: Arc::new(history),
             storage_path,
+            running: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            workers: Arc::new(Semaphore::new(MAX_CONCURRENT_RUNS)),
+            permissions,
+            watcher: FileWatcher::new(),
+            sinks: Arc::new(sinks),
+            load_warning: None,
         };
         
         // Load existing tasks
-        if let Ok(tasks) = scheduler.load_tasks() {
+        let loaded = persist::read_json::<HashMap<String, Task>>(&scheduler.storage_path, &TASKS_SCHEMA);
+        if let Some(tasks) = loaded.value {
+            scheduler.sync_watchers(&tasks);
             *scheduler.tasks.blocking_lock() = tasks;
         }
+        scheduler.load_warning = loaded.warning;
         
         scheduler
     }
 
-    pub async fn add_task(&self, mut task: Task) -> Result<Task, String> {
+    pub async fn add_task(&self, mut task: Task) -> Result<Task, AppError> {
+        self.validate_task(&task)?;
+
         // Calculate next run time
-        task.next_run = Some(self.calculate_next_run(&task.schedule_type));
+        task.next_run = self.calculate_next_run(&task);
         
         let mut tasks = self.tasks.lock().await;
         tasks.insert(task.id.clone(), task.clone());
+        self.sync_watchers(&tasks);
         drop(tasks);
         
         self.save_tasks().await?;
         Ok(task)
     }
 
+    // Edits a task in place, keeping its id, run count and history. The next run is only
+    // recomputed when the schedule or time zone changed.
+    pub async fn update_task(&self, task_id: &str, update: TaskUpdate) -> Result<Task, AppError> {
+        let mut tasks = self.tasks.lock().await;
+        let current = tasks.get(task_id).ok_or_else(|| task_not_found(task_id))?;
+
+        let mut task = current.clone();
+        if let Some(name) = update.name {
+            task.name = name;
+        }
+        if let Some(agent_id) = update.agent_id {
+            task.agent_id = agent_id;
+        }
+        if let Some(prompt_template) = update.prompt_template {
+            task.prompt_template = prompt_template;
+        }
+        if let Some(schedule_type) = update.schedule_type {
+            task.schedule_type = schedule_type;
+        }
+        if let Some(timezone) = update.timezone {
+            task.timezone = timezone;
+        }
+        if let Some(misfire_policy) = update.misfire_policy {
+            task.misfire_policy = misfire_policy;
+        }
+        if let Some(timeout_seconds) = update.timeout_seconds {
+            task.timeout_seconds = timeout_seconds;
+        }
+        if let Some(retry_policy) = update.retry_policy {
+            task.retry_policy = retry_policy;
+        }
+        if let Some(workflow) = update.workflow {
+            task.workflow = workflow;
+        }
+        if let Some(sinks) = update.sinks {
+            task.sinks = sinks;
+        }
+        self.validate_task(&task)?;
+
+        if task.enabled && (task.schedule_type != current.schedule_type || task.timezone != current.timezone) {
+            task.next_run = self.calculate_next_run(&task);
+        }
+
+        tasks.insert(task.id.clone(), task.clone());
+        self.sync_watchers(&tasks);
+        drop(tasks);
+
+        self.save_tasks().await?;
+        Ok(task)
+    }
+
+    // Stops a task from running before `until`; None lifts the pause
+    pub async fn pause_task_until(&self, task_id: &str, until: Option<DateTime<Utc>>) -> Result<Task, AppError> {
+        if let Some(until) = until {
+            if until <= Utc::now() {
+                return Err(AppError::invalid("Pause end must be in the future"));
+            }
+        }
+
+        let mut tasks = self.tasks.lock().await;
+        let task = tasks.get_mut(task_id).ok_or_else(|| task_not_found(task_id))?;
+        task.paused_until = until;
+        if task.enabled {
+            task.next_run = self.calculate_next_run(task);
+        }
+        let updated_task = task.clone();
+        drop(tasks);
+
+        self.save_tasks().await?;
+        Ok(updated_task)
+    }
+
+    // Starts a run right away, outside the schedule, even if the task is disabled or paused.
+    // The next scheduled run is left as it is. Returns the new run's id.
+    pub async fn run_task_now<F, Fut>(&self, task_id: &str, executor: F) -> Result<String, AppError>
+    where
+        F: Fn(String, String, String) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = Result<String, AppError>> + Send + 'static,
+    {
+        let now = Utc::now();
+        let mut tasks = self.tasks.lock().await;
+        let task = tasks.get_mut(task_id).ok_or_else(|| task_not_found(task_id))?;
+        let mut started = task.clone();
+        started.last_run = Some(now);
+        started.run_count += 1;
+        self.spawn_run(started.clone(), vec![now], Vec::new(), executor)?;
+        *task = started;
+        drop(tasks);
+
+        self.save_tasks().await?;
+        Ok(run_id(task_id, now))
+    }
+
+    fn validate_task(&self, task: &Task) -> Result<(), AppError> {
+        task.schedule_type.validate().map_err(AppError::invalid)?;
+        task.misfire_policy.validate().map_err(AppError::invalid)?;
+        task.retry_policy.validate().map_err(AppError::invalid)?;
+        template::validate(&task.prompt_template, TEMPLATE_VARIABLES)
+            .map_err(|e| AppError::invalid(format!("Invalid prompt template: {}", e)))?;
+        if let Some(workflow) = &task.workflow {
+            let step_variables: Vec<&str> = TEMPLATE_VARIABLES.iter().chain(STEP_TEMPLATE_VARIABLES).copied().collect();
+            workflow.validate(&step_variables).map_err(AppError::invalid)?;
+        }
+        parse_timezone(&task.timezone).map_err(AppError::invalid)?;
+        if task.timeout_seconds == 0 {
+            return Err(AppError::invalid("Invalid timeout: timeout_seconds must be at least 1"));
+        }
+        for sink in &task.sinks {
+            sink.validate(&self.sinks.sessions, TEMPLATE_VARIABLES).map_err(AppError::invalid)?;
+        }
+        // The agent must be allowed to read what it watches, since changed paths end up in its prompt
+        if let ScheduleType::FileWatch { path, .. } = &task.schedule_type {
+            self.permissions.check(Some(&task.agent_id), path, Access::Read)?;
+        }
+        Ok(())
+    }
+
     pub async fn get_tasks(&self) -> Vec<Task> {
         let tasks = self.tasks.lock().await;
         tasks.values().cloned().collect()
     }
 
-    pub async fn delete_task(&self, task_id: &str) -> Result<(), String> {
+    pub async fn delete_task(&self, task_id: &str) -> Result<(), AppError> {
+        self.stop_run(task_id);
         let mut tasks = self.tasks.lock().await;
         tasks.remove(task_id);
+        self.sync_watchers(&tasks);
         drop(tasks);
         
         self.save_tasks().await?;
         Ok(())
     }
 
-    pub async fn toggle_task(&self, task_id: &str, enabled: bool) -> Result<Task, String> {
+    pub async fn toggle_task(&self, task_id: &str, enabled: bool) -> Result<Task, AppError> {
         let mut tasks = self.tasks.lock().await;
         
         if let Some(task) = tasks.get_mut(task_id) {
             task.enabled = enabled;
             if enabled {
-                task.next_run = Some(self.calculate_next_run(&task.schedule_type));
+                task.next_run = self.calculate_next_run(task);
             } else {
                 task.next_run = None;
             }
             let updated_task = task.clone();
+            self.sync_watchers(&tasks);
             drop(tasks);
             
             self.save_tasks().await?;
             Ok(updated_task)
         } else {
-            Err("Task not found".to_string())
+            Err(task_not_found(task_id))
         }
     }
 
-    pub async fn get_results(&self, limit: Option<usize>) -> Vec<TaskResult> {
-        let results = self.results.lock().await;
-        let limit = limit.unwrap_or(50);
-        results.iter().rev().take(limit).cloned().collect()
+    pub async fn get_results(&self, query: &HistoryQuery) -> HistoryPage {
+        self.history.query(query)
     }
 
     pub async fn add_result(&self, result: TaskResult) {
-        let mut results = self.results.lock().await;
-        results.push(result);
-        
-        // Keep only last 100 results
-        if results.len() > 100 {
-            let len = results.len();
-            results.drain(0..len - 100);
+        record_result(&self.history, result);
+    }
+
+    pub fn running_task_ids(&self) -> Vec<String> {
+        self.running.lock().unwrap().keys().cloned().collect()
+    }
+
+    // Stops a queued or in-progress run
+    pub async fn cancel_run(&self, task_id: &str) -> Result<(), AppError> {
+        if self.stop_run(task_id) {
+            return Ok(());
+        }
+        if self.tasks.lock().await.contains_key(task_id) {
+            Err(AppError::invalid("Task is not running"))
+        } else {
+            Err(task_not_found(task_id))
         }
     }
 
-    pub async fn check_and_run_tasks<F>(&self, executor: F)
+    // Signals the task's run to stop. Returns false if the task wasn't running.
+    fn stop_run(&self, task_id: &str) -> bool {
+        match self.running.lock().unwrap().get(task_id) {
+            Some(cancel) => {
+                let _ = cancel.send(true);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get_retention(&self) -> RetentionSettings {
+        self.history.get_retention()
+    }
+
+    pub fn update_retention(&self, retention: RetentionSettings) -> Result<(), AppError> {
+        self.history.update_retention(retention)
+    }
+
+    // Hands due tasks to the worker pool and returns without waiting for them to finish.
+    // A task whose previous run is still going is not started again; its due slots are
+    // recorded as skipped.
+    pub async fn check_and_run_tasks<F, Fut>(&self, executor: F)
     where
-        F: Fn(String, String, String) -> Result<String, String>,
+        F: Fn(String, String, String) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = Result<String, AppError>> + Send + 'static,
     {
         let now = Utc::now();
         let mut tasks_to_run = Vec::new();
@@ -136,13 +651,41 @@ impl TaskScheduler {
         {
             let mut tasks = self.tasks.lock().await;
             for task in tasks.values_mut() {
-                if task.enabled {
+                if task.paused_until.map_or(false, |until| until <= now) {
+                    task.paused_until = None;
+                }
+
+                if task.enabled && matches!(task.schedule_type, ScheduleType::FileWatch { .. }) {
+                    // Changes that arrive during a run are picked up once it has finished
+                    if task.paused_until.is_some() {
+                        self.watcher.take_ready(&task.id);
+                    } else if self.running.lock().unwrap().contains_key(&task.id) {
+                        self.watcher.postpone(&task.id);
+                    } else if let Some(paths) = self.watcher.take_ready(&task.id) {
+                        task.last_run = Some(now);
+                        task.run_count += 1;
+                        tasks_to_run.push((task.clone(), vec![now], Vec::new(), "", paths));
+                    }
+                } else if task.enabled {
                     if let Some(next_run) = task.next_run {
                         if next_run <= now {
-                            tasks_to_run.push(task.clone());
-                            task.last_run = Some(now);
-                            task.run_count += 1;
-                            task.next_run = Some(self.calculate_next_run(&task.schedule_type));
+                            let tz = parse_timezone(&task.timezone).unwrap_or(Tz::UTC);
+                            let occurrences = due_occurrences(&task.schedule_type, tz, next_run, now);
+                            let (mut run, mut skip) = plan_catch_up(&task.misfire_policy, &occurrences, now);
+                            let mut skip_reason = "by the misfire policy";
+
+                            if self.running.lock().unwrap().contains_key(&task.id) {
+                                skip.append(&mut run);
+                                skip.sort();
+                                skip_reason = STILL_RUNNING;
+                            }
+
+                            if !run.is_empty() {
+                                task.last_run = Some(now);
+                                task.run_count += run.len() as u32;
+                            }
+                            task.next_run = self.calculate_next_run(task);
+                            tasks_to_run.push((task.clone(), run, skip, skip_reason, Vec::new()));
                         }
                     }
                 }
@@ -153,121 +696,666 @@ impl TaskScheduler {
             return;
         }
         
-        // Execute tasks
-        for task in &tasks_to_run {
-            let prompt = self.render_prompt(&task.prompt_template);
-            match executor(task.agent_id.clone(), prompt.clone(), String::new()) {
-                Ok(response) => {
-                    self.add_result(TaskResult {
-                        task_id: task.id.clone(),
-                        task_name: task.name.clone(),
-                        agent_name: task.agent_id.clone(),
-                        executed_at: now,
-                        prompt,
-                        response,
-                        success: true,
-                        error: None,
-                    }).await;
-                }
-                Err(error) => {
-                    self.add_result(TaskResult {
-                        task_id: task.id.clone(),
-                        task_name: task.name.clone(),
-                        agent_name: task.agent_id.clone(),
-                        executed_at: now,
-                        prompt,
-                        response: String::new(),
-                        success: false,
-                        error: Some(error),
-                    }).await;
-                }
+        for (task, run, mut skip, mut skip_reason, changed_paths) in tasks_to_run {
+            // A manual run may have started since the check above
+            if !run.is_empty() && self.spawn_run(task.clone(), run.clone(), changed_paths, executor.clone()).is_err() {
+                skip.extend(run);
+                skip.sort();
+                skip_reason = STILL_RUNNING;
+            }
+
+            // One entry for all of them, so a long outage doesn't flood the history
+            if let (Some(&first), Some(&last)) = (skip.first(), skip.last()) {
+                let reason = if skip.len() == 1 {
+                    format!("Run scheduled for {} was skipped {}", first.to_rfc3339(), skip_reason)
+                } else {
+                    format!(
+                        "{} runs scheduled from {} to {} were skipped {}",
+                        skip.len(),
+                        first.to_rfc3339(),
+                        last.to_rfc3339(),
+                        skip_reason
+                    )
+                };
+                self.add_result(TaskResult {
+                    task_id: task.id.clone(),
+                    task_name: task.name.clone(),
+                    agent_name: task.agent_id.clone(),
+                    executed_at: now,
+                    prompt: String::new(),
+                    response: String::new(),
+                    success: false,
+                    error: None,
+                    scheduled_for: Some(first),
+                    skipped: true,
+                    skip_reason: Some(reason),
+                    skipped_runs: skip.len() as u32,
+                    duration_ms: 0,
+                    timed_out: false,
+                    run_id: run_id(&task.id, first),
+                    attempt: 1,
+                    error_class: None,
+                    steps: Vec::new(),
+                    sink_errors: Vec::new(),
+                }).await;
             }
         }
         
         let _ = self.save_tasks().await;
     }
 
-    fn calculate_next_run(&self, schedule_type: &ScheduleType) -> DateTime<Utc> {
-        let now = Utc::now();
-        
-        match schedule_type {
-            ScheduleType::Interval { minutes } => {
-                now + Duration::minutes(*minutes as i64)
-            }
-            ScheduleType::Hourly { at_minute } => {
-                let mut next = now;
-                next = next.with_minute(*at_minute).unwrap_or(now);
-                next = next.with_second(0).unwrap_or(now);
-                
-                if next <= now {
-                    next = next + Duration::hours(1);
-                }
-                next
+    // Marks the task as running and hands its runs to the worker pool. Fails without
+    // starting anything if the task is already running.
+    fn spawn_run<F, Fut>(
+        &self,
+        task: Task,
+        runs: Vec<DateTime<Utc>>,
+        changed_paths: Vec<PathBuf>,
+        executor: F,
+    ) -> Result<(), AppError>
+    where
+        F: Fn(String, String, String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String, AppError>> + Send + 'static,
+    {
+        let (cancel, cancelled) = watch::channel(false);
+        match self.running.lock().unwrap().entry(task.id.clone()) {
+            Entry::Occupied(_) => return Err(AppError::invalid("Task is already running")),
+            Entry::Vacant(slot) => {
+                slot.insert(cancel);
             }
-            ScheduleType::Daily { at_hour, at_minute } => {
-                let mut next = now;
-                next = next.with_hour(*at_hour).unwrap_or(now);
-                next = next.with_minute(*at_minute).unwrap_or(now);
-                next = next.with_second(0).unwrap_or(now);
-                
-                if next <= now {
-                    next = next + Duration::days(1);
-                }
-                next
+        }
+
+        let handles = WorkerHandles {
+            workers: self.workers.clone(),
+            running: self.running.clone(),
+            history: self.history.clone(),
+            permissions: self.permissions.clone(),
+            sinks: self.sinks.clone(),
+        };
+        tokio::spawn(run_task(task, runs, changed_paths, executor, cancelled, handles));
+        Ok(())
+    }
+
+    // Sleeps until the next regular check or until a file-watch batch is ready,
+    // whichever comes first
+    pub async fn wait_for_next_check(&self) {
+        let tick = tokio::time::Instant::now() + CHECK_INTERVAL;
+        loop {
+            let deadline = match self.watcher.next_deadline() {
+                Some(ready) => tick.min(tokio::time::Instant::from_std(ready)),
+                None => tick,
+            };
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => return,
+                // A new change may move the deadline, so recompute it
+                _ = self.watcher.changed() => {}
             }
-            ScheduleType::Weekly { day, at_hour, at_minute } => {
-                let mut next = now;
-                next = next.with_hour(*at_hour).unwrap_or(now);
-                next = next.with_minute(*at_minute).unwrap_or(now);
-                next = next.with_second(0).unwrap_or(now);
-                
-                let current_day = next.weekday().num_days_from_sunday();
-                let days_until_target = if *day >= current_day {
-                    day - current_day
-                } else {
-                    7 - (current_day - day)
-                };
-                
-                next = next + Duration::days(days_until_target as i64);
-                
-                if next <= now {
-                    next = next + Duration::days(7);
+        }
+    }
+
+    // Starts watching for enabled FileWatch tasks and stops watching for the rest
+    fn sync_watchers(&self, tasks: &HashMap<String, Task>) {
+        let specs = tasks
+            .values()
+            .filter(|task| task.enabled)
+            .filter_map(|task| match &task.schedule_type {
+                ScheduleType::FileWatch { path, patterns, recursive, debounce_seconds } => {
+                    let path = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+                    Some((task.id.clone(), WatchSpec {
+                        path,
+                        patterns: patterns.clone(),
+                        recursive: *recursive,
+                        debounce: std::time::Duration::from_secs(*debounce_seconds),
+                    }))
                 }
-                next
-            }
+                _ => None,
+            })
+            .collect();
+        if let Err(e) = self.watcher.sync(specs) {
+            eprintln!("{}", e);
         }
     }
 
-    fn render_prompt(&self, template: &str) -> String {
-        let now = Utc::now();
-        template
-            .replace("{date}", &now.format("%Y-%m-%d").to_string())
-            .replace("{time}", &now.format("%H:%M:%S").to_string())
-            .replace("{datetime}", &now.format("%Y-%m-%d %H:%M:%S").to_string())
+    // First slot after now, or after the end of the task's pause
+    fn calculate_next_run(&self, task: &Task) -> Option<DateTime<Utc>> {
+        let tz = parse_timezone(&task.timezone).unwrap_or(Tz::UTC);
+        let from = task.paused_until.map_or(Utc::now(), |until| until.max(Utc::now()));
+        next_run_after(&task.schedule_type, tz, from)
     }
 
-    async fn save_tasks(&self) -> Result<(), String> {
+    async fn save_tasks(&self) -> Result<(), AppError> {
         let tasks = self.tasks.lock().await;
-        let json = serde_json::to_string_pretty(&*tasks)
-            .map_err(|e| format!("Failed to serialize tasks: {}", e))?;
+        let json = persist::to_versioned_json(&TASKS_SCHEMA, &*tasks)
+            .map_err(|e| AppError::internal(format!("Failed to serialize tasks: {}", e)))?;
         
-        fs::write(&self.storage_path, json)
-            .map_err(|e| format!("Failed to write tasks file: {}", e))?;
+        persist::write_with_backup(&self.storage_path, &json)
+            .map_err(|e| AppError::io(self.storage_path.display().to_string(), e))?;
         
         Ok(())
     }
 
-    fn load_tasks(&self) -> Result<HashMap<String, Task>, String> {
-        if !self.storage_path.exists() {
-            return Ok(HashMap::new());
+    // Problem found with the tasks file at startup, if any
+    pub fn load_warning(&self) -> Option<StorageWarning> {
+        self.load_warning.clone()
+    }
+}
+
+fn task_not_found(task_id: &str) -> AppError {
+    AppError::TaskNotFound { task_id: task_id.to_string() }
+}
+
+// Template variables for one run. Catch-up runs see the date and time of the slot they
+// stand in for; `run_count` counts this run. `changed_paths` is empty unless a file
+// change triggered the run.
+fn template_context(
+    task: &Task,
+    scheduled_for: DateTime<Utc>,
+    run_count: u32,
+    changed_paths: &[PathBuf],
+    handles: &WorkerHandles,
+) -> Context {
+    let tz = parse_timezone(&task.timezone).unwrap_or(Tz::UTC);
+    let at = scheduled_for.with_timezone(&tz);
+    let previous = handles.history.last_completed(&task.id);
+
+    let mut context = Context::new();
+    context.set("now", Value::date(at, "%Y-%m-%d %H:%M:%S"));
+    context.set("datetime", Value::date(at, "%Y-%m-%d %H:%M:%S"));
+    context.set("date", Value::date(at, "%Y-%m-%d"));
+    context.set("time", Value::date(at, "%H:%M:%S"));
+    context.set(
+        "task",
+        Value::map(vec![
+            ("id", Value::Str(task.id.clone())),
+            ("name", Value::Str(task.name.clone())),
+            ("agent_id", Value::Str(task.agent_id.clone())),
+            ("run_count", Value::Int(run_count as i64)),
+            (
+                "last_run",
+                previous.as_ref().map_or(Value::Null, |p| {
+                    Value::date(p.executed_at.with_timezone(&tz), "%Y-%m-%d %H:%M:%S")
+                }),
+            ),
+            ("timezone", Value::Str(task.timezone.clone())),
+        ]),
+    );
+    context.set(
+        "previous",
+        previous.map_or(Value::Null, |p| {
+            Value::map(vec![
+                ("output", Value::Str(p.response)),
+                ("success", Value::Bool(p.success)),
+                ("error", p.error.map_or(Value::Null, Value::Str)),
+                ("executed_at", Value::date(p.executed_at.with_timezone(&tz), "%Y-%m-%d %H:%M:%S")),
+            ])
+        }),
+    );
+
+    context.set(
+        "changed_paths",
+        Value::List(
+            changed_paths
+                .iter()
+                .map(|path| Value::Str(path.to_string_lossy().to_string()))
+                .collect(),
+        ),
+    );
+
+    // file() reads on behalf of the task's agent, so the same permission rules apply
+    let permissions = handles.permissions.clone();
+    let agent_id = task.agent_id.clone();
+    context.with_file_reader(Arc::new(move |path: &str| {
+        filesystem::read_file(&permissions, Some(&agent_id), path).map_err(|e| e.to_string())
+    }))
+}
+
+// Delivers a run's final result to the task's sinks, then records it with any sink failures
+async fn finish_run(task: &Task, mut result: TaskResult, context: &Context, handles: &WorkerHandles) {
+    result.sink_errors = sinks::deliver(&task.sinks, &result, &task.agent_id, context, &handles.sinks).await;
+    for failure in &result.sink_errors {
+        eprintln!("Task {}: {} failed: {}", task.id, failure.sink, failure.error);
+    }
+    record_result(&handles.history, result);
+}
+
+fn record_result(history: &RunHistory, result: TaskResult) {
+    if let Err(e) = history.append(result) {
+        eprintln!("Failed to record task result: {}", e);
+    }
+}
+
+// One attempt of a run: the task's prompt, or its workflow if it has one.
+// Returns the response, the error and its class if the run failed, and per-step results.
+async fn execute_run<F, Fut>(
+    task: &Task,
+    prompt: &str,
+    context: &Context,
+    executor: &F,
+) -> (String, Option<(String, ErrorClass)>, Vec<StepResult>)
+where
+    F: Fn(String, String, String) -> Fut,
+    Fut: Future<Output = Result<String, AppError>>,
+{
+    match &task.workflow {
+        None => match executor(task.agent_id.clone(), prompt.to_string(), String::new()).await {
+            Ok(response) => (response, None, Vec::new()),
+            Err(error) => (String::new(), Some((error.to_string(), ErrorClass::classify(&error))), Vec::new()),
+        },
+        Some(workflow) => {
+            let mut context = context.clone();
+            context.set("input", Value::Str(prompt.to_string()));
+            let run = workflow
+                .run(&task.agent_id, &context, |agent_id, step_prompt| {
+                    executor(agent_id, step_prompt, String::new())
+                })
+                .await;
+            let class = run.cause.as_ref().map_or(ErrorClass::Other, ErrorClass::classify);
+            (run.response, run.error.map(|error| (error, class)), run.steps)
         }
-        
-        let contents = fs::read_to_string(&self.storage_path)
-            .map_err(|e| format!("Failed to read tasks file: {}", e))?;
-        
-        let tasks: HashMap<String, Task> = serde_json::from_str(&contents)
-            .map_err(|e| format!("Failed to parse tasks file: {}", e))?;
-        
-        Ok(tasks)
+    }
+}
+
+fn run_id(task_id: &str, scheduled_for: DateTime<Utc>) -> String {
+    format!("{}_{}", task_id, scheduled_for.timestamp_millis())
+}
+
+// Shared scheduler state a worker job needs
+struct WorkerHandles {
+    workers: Arc<Semaphore>,
+    running: Arc<std::sync::Mutex<HashMap<String, watch::Sender<bool>>>>,
+    history: Arc<RunHistory>,
+    permissions: Arc<PermissionPolicy>,
+    sinks: Arc<SinkHandles>,
+}
+
+// Worker job for one due task: runs each slot in order under the task's timeout, retrying
+// failures per its retry policy. A worker is held only while an attempt executes, not
+// during backoff. Cancelling stops the current attempt and drops the rest.
+async fn run_task<F, Fut>(
+    task: Task,
+    runs: Vec<DateTime<Utc>>,
+    changed_paths: Vec<PathBuf>,
+    executor: F,
+    mut cancelled: watch::Receiver<bool>,
+    handles: WorkerHandles,
+) where
+    F: Fn(String, String, String) -> Fut,
+    Fut: Future<Output = Result<String, AppError>>,
+{
+    let timeout = std::time::Duration::from_secs(task.timeout_seconds);
+    let policy = &task.retry_policy;
+
+    let first_run_count = task.run_count + 1 - runs.len() as u32;
+
+    for (index, scheduled_for) in runs.into_iter().enumerate() {
+        let run_id = run_id(&task.id, scheduled_for);
+        let mut attempt = 1;
+
+        let context = template_context(&task, scheduled_for, first_run_count + index as u32, &changed_paths, &handles);
+        let prompt = match template::render(&task.prompt_template, &context) {
+            Ok(prompt) => prompt,
+            Err(e) => {
+                let result = TaskResult {
+                    task_id: task.id.clone(),
+                    task_name: task.name.clone(),
+                    agent_name: task.agent_id.clone(),
+                    executed_at: Utc::now(),
+                    prompt: task.prompt_template.clone(),
+                    response: String::new(),
+                    success: false,
+                    error: Some(format!("Failed to render prompt template: {}", e)),
+                    scheduled_for: Some(scheduled_for),
+                    skipped: false,
+                    skip_reason: None,
+                    skipped_runs: 0,
+                    duration_ms: 0,
+                    timed_out: false,
+                    run_id,
+                    attempt,
+                    error_class: Some(ErrorClass::Other),
+                    steps: Vec::new(),
+                    sink_errors: Vec::new(),
+                };
+                finish_run(&task, result, &context, &handles).await;
+                continue;
+            }
+        };
+
+        loop {
+            let permit = tokio::select! {
+                permit = handles.workers.clone().acquire_owned() => permit.ok(),
+                _ = cancelled.wait_for(|c| *c) => None,
+            };
+
+            let started = Instant::now();
+            let executed_at = Utc::now();
+
+            let cancelled_error = |message: &str| Some((message.to_string(), ErrorClass::Other));
+            let (response, error, steps, timed_out) = if permit.is_none() || *cancelled.borrow() {
+                (String::new(), cancelled_error("Run was cancelled before it started"), Vec::new(), false)
+            } else {
+                tokio::select! {
+                    result = tokio::time::timeout(timeout, execute_run(&task, &prompt, &context, &executor)) => {
+                        match result {
+                            Ok((response, error, steps)) => (response, error, steps, false),
+                            Err(_) => (
+                                String::new(),
+                                Some((format!("Run timed out after {} seconds", task.timeout_seconds), ErrorClass::Timeout)),
+                                Vec::new(),
+                                true,
+                            ),
+                        }
+                    }
+                    _ = cancelled.wait_for(|c| *c) => (String::new(), cancelled_error("Run was cancelled"), Vec::new(), false),
+                }
+            };
+            drop(permit);
+
+            let (error, error_class) = error.unzip();
+            let retry = !*cancelled.borrow()
+                && attempt < policy.max_attempts
+                && error_class.map_or(false, |class| policy.retry_on.contains(&class));
+
+            let result = TaskResult {
+                task_id: task.id.clone(),
+                task_name: task.name.clone(),
+                agent_name: task.agent_id.clone(),
+                executed_at,
+                prompt: prompt.clone(),
+                response,
+                success: error.is_none(),
+                error,
+                scheduled_for: Some(scheduled_for),
+                skipped: false,
+                skip_reason: None,
+                skipped_runs: 0,
+                duration_ms: started.elapsed().as_millis() as u64,
+                timed_out,
+                run_id: run_id.clone(),
+                attempt,
+                error_class,
+                steps,
+                sink_errors: Vec::new(),
+            };
+
+            if !retry {
+                // Cancelled runs aren't delivered anywhere but the history
+                if *cancelled.borrow() {
+                    record_result(&handles.history, result);
+                } else {
+                    finish_run(&task, result, &context, &handles).await;
+                }
+                break;
+            }
+            record_result(&handles.history, result);
+
+            tokio::select! {
+                _ = tokio::time::sleep(policy.backoff(attempt)) => {}
+                _ = cancelled.wait_for(|c| *c) => break,
+            }
+            attempt += 1;
+        }
+    }
+
+    handles.running.lock().unwrap().remove(&task.id);
+}
+
+// Next fire time strictly after `now`. Interval schedules count elapsed time; all other
+// schedules are wall-clock times in `tz`:
+// - a wall time skipped by a spring-forward gap fires the same distance past the
+//   transition (02:30 during a 02:00->03:00 jump fires at 03:30)
+// - a wall time repeated by a fall-back overlap fires once, at its first occurrence
+pub fn next_run_after(schedule_type: &ScheduleType, tz: Tz, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    if let ScheduleType::Interval { minutes } = schedule_type {
+        return Some(now + Duration::minutes(*minutes as i64));
+    }
+
+    let cron = match schedule_type {
+        ScheduleType::Cron { expr } => Some(CronSchedule::parse(expr).ok()?),
+        _ => None,
+    };
+
+    let mut cursor = now.with_timezone(&tz).naive_local();
+    // A few iterations are enough to step past occurrences that resolve to the past
+    for _ in 0..8 {
+        let candidate = match (schedule_type, &cron) {
+            (_, Some(cron)) => cron.next_after(cursor)?,
+            (ScheduleType::Hourly { at_minute }, _) => {
+                let next = cursor.with_minute(*at_minute)?.with_second(0)?.with_nanosecond(0)?;
+                if next <= cursor { next + Duration::hours(1) } else { next }
+            }
+            (ScheduleType::Daily { at_hour, at_minute }, _) => {
+                let next = cursor.date().and_hms_opt(*at_hour, *at_minute, 0)?;
+                if next <= cursor { next + Duration::days(1) } else { next }
+            }
+            (ScheduleType::Weekly { day, at_hour, at_minute }, _) => {
+                let current_day = cursor.weekday().num_days_from_sunday();
+                let days_until_target = (day + 7 - current_day) % 7;
+                let next = cursor.date().and_hms_opt(*at_hour, *at_minute, 0)?
+                    + Duration::days(days_until_target as i64);
+                if next <= cursor { next + Duration::days(7) } else { next }
+            }
+            _ => return None,
+        };
+
+        let instant = resolve_local(tz, candidate)?;
+        if instant > now {
+            return Some(instant);
+        }
+        cursor = candidate;
+    }
+    None
+}
+
+// Fire times from `first` up to and including `now`, oldest first. Only the most recent
+// MAX_MISSED_OCCURRENCES are returned.
+pub fn due_occurrences(schedule_type: &ScheduleType, tz: Tz, first: DateTime<Utc>, now: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+    let mut first = first;
+    if let ScheduleType::Interval { minutes } = schedule_type {
+        // Jump straight to the window we keep instead of stepping through every interval
+        let step = Duration::minutes((*minutes).max(1) as i64);
+        let steps = (now - first).num_minutes() / step.num_minutes();
+        let excess = steps - MAX_MISSED_OCCURRENCES as i64;
+        if excess > 0 {
+            first = first + step * excess as i32;
+        }
+    }
+
+    let mut occurrences = VecDeque::new();
+    let mut next = Some(first);
+    while let Some(at) = next {
+        if at > now {
+            break;
+        }
+        occurrences.push_back(at);
+        if occurrences.len() > MAX_MISSED_OCCURRENCES {
+            occurrences.pop_front();
+        }
+        next = next_run_after(schedule_type, tz, at);
+    }
+    occurrences.into()
+}
+
+// Splits due fire times into those to execute and those to record as skipped.
+// Occurrences within the grace period are on time and always run.
+fn plan_catch_up(
+    policy: &MisfirePolicy,
+    occurrences: &[DateTime<Utc>],
+    now: DateTime<Utc>,
+) -> (Vec<DateTime<Utc>>, Vec<DateTime<Utc>>) {
+    let grace_start = now - Duration::seconds(MISFIRE_GRACE_SECONDS);
+    let (missed, on_time): (Vec<DateTime<Utc>>, Vec<DateTime<Utc>>) =
+        occurrences.iter().partition(|at| **at < grace_start);
+
+    if missed.is_empty() {
+        return (on_time, Vec::new());
+    }
+
+    match policy {
+        MisfirePolicy::Skip => (on_time, missed),
+        MisfirePolicy::RunOnce => {
+            // An on-time run already catches up; otherwise run for the latest missed slot
+            if on_time.is_empty() {
+                let (latest, rest) = missed.split_last().unwrap();
+                (vec![*latest], rest.to_vec())
+            } else {
+                (on_time, missed)
+            }
+        }
+        MisfirePolicy::RunAll { max_runs } => {
+            let keep = (*max_runs as usize).saturating_sub(on_time.len());
+            let split = missed.len().saturating_sub(keep);
+            let mut run = missed[split..].to_vec();
+            run.extend(on_time);
+            (run, missed[..split].to_vec())
+        }
+    }
+}
+
+// The instant a wall-clock time in `tz` stands for: the first one of a repeated time, and
+// the time as far past a gap as it was into it
+pub fn resolve_local(tz: Tz, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(dt) => Some(dt.with_timezone(&Utc)),
+        LocalResult::Ambiguous(earliest, _) => Some(earliest.with_timezone(&Utc)),
+        LocalResult::None => {
+            // Interpret the skipped time with the offset in effect before the gap
+            let before_gap = tz.from_local_datetime(&(local - Duration::hours(3))).earliest()?;
+            let offset = before_gap.offset().fix().local_minus_utc() as i64;
+            Some(Utc.from_utc_datetime(&(local - Duration::seconds(offset))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // America/New_York in 2024: clocks jump 02:00 -> 03:00 on March 10
+    // and fall back 02:00 -> 01:00 on November 3
+    const NEW_YORK: Tz = chrono_tz::America::New_York;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    fn local(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn resolve_local_moves_skipped_time_past_the_gap() {
+        // 02:30 does not exist; it is read with the EST offset and lands on 03:30 EDT
+        assert_eq!(resolve_local(NEW_YORK, local("2024-03-10 02:30")), Some(utc("2024-03-10T07:30:00Z")));
+        assert_eq!(resolve_local(NEW_YORK, local("2024-03-10 03:30")), Some(utc("2024-03-10T07:30:00Z")));
+    }
+
+    #[test]
+    fn resolve_local_picks_first_of_repeated_time() {
+        // 01:30 happens at 05:30 UTC (EDT) and again at 06:30 UTC (EST)
+        assert_eq!(resolve_local(NEW_YORK, local("2024-11-03 01:30")), Some(utc("2024-11-03T05:30:00Z")));
+    }
+
+    #[test]
+    fn daily_run_in_spring_forward_gap_fires_after_the_jump() {
+        let schedule = ScheduleType::Daily { at_hour: 2, at_minute: 30 };
+
+        let first = next_run_after(&schedule, NEW_YORK, utc("2024-03-10T05:00:00Z")).unwrap();
+        assert_eq!(first, utc("2024-03-10T07:30:00Z"));
+
+        // The day after, 02:30 exists again (EDT)
+        let second = next_run_after(&schedule, NEW_YORK, first).unwrap();
+        assert_eq!(second, utc("2024-03-11T06:30:00Z"));
+    }
+
+    #[test]
+    fn daily_run_in_fall_back_overlap_fires_once() {
+        let schedule = ScheduleType::Daily { at_hour: 1, at_minute: 30 };
+
+        let first = next_run_after(&schedule, NEW_YORK, utc("2024-11-03T05:00:00Z")).unwrap();
+        assert_eq!(first, utc("2024-11-03T05:30:00Z"));
+
+        // Neither the first run nor the repeated 01:00-02:00 hour fires it again that day
+        let expected = utc("2024-11-04T06:30:00Z");
+        assert_eq!(next_run_after(&schedule, NEW_YORK, first).unwrap(), expected);
+        assert_eq!(next_run_after(&schedule, NEW_YORK, utc("2024-11-03T06:00:00Z")).unwrap(), expected);
+    }
+
+    #[test]
+    fn due_occurrences_across_transitions() {
+        let schedule = ScheduleType::Hourly { at_minute: 30 };
+
+        // 00:30 EST, then 01:30 EST, then 03:30 EDT: the skipped 02:30 lands on 03:30
+        // and is not counted twice
+        let spring = due_occurrences(
+            &schedule,
+            NEW_YORK,
+            utc("2024-03-10T05:30:00Z"),
+            utc("2024-03-10T08:00:00Z"),
+        );
+        assert_eq!(spring, vec![utc("2024-03-10T05:30:00Z"), utc("2024-03-10T06:30:00Z"), utc("2024-03-10T07:30:00Z")]);
+
+        // 00:30 EDT, 01:30 EDT, then 02:30 EST: the repeated 01:30 EST does not fire
+        let fall = due_occurrences(
+            &schedule,
+            NEW_YORK,
+            utc("2024-11-03T04:30:00Z"),
+            utc("2024-11-03T07:45:00Z"),
+        );
+        assert_eq!(fall, vec![utc("2024-11-03T04:30:00Z"), utc("2024-11-03T05:30:00Z"), utc("2024-11-03T07:30:00Z")]);
+    }
+
+    #[test]
+    fn skipped_slots_are_recorded_as_one_entry_that_is_not_a_failure() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let (_settings_dir, settings) = crate::testing::settings(crate::settings::AppSettings::default());
+        let permissions = Arc::new(PermissionPolicy::new(dir.path().join("permissions.json")));
+        let sinks = SinkHandles {
+            permissions: permissions.clone(),
+            sessions: Arc::new(crate::session::SessionManager::new(dir.path().join("sessions"))),
+            settings: Arc::new(settings),
+            notifier: Arc::new(|_: &str, _: &str| Ok(())),
+        };
+        let history = RunHistory::new(dir.path().join("history.jsonl"), dir.path().join("retention.json"));
+        let scheduler = TaskScheduler::new(dir.path().join("tasks.json"), history, permissions, sinks);
+
+        // Two days of hourly slots went by while the app was closed
+        let task: Task = serde_json::from_value(serde_json::json!({
+            "id": "task_1",
+            "name": "Hourly",
+            "agent_id": "agent",
+            "prompt_template": "hello",
+            "schedule_type": { "type": "Hourly", "at_minute": 30 },
+            "enabled": true,
+            "created_at": Utc::now() - Duration::days(3),
+            "last_run": null,
+            "next_run": Utc::now() - Duration::days(2),
+            "run_count": 0,
+            "timezone": "UTC",
+            "misfire_policy": { "type": "Skip" },
+        }))
+        .unwrap();
+
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            scheduler.tasks.lock().await.insert(task.id.clone(), task);
+            scheduler
+                .check_and_run_tasks(|_, _, _| async { Ok::<_, AppError>("done".to_string()) })
+                .await;
+
+            let query = |success, skipped| HistoryQuery {
+                task_id: Some("task_1".to_string()),
+                success,
+                skipped,
+                ..HistoryQuery::default()
+            };
+            let skipped = scheduler.get_results(&query(None, Some(true))).await;
+            assert_eq!(skipped.total, 1);
+            let entry = &skipped.results[0];
+            assert!(entry.skipped_runs >= 47, "covers {} slots", entry.skipped_runs);
+            assert!(entry.error.is_none());
+            assert!(entry.skip_reason.as_deref().unwrap().contains("were skipped by the misfire policy"));
+
+            assert_eq!(scheduler.get_results(&query(Some(false), None)).await.total, 0);
+        });
     }
 }
@@ -1,7 +1,16 @@
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
 use chrono::{DateTime, Utc};
+use crate::error::AppError;
+use crate::filesystem;
+use crate::permissions::PermissionPolicy;
+use crate::persist::{self, Schema, StorageWarning};
+
+// Numbers messages, since one reply can record several messages with the same role in the same millisecond
+static NEXT_MESSAGE: AtomicU64 = AtomicU64::new(1);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionMessage {
@@ -12,6 +21,30 @@ pub struct SessionMessage {
     pub agent_id: Option<String>,
 }
 
+impl SessionMessage {
+    pub fn new(role: &str, content: &str, agent_id: Option<String>) -> Self {
+        SessionMessage {
+            id: format!(
+                "msg_{}_{}_{}",
+                Utc::now().timestamp_millis(),
+                role,
+                NEXT_MESSAGE.fetch_add(1, Ordering::Relaxed)
+            ),
+            role: role.to_string(),
+            content: content.to_string(),
+            timestamp: Utc::now(),
+            agent_id,
+        }
+    }
+}
+
+// Session file format history, also used for exported sessions:
+// 0 - bare session object
+// 1 - versioned envelope
+pub const SESSION_SCHEMA: Schema = Schema {
+    migrations: &[persist::add_envelope],
+};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
     pub id: String,
@@ -35,62 +68,96 @@ pub struct SessionMetadata {
 
 pub struct SessionManager {
     sessions_dir: PathBuf,
+    // Held while a session file is written, so a read-modify-write isn't interleaved with
+    // another write to the same session
+    write_lock: Mutex<()>,
 }
 
 impl SessionManager {
     pub fn new(sessions_dir: PathBuf) -> Self {
         fs::create_dir_all(&sessions_dir).ok();
-        SessionManager { sessions_dir }
+        SessionManager {
+            sessions_dir,
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    // Session ids become file names, so only letters, digits, `_` and `-` are accepted
+    fn session_path(&self, session_id: &str) -> Result<PathBuf, AppError> {
+        let valid = !session_id.is_empty()
+            && session_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid {
+            return Err(AppError::invalid(format!(
+                "Invalid session id '{}': only letters, digits, '_' and '-' are allowed",
+                session_id
+            )));
+        }
+        Ok(self.sessions_dir.join(format!("{}.json", session_id)))
+    }
+
+    pub fn save_session(&self, session: Session) -> Result<(), AppError> {
+        let _guard = self.write_lock.lock().unwrap();
+        self.write_session(&session)
     }
 
-    pub fn save_session(&self, session: Session) -> Result<(), String> {
-        let file_path = self.sessions_dir.join(format!("{}.json", session.id));
-        let json = serde_json::to_string_pretty(&session)
-            .map_err(|e| format!("Failed to serialize session: {}", e))?;
+    fn write_session(&self, session: &Session) -> Result<(), AppError> {
+        let file_path = self.session_path(&session.id)?;
+        let json = persist::to_versioned_json(&SESSION_SCHEMA, session)
+            .map_err(|e| AppError::internal(format!("Failed to serialize session: {}", e)))?;
         
-        fs::write(&file_path, json)
-            .map_err(|e| format!("Failed to write session file: {}", e))?;
+        persist::write_with_backup(&file_path, &json)
+            .map_err(|e| AppError::io(file_path.display().to_string(), e))?;
         
         Ok(())
     }
 
-    pub fn load_session(&self, session_id: &str) -> Result<Session, String> {
-        let file_path = self.sessions_dir.join(format!("{}.json", session_id));
+    pub fn load_session(&self, session_id: &str) -> Result<Session, AppError> {
+        let file_path = self.session_path(session_id)?;
         
         if !file_path.exists() {
-            return Err(format!("Session '{}' not found", session_id));
+            return Err(AppError::SessionNotFound { session_id: session_id.to_string() });
         }
 
-        let contents = fs::read_to_string(&file_path)
-            .map_err(|e| format!("Failed to read session file: {}", e))?;
-        
-        let session: Session = serde_json::from_str(&contents)
-            .map_err(|e| format!("Failed to parse session file: {}", e))?;
-        
+        let loaded = persist::read_json::<Session>(&file_path, &SESSION_SCHEMA);
+        if let Some(warning) = &loaded.warning {
+            eprintln!("{}", warning.message);
+        }
+        loaded.value.ok_or_else(|| AppError::IoError {
+            path: file_path.display().to_string(),
+            message: format!("Session '{}' is corrupt and has no usable backup", session_id),
+        })
+    }
+
+    pub fn append_messages(&self, session_id: &str, messages: Vec<SessionMessage>) -> Result<Session, AppError> {
+        let _guard = self.write_lock.lock().unwrap();
+        let mut session = self.load_session(session_id)?;
+        session.messages.extend(messages);
+        session.message_count = session.messages.len();
+        session.updated_at = Utc::now();
+
+        self.write_session(&session)?;
         Ok(session)
     }
 
-    pub fn list_sessions(&self) -> Result<Vec<SessionMetadata>, String> {
+    pub fn list_sessions(&self) -> Result<Vec<SessionMetadata>, AppError> {
         let mut sessions = Vec::new();
 
         let entries = fs::read_dir(&self.sessions_dir)
-            .map_err(|e| format!("Failed to read sessions directory: {}", e))?;
+            .map_err(|e| AppError::io(self.sessions_dir.display().to_string(), e))?;
 
         for entry in entries {
             if let Ok(entry) = entry {
                 let path = entry.path();
                 if path.extension().and_then(|s| s.to_str()) == Some("json") {
-                    if let Ok(contents) = fs::read_to_string(&path) {
-                        if let Ok(session) = serde_json::from_str::<Session>(&contents) {
-                            sessions.push(SessionMetadata {
-                                id: session.id,
-                                name: session.name,
-                                created_at: session.created_at,
-                                updated_at: session.updated_at,
-                                message_count: session.message_count,
-                                agent_id: session.agent_id,
-                            });
-                        }
+                    if let Some(session) = persist::read_json::<Session>(&path, &SESSION_SCHEMA).value {
+                        sessions.push(SessionMetadata {
+                            id: session.id,
+                            name: session.name,
+                            created_at: session.created_at,
+                            updated_at: session.updated_at,
+                            message_count: session.message_count,
+                            agent_id: session.agent_id,
+                        });
                     }
                 }
             }
@@ -102,37 +169,55 @@ impl SessionManager {
         Ok(sessions)
     }
 
-    pub fn delete_session(&self, session_id: &str) -> Result<(), String> {
-        let file_path = self.sessions_dir.join(format!("{}.json", session_id));
+    // Reads every session file, restoring damaged ones from their backups, and reports
+    // what was found
+    pub fn check_sessions(&self) -> Vec<StorageWarning> {
+        let entries = match fs::read_dir(&self.sessions_dir) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        // Collect the paths first: recovering a file renames entries in this directory
+        let paths: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("json"))
+            .collect();
+        paths
+            .iter()
+            .filter_map(|path| persist::read_json::<Session>(path, &SESSION_SCHEMA).warning)
+            .collect()
+    }
+
+    pub fn delete_session(&self, session_id: &str) -> Result<(), AppError> {
+        let file_path = self.session_path(session_id)?;
+        let _guard = self.write_lock.lock().unwrap();
         
         if !file_path.exists() {
-            return Err(format!("Session '{}' not found", session_id));
+            return Err(AppError::SessionNotFound { session_id: session_id.to_string() });
         }
 
-        fs::remove_file(&file_path)
-            .map_err(|e| format!("Failed to delete session file: {}", e))?;
+        persist::remove_with_backup(&file_path)
+            .map_err(|e| AppError::io(file_path.display().to_string(), e))?;
         
         Ok(())
     }
 
-    pub fn export_session(&self, session_id: &str, export_path: &str) -> Result<(), String> {
+    // Export and import paths are outside the sessions directory, so they go through the
+    // permission policy like any other file access
+    pub fn export_session(&self, policy: &PermissionPolicy, session_id: &str, export_path: &str) -> Result<(), AppError> {
         let session = self.load_session(session_id)?;
         
-        let json = serde_json::to_string_pretty(&session)
-            .map_err(|e| format!("Failed to serialize session: {}", e))?;
+        let json = persist::to_versioned_json(&SESSION_SCHEMA, &session)
+            .map_err(|e| AppError::internal(format!("Failed to serialize session: {}", e)))?;
         
-        fs::write(export_path, json)
-            .map_err(|e| format!("Failed to write export file: {}", e))?;
-        
-        Ok(())
+        filesystem::write_file(policy, None, export_path, &json)
     }
 
-    pub fn import_session(&self, import_path: &str) -> Result<Session, String> {
-        let contents = fs::read_to_string(import_path)
-            .map_err(|e| format!("Failed to read import file: {}", e))?;
+    pub fn import_session(&self, policy: &PermissionPolicy, import_path: &str) -> Result<Session, AppError> {
+        let contents = filesystem::read_file(policy, None, import_path)?;
         
-        let mut session: Session = serde_json::from_str(&contents)
-            .map_err(|e| format!("Failed to parse import file: {}", e))?;
+        let mut session: Session = persist::from_versioned_str(&SESSION_SCHEMA, &contents)
+            .map_err(|e| AppError::invalid(format!("Failed to import session: {}", e)))?;
         
         // Generate new ID to avoid conflicts
         session.id = format!("session_{}", Utc::now().timestamp_millis());
@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use reqwest::Url;
+use crate::filesystem;
+use crate::permissions::PermissionPolicy;
+use crate::scheduler::TaskResult;
+use crate::session::{SessionManager, SessionMessage};
+use crate::settings::SettingsManager;
+use crate::template::{self, Context};
+
+// How long a webhook may take to answer before the delivery counts as failed
+const WEBHOOK_TIMEOUT_SECONDS: u64 = 10;
+
+// Longest response shown in a notification body
+const NOTIFICATION_BODY_CHARS: usize = 200;
+
+// Where a task's result is delivered after its final attempt. File and Session only receive
+// successful responses; Notification and Webhook also report failures.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum OutputSink {
+    // `path` is a prompt template, e.g. "/home/me/reports/{date}-{task.name}.md".
+    // The file is written as the task's agent, so the permission policy applies.
+    File { path: String },
+    // Appends the prompt and response to a saved session
+    Session { session_id: String },
+    Notification,
+    // POSTs the task result as JSON; only loopback addresses are accepted
+    Webhook { url: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SinkError {
+    // Which sink failed, e.g. "webhook http://localhost:8080/hook"
+    pub sink: String,
+    pub error: String,
+}
+
+// Shows a desktop notification with a title and body
+pub type Notifier = Arc<dyn Fn(&str, &str) -> Result<(), String> + Send + Sync>;
+
+// What the sinks need to deliver results
+pub struct SinkHandles {
+    pub permissions: Arc<PermissionPolicy>,
+    pub sessions: Arc<SessionManager>,
+    // Source of the shared HTTP client
+    pub settings: Arc<SettingsManager>,
+    pub notifier: Notifier,
+}
+
+impl OutputSink {
+    // `variables` are the template variables a file path may use
+    pub fn validate(&self, sessions: &SessionManager, variables: &[&str]) -> Result<(), String> {
+        match self {
+            OutputSink::File { path } => {
+                template::validate(path, variables)
+                    .map_err(|e| format!("Invalid file sink path: {}", e))?;
+            }
+            OutputSink::Session { session_id } => {
+                sessions
+                    .load_session(session_id)
+                    .map_err(|e| format!("Invalid session sink: {}", e))?;
+            }
+            OutputSink::Notification => {}
+            OutputSink::Webhook { url } => {
+                parse_local_url(url)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn label(&self) -> String {
+        match self {
+            OutputSink::File { path } => format!("file {}", path),
+            OutputSink::Session { session_id } => format!("session {}", session_id),
+            OutputSink::Notification => "notification".to_string(),
+            OutputSink::Webhook { url } => format!("webhook {}", url),
+        }
+    }
+}
+
+// Delivers `result` to each sink in turn and returns the ones that failed. `context` is the
+// run's template context, used to render file paths.
+pub async fn deliver(
+    sinks: &[OutputSink],
+    result: &TaskResult,
+    agent_id: &str,
+    context: &Context,
+    handles: &SinkHandles,
+) -> Vec<SinkError> {
+    let mut errors = Vec::new();
+    for sink in sinks {
+        let outcome = match sink {
+            OutputSink::File { path } if result.success => write_file(path, result, agent_id, context, handles),
+            OutputSink::Session { session_id } if result.success => {
+                let messages = vec![
+                    SessionMessage::new("user", &result.prompt, None),
+                    SessionMessage::new("assistant", &result.response, Some(agent_id.to_string())),
+                ];
+                handles
+                    .sessions
+                    .append_messages(session_id, messages)
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            }
+            OutputSink::Notification => notify(result, handles),
+            OutputSink::Webhook { url } => post_webhook(url, result, handles).await,
+            _ => Ok(()),
+        };
+        if let Err(error) = outcome {
+            errors.push(SinkError { sink: sink.label(), error });
+        }
+    }
+    errors
+}
+
+fn write_file(
+    path: &str,
+    result: &TaskResult,
+    agent_id: &str,
+    context: &Context,
+    handles: &SinkHandles,
+) -> Result<(), String> {
+    let path = template::render(path, context)
+        .map_err(|e| format!("Failed to render file path: {}", e))?;
+    filesystem::write_file(&handles.permissions, Some(agent_id), &path, &result.response)
+        .map_err(|e| format!("Failed to write file: {}", e))
+}
+
+fn notify(result: &TaskResult, handles: &SinkHandles) -> Result<(), String> {
+    let body = if result.success {
+        let mut body: String = result.response.chars().take(NOTIFICATION_BODY_CHARS).collect();
+        if body.len() < result.response.len() {
+            body.push('…');
+        }
+        body
+    } else {
+        format!("Failed: {}", result.error.as_deref().unwrap_or("unknown error"))
+    };
+    (handles.notifier)(&result.task_name, &body)
+}
+
+async fn post_webhook(url: &str, result: &TaskResult, handles: &SinkHandles) -> Result<(), String> {
+    let url = parse_local_url(url)?;
+    handles
+        .settings
+        .client()
+        .post(url)
+        .timeout(Duration::from_secs(WEBHOOK_TIMEOUT_SECONDS))
+        .json(result)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map(|_| ())
+        .map_err(|e| format!("Webhook request failed: {}", e))
+}
+
+// Webhooks may only point at this machine so task output never leaves it
+fn parse_local_url(url: &str) -> Result<Url, String> {
+    let parsed = Url::parse(url).map_err(|e| format!("Invalid webhook URL '{}': {}", url, e))?;
+    if parsed.scheme() != "http" && parsed.scheme() != "https" {
+        return Err(format!("Invalid webhook URL '{}': must use http or https", url));
+    }
+    let local = parsed.host_str().map_or(false, |host| {
+        host.eq_ignore_ascii_case("localhost")
+            || host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse::<IpAddr>()
+                .map_or(false, |ip| ip.is_loopback())
+    });
+    if !local {
+        return Err(format!("Invalid webhook URL '{}': only localhost addresses are allowed", url));
+    }
+    Ok(parsed)
+}
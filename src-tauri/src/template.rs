@@ -0,0 +1,1171 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::sync::Arc;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Days, Duration};
+use chrono_tz::Tz;
+
+// Prompt templates for scheduled tasks.
+//
+//   {name}                     a variable; dotted paths reach into objects: {task.name}
+//   {env.AGENTWORKS_REPO}      an environment variable; only names starting with AGENTWORKS_
+//   {file("/abs/path")}        a file's contents (subject to the permission policy)
+//   {date + 1d}                date arithmetic with d, h, m (minutes) and w units
+//   {x | upper}                filters, chained left to right
+//   {#if cond}..{#elif cond}..{#else}..{/if}
+//   {#for item in list}..{loop.index}..{/for}
+//   {{ and }}                  literal braces
+//
+// Conditions can use `==`, `!=` and `not`. Empty strings, empty lists, 0, false and
+// missing values are false.
+
+const FILTERS: &[(&str, usize)] = &[
+    ("format", 1),
+    ("upper", 0),
+    ("lower", 0),
+    ("trim", 0),
+    ("default", 1),
+    ("lines", 0),
+    ("split", 1),
+    ("join", 1),
+    ("truncate", 1),
+    ("length", 0),
+];
+
+const FUNCTIONS: &[(&str, usize)] = &[("file", 1)];
+
+// Rendered prompts go to the model server, which may be remote, so templates only see
+// environment variables set aside for them rather than keys and tokens
+const ENV_PREFIX: &str = "AGENTWORKS_";
+
+#[derive(Debug, Clone)]
+pub struct TemplateError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Str(String),
+    // A point in time and the format it prints with
+    Date(DateTime<Tz>, String),
+    Duration(Span),
+    List(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+}
+
+impl Value {
+    pub fn date(at: DateTime<Tz>, format: &str) -> Value {
+        Value::Date(at, format.to_string())
+    }
+
+    pub fn map(entries: Vec<(&str, Value)>) -> Value {
+        Value::Map(entries.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Bool(b) => *b,
+            Value::Int(n) => *n != 0,
+            Value::Str(s) => !s.is_empty(),
+            Value::List(items) => !items.is_empty(),
+            Value::Map(entries) => !entries.is_empty(),
+            Value::Date(..) | Value::Duration(_) => true,
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "nothing",
+            Value::Bool(_) => "a boolean",
+            Value::Int(_) => "a number",
+            Value::Str(_) => "text",
+            Value::Date(..) => "a date",
+            Value::Duration(_) => "a duration",
+            Value::List(_) => "a list",
+            Value::Map(_) => "an object",
+        }
+    }
+
+    fn to_text(&self) -> Result<String, String> {
+        Ok(match self {
+            Value::Null => String::new(),
+            Value::Bool(b) => b.to_string(),
+            Value::Int(n) => n.to_string(),
+            Value::Str(s) => s.clone(),
+            Value::Date(at, format) => {
+                // Formatting reports an invalid strftime string as fmt::Error, which to_string() would panic on
+                let mut text = String::new();
+                write!(text, "{}", at.format(format)).map_err(|_| invalid_date_format(format))?;
+                text
+            }
+            Value::Duration(d) => format!("{}m", d.total_minutes()),
+            Value::List(items) => items
+                .iter()
+                .map(|item| item.to_text())
+                .collect::<Result<Vec<String>, String>>()?
+                .join(", "),
+            Value::Map(_) => return Err("an object can't be printed; use one of its fields".to_string()),
+        })
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Null, Value::Null) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Date(a, _), Value::Date(b, _)) => a == b,
+            (Value::Duration(a), Value::Duration(b)) => a.total_minutes() == b.total_minutes(),
+            (Value::List(a), Value::List(b)) => a == b,
+            // Everything else compares by its printed form, so {task.run_count == "3"} works
+            (a, b) => match (a.to_text(), b.to_text()) {
+                (Ok(a), Ok(b)) => a == b,
+                _ => false,
+            },
+        }
+    }
+}
+
+// A length of time written as e.g. `2d` or `90m`. Days and weeks are calendar days, so
+// `{date - 1d}` is the previous date even when a DST change makes that day 23 or 25 hours long.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    days: i64,
+    time: Duration,
+}
+
+impl Span {
+    fn days(days: i64) -> Span {
+        Span { days, time: Duration::zero() }
+    }
+
+    fn time(time: Duration) -> Span {
+        Span { days: 0, time }
+    }
+
+    fn total_minutes(&self) -> i64 {
+        self.days.saturating_mul(24 * 60).saturating_add(self.time.num_minutes())
+    }
+
+    fn checked_add(&self, other: &Span) -> Option<Span> {
+        Some(Span {
+            days: self.days.checked_add(other.days)?,
+            time: self.time.checked_add(&other.time)?,
+        })
+    }
+
+    fn checked_neg(&self) -> Option<Span> {
+        Some(Span {
+            days: self.days.checked_neg()?,
+            time: Duration::zero().checked_sub(&self.time)?,
+        })
+    }
+
+    // Moves the local date by whole days first, keeping the wall-clock time, then adds the rest
+    fn checked_add_to(&self, at: DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let at = if self.days == 0 {
+            at
+        } else {
+            let local = at.naive_local();
+            let local = if self.days > 0 {
+                local.checked_add_days(Days::new(self.days as u64))?
+            } else {
+                local.checked_sub_days(Days::new(self.days.unsigned_abs()))?
+            };
+            crate::scheduler::resolve_local(at.timezone(), local)?.with_timezone(&at.timezone())
+        };
+        at.checked_add_signed(self.time)
+    }
+}
+
+pub type FileReader = Arc<dyn Fn(&str) -> Result<String, String> + Send + Sync>;
+
+// Variables available to a template, plus how `file()` reads files
+#[derive(Clone)]
+pub struct Context {
+    vars: BTreeMap<String, Value>,
+    read_file: Option<FileReader>,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Context {
+            vars: BTreeMap::new(),
+            read_file: None,
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: Value) {
+        self.vars.insert(name.to_string(), value);
+    }
+
+    pub fn with_file_reader(mut self, read_file: FileReader) -> Self {
+        self.read_file = Some(read_file);
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    Expr(Expr),
+    If { branches: Vec<(Expr, Vec<Node>)>, otherwise: Vec<Node> },
+    For { var: String, items: Expr, body: Vec<Node> },
+}
+
+#[derive(Debug, Clone)]
+struct Expr {
+    pos: usize,
+    kind: ExprKind,
+}
+
+#[derive(Debug, Clone)]
+enum ExprKind {
+    Path(Vec<String>),
+    Literal(Value),
+    Call(String, Vec<Expr>),
+    Filter(Box<Expr>, String, Vec<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Eq(Box<Expr>, Box<Expr>),
+    Ne(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+// Checks syntax, and that every variable is one of `variables` (or a loop variable)
+pub fn validate(template: &str, variables: &[&str]) -> Result<(), TemplateError> {
+    let nodes = parse(template)?;
+    let mut scope: Vec<String> = variables.iter().map(|v| v.to_string()).collect();
+    scope.push("env".to_string());
+    check_scope(template, &nodes, &mut scope)
+}
+
+// Dotted paths of every variable the template uses, e.g. ["steps", "fetch", "output"]
+pub fn variable_paths(template: &str) -> Result<Vec<Vec<String>>, TemplateError> {
+    let mut paths = Vec::new();
+    for node in &parse(template)? {
+        collect_node_paths(node, &mut paths);
+    }
+    Ok(paths)
+}
+
+pub fn render(template: &str, context: &Context) -> Result<String, TemplateError> {
+    let nodes = parse(template)?;
+    let mut renderer = Renderer {
+        source: template,
+        context,
+        locals: Vec::new(),
+    };
+    let mut out = String::new();
+    renderer.render_nodes(&nodes, &mut out)?;
+    Ok(out)
+}
+
+fn error_at(source: &str, pos: usize, message: String) -> TemplateError {
+    let before = &source[..pos.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+    TemplateError { line, column, message }
+}
+
+// ---- Parsing ----
+
+enum Tag {
+    Expr(Expr),
+    If(Expr),
+    Elif(Expr),
+    Else,
+    EndIf,
+    For(String, Expr),
+    EndFor,
+}
+
+enum Piece {
+    Text(String),
+    Tag(Tag),
+}
+
+type Pieces = std::iter::Peekable<std::vec::IntoIter<(usize, Piece)>>;
+
+fn parse(source: &str) -> Result<Vec<Node>, TemplateError> {
+    let mut pieces: Vec<(usize, Piece)> = Vec::new();
+    let mut text = String::new();
+    let mut i = 0;
+
+    while i < source.len() {
+        let rest = &source[i..];
+        if rest.starts_with("{{") {
+            text.push('{');
+            i += 2;
+        } else if rest.starts_with("}}") {
+            text.push('}');
+            i += 2;
+        } else if rest.starts_with('{') {
+            let end = find_tag_end(source, i)?;
+            if !text.is_empty() {
+                pieces.push((i, Piece::Text(std::mem::take(&mut text))));
+            }
+            pieces.push((i, Piece::Tag(parse_tag(source, i + 1, end)?)));
+            i = end + 1;
+        } else {
+            let ch = rest.chars().next().unwrap();
+            text.push(ch);
+            i += ch.len_utf8();
+        }
+    }
+    if !text.is_empty() {
+        pieces.push((i, Piece::Text(text)));
+    }
+
+    parse_block(source, &mut pieces.into_iter().peekable(), None)
+}
+
+// Parses nodes up to the tag that ends the block opened by `open` ("if" or "for" at a
+// position), leaving that tag for the caller
+fn parse_block(source: &str, pieces: &mut Pieces, open: Option<(&str, usize)>) -> Result<Vec<Node>, TemplateError> {
+    let mut nodes = Vec::new();
+
+    while let Some((pos, piece)) = pieces.peek() {
+        let pos = *pos;
+        match piece {
+            Piece::Tag(Tag::Elif(_)) | Piece::Tag(Tag::Else) | Piece::Tag(Tag::EndIf) => {
+                if matches!(open, Some(("if", _))) {
+                    return Ok(nodes);
+                }
+                return Err(error_at(source, pos, "this tag has no matching {#if}".to_string()));
+            }
+            Piece::Tag(Tag::EndFor) => {
+                if matches!(open, Some(("for", _))) {
+                    return Ok(nodes);
+                }
+                return Err(error_at(source, pos, "{/for} has no matching {#for}".to_string()));
+            }
+            _ => {}
+        }
+
+        match pieces.next().unwrap().1 {
+            Piece::Text(text) => nodes.push(Node::Text(text)),
+            Piece::Tag(Tag::Expr(expr)) => nodes.push(Node::Expr(expr)),
+            Piece::Tag(Tag::If(condition)) => {
+                let mut branches = Vec::new();
+                let mut otherwise = Vec::new();
+                let mut condition = Some(condition);
+                let mut seen_else = false;
+                loop {
+                    let body = parse_block(source, pieces, Some(("if", pos)))?;
+                    match condition.take() {
+                        Some(c) => branches.push((c, body)),
+                        None => otherwise = body,
+                    }
+                    match pieces.next() {
+                        Some((_, Piece::Tag(Tag::Elif(c)))) if !seen_else => condition = Some(c),
+                        Some((_, Piece::Tag(Tag::Else))) if !seen_else => seen_else = true,
+                        Some((_, Piece::Tag(Tag::EndIf))) => break,
+                        Some((p, _)) => return Err(error_at(source, p, "unexpected tag after {#else}".to_string())),
+                        None => unreachable!(),
+                    }
+                }
+                nodes.push(Node::If { branches, otherwise });
+            }
+            Piece::Tag(Tag::For(var, items)) => {
+                let body = parse_block(source, pieces, Some(("for", pos)))?;
+                pieces.next();
+                nodes.push(Node::For { var, items, body });
+            }
+            Piece::Tag(_) => unreachable!(),
+        }
+    }
+
+    match open {
+        Some(("if", pos)) => Err(error_at(source, pos, "{#if} is never closed with {/if}".to_string())),
+        Some((_, pos)) => Err(error_at(source, pos, "{#for} is never closed with {/for}".to_string())),
+        None => Ok(nodes),
+    }
+}
+
+// Position of the `}` closing the tag opened at `start`, skipping string literals
+fn find_tag_end(source: &str, start: usize) -> Result<usize, TemplateError> {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (offset, ch) in source[start + 1..].char_indices() {
+        let pos = start + 1 + offset;
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if ch == '\\' {
+                escaped = true;
+            } else if ch == '"' {
+                in_string = false;
+            }
+        } else if ch == '"' {
+            in_string = true;
+        } else if ch == '}' {
+            return Ok(pos);
+        } else if ch == '{' || ch == '\n' {
+            break;
+        }
+    }
+    Err(error_at(
+        source,
+        start,
+        "unclosed '{'; write '{{' for a literal brace".to_string(),
+    ))
+}
+
+fn parse_tag(source: &str, start: usize, end: usize) -> Result<Tag, TemplateError> {
+    let content = &source[start..end];
+    let trimmed = content.trim_start();
+    let offset = start + (content.len() - trimmed.len());
+    let trimmed = trimmed.trim_end();
+
+    let keyword_rest = |keyword: &str| -> Option<usize> {
+        if trimmed == keyword || trimmed.starts_with(&format!("{} ", keyword)) {
+            Some(offset + keyword.len())
+        } else {
+            None
+        }
+    };
+
+    if let Some(rest) = keyword_rest("#if") {
+        return Ok(Tag::If(parse_expr_at(source, rest, end)?));
+    }
+    if let Some(rest) = keyword_rest("#elif") {
+        return Ok(Tag::Elif(parse_expr_at(source, rest, end)?));
+    }
+    if trimmed == "#else" {
+        return Ok(Tag::Else);
+    }
+    if trimmed == "/if" {
+        return Ok(Tag::EndIf);
+    }
+    if trimmed == "/for" {
+        return Ok(Tag::EndFor);
+    }
+    if let Some(rest) = keyword_rest("#for") {
+        let mut lexer = Lexer::new(source, rest, end);
+        let var = match lexer.next()? {
+            Some((_, Token::Ident(name))) if !name.contains('.') => name,
+            _ => return Err(error_at(source, rest, "expected a loop variable name after #for".to_string())),
+        };
+        match lexer.next()? {
+            Some((_, Token::Ident(word))) if word == "in" => {}
+            _ => return Err(error_at(source, rest, "expected 'in' after the loop variable".to_string())),
+        }
+        let mut parser = Parser { source, lexer, peeked: None };
+        let items = parser.parse_full()?;
+        return Ok(Tag::For(var, items));
+    }
+    if trimmed.starts_with('#') || trimmed.starts_with('/') {
+        return Err(error_at(source, offset, format!("unknown block tag '{}'", trimmed)));
+    }
+    Ok(Tag::Expr(parse_expr_at(source, start, end)?))
+}
+
+fn parse_expr_at(source: &str, start: usize, end: usize) -> Result<Expr, TemplateError> {
+    let mut parser = Parser {
+        source,
+        lexer: Lexer::new(source, start, end),
+        peeked: None,
+    };
+    parser.parse_full()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Int(i64),
+    Duration(Span),
+    Plus,
+    Minus,
+    Pipe,
+    Comma,
+    LParen,
+    RParen,
+    EqEq,
+    NotEq,
+}
+
+struct Lexer<'a> {
+    source: &'a str,
+    pos: usize,
+    end: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(source: &'a str, pos: usize, end: usize) -> Self {
+        Lexer { source, pos, end }
+    }
+
+    fn next(&mut self) -> Result<Option<(usize, Token)>, TemplateError> {
+        let rest = &self.source[self.pos..self.end];
+        let skipped = rest.len() - rest.trim_start().len();
+        self.pos += skipped;
+        let start = self.pos;
+        let rest = &self.source[self.pos..self.end];
+        let ch = match rest.chars().next() {
+            Some(ch) => ch,
+            None => return Ok(None),
+        };
+
+        let simple = match ch {
+            '+' => Some(Token::Plus),
+            '-' => Some(Token::Minus),
+            '|' => Some(Token::Pipe),
+            ',' => Some(Token::Comma),
+            '(' => Some(Token::LParen),
+            ')' => Some(Token::RParen),
+            _ => None,
+        };
+        if let Some(token) = simple {
+            self.pos += 1;
+            return Ok(Some((start, token)));
+        }
+        if rest.starts_with("==") {
+            self.pos += 2;
+            return Ok(Some((start, Token::EqEq)));
+        }
+        if rest.starts_with("!=") {
+            self.pos += 2;
+            return Ok(Some((start, Token::NotEq)));
+        }
+
+        if ch == '"' {
+            let mut value = String::new();
+            let mut escaped = false;
+            for (offset, c) in rest[1..].char_indices() {
+                if escaped {
+                    value.push(match c {
+                        'n' => '\n',
+                        't' => '\t',
+                        other => other,
+                    });
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == '"' {
+                    self.pos += offset + 2;
+                    return Ok(Some((start, Token::Str(value))));
+                } else {
+                    value.push(c);
+                }
+            }
+            return Err(error_at(self.source, start, "unterminated string".to_string()));
+        }
+
+        if ch.is_ascii_digit() {
+            let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+            let number: i64 = digits
+                .parse()
+                .map_err(|_| error_at(self.source, start, format!("number '{}' is too large", digits)))?;
+            self.pos += digits.len();
+            let unit: String = self.source[self.pos..self.end]
+                .chars()
+                .take_while(|c| c.is_ascii_alphabetic())
+                .collect();
+            if unit.is_empty() {
+                return Ok(Some((start, Token::Int(number))));
+            }
+            self.pos += unit.len();
+            let duration = match unit.as_str() {
+                // Days and weeks keep the limits of the equivalent Duration
+                "w" => Duration::try_weeks(number).map(|_| Span::days(number * 7)),
+                "d" => Duration::try_days(number).map(|_| Span::days(number)),
+                "h" => Duration::try_hours(number).map(Span::time),
+                "m" => Duration::try_minutes(number).map(Span::time),
+                _ => {
+                    return Err(error_at(
+                        self.source,
+                        start,
+                        format!("unknown duration unit '{}': use w, d, h or m", unit),
+                    ))
+                }
+            };
+            let duration = duration.ok_or_else(|| {
+                error_at(self.source, start, format!("duration '{}{}' is too large", digits, unit))
+            })?;
+            return Ok(Some((start, Token::Duration(duration))));
+        }
+
+        if ch.is_alphabetic() || ch == '_' {
+            let ident: String = rest
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '.')
+                .collect();
+            self.pos += ident.len();
+            if ident.ends_with('.') || ident.contains("..") {
+                return Err(error_at(self.source, start, format!("invalid name '{}'", ident)));
+            }
+            return Ok(Some((start, Token::Ident(ident))));
+        }
+
+        Err(error_at(
+            self.source,
+            start,
+            format!("unexpected character '{}'; write '{{{{' for a literal brace", ch),
+        ))
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    lexer: Lexer<'a>,
+    peeked: Option<Option<(usize, Token)>>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&mut self) -> Result<Option<&(usize, Token)>, TemplateError> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.lexer.next()?);
+        }
+        Ok(self.peeked.as_ref().unwrap().as_ref())
+    }
+
+    fn next(&mut self) -> Result<Option<(usize, Token)>, TemplateError> {
+        match self.peeked.take() {
+            Some(token) => Ok(token),
+            None => self.lexer.next(),
+        }
+    }
+
+    fn error(&self, pos: usize, message: &str) -> TemplateError {
+        error_at(self.source, pos, message.to_string())
+    }
+
+    fn parse_full(&mut self) -> Result<Expr, TemplateError> {
+        let start = self.lexer.pos;
+        if self.peek()?.is_none() {
+            return Err(self.error(start, "empty expression"));
+        }
+        let expr = self.parse_comparison()?;
+        match self.next()? {
+            None => Ok(expr),
+            Some((pos, token)) => Err(self.error(pos, &format!("unexpected {:?}", token))),
+        }
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, TemplateError> {
+        let left = self.parse_unary()?;
+        let op = match self.peek()? {
+            Some((_, Token::EqEq)) => Token::EqEq,
+            Some((_, Token::NotEq)) => Token::NotEq,
+            _ => return Ok(left),
+        };
+        self.next()?;
+        let right = self.parse_unary()?;
+        let pos = left.pos;
+        let (l, r) = (Box::new(left), Box::new(right));
+        Ok(Expr {
+            pos,
+            kind: if op == Token::EqEq { ExprKind::Eq(l, r) } else { ExprKind::Ne(l, r) },
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, TemplateError> {
+        if let Some((pos, Token::Ident(word))) = self.peek()? {
+            if word == "not" {
+                let pos = *pos;
+                self.next()?;
+                let inner = self.parse_unary()?;
+                return Ok(Expr { pos, kind: ExprKind::Not(Box::new(inner)) });
+            }
+        }
+        self.parse_pipeline()
+    }
+
+    fn parse_pipeline(&mut self) -> Result<Expr, TemplateError> {
+        let mut expr = self.parse_additive()?;
+        while let Some((_, Token::Pipe)) = self.peek()? {
+            self.next()?;
+            let (pos, name) = match self.next()? {
+                Some((pos, Token::Ident(name))) => (pos, name),
+                Some((pos, _)) => return Err(self.error(pos, "expected a filter name after '|'")),
+                None => return Err(self.error(self.lexer.pos, "expected a filter name after '|'")),
+            };
+            let args = self.parse_optional_args()?;
+            let arity = FILTERS
+                .iter()
+                .find(|(filter, _)| *filter == name)
+                .map(|(_, arity)| *arity)
+                .ok_or_else(|| {
+                    let names: Vec<&str> = FILTERS.iter().map(|(f, _)| *f).collect();
+                    self.error(pos, &format!("unknown filter '{}'; available: {}", name, names.join(", ")))
+                })?;
+            if args.len() != arity {
+                return Err(self.error(pos, &format!("filter '{}' takes {} argument(s)", name, arity)));
+            }
+            expr = Expr { pos, kind: ExprKind::Filter(Box::new(expr), name, args) };
+        }
+        Ok(expr)
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr, TemplateError> {
+        let mut expr = self.parse_primary()?;
+        loop {
+            let plus = match self.peek()? {
+                Some((_, Token::Plus)) => true,
+                Some((_, Token::Minus)) => false,
+                _ => return Ok(expr),
+            };
+            let (pos, _) = self.next()?.unwrap();
+            let right = Box::new(self.parse_primary()?);
+            let left = Box::new(expr);
+            expr = Expr {
+                pos,
+                kind: if plus { ExprKind::Add(left, right) } else { ExprKind::Sub(left, right) },
+            };
+        }
+    }
+
+    fn parse_optional_args(&mut self) -> Result<Vec<Expr>, TemplateError> {
+        if !matches!(self.peek()?, Some((_, Token::LParen))) {
+            return Ok(Vec::new());
+        }
+        let (open, _) = self.next()?.unwrap();
+        let mut args = Vec::new();
+        if let Some((_, Token::RParen)) = self.peek()? {
+            self.next()?;
+            return Ok(args);
+        }
+        loop {
+            args.push(self.parse_comparison()?);
+            match self.next()? {
+                Some((_, Token::Comma)) => continue,
+                Some((_, Token::RParen)) => return Ok(args),
+                _ => return Err(self.error(open, "unclosed '(' in argument list")),
+            }
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, TemplateError> {
+        let (pos, token) = match self.next()? {
+            Some(token) => token,
+            None => return Err(self.error(self.lexer.pos, "expected a value")),
+        };
+        let kind = match token {
+            Token::Str(s) => ExprKind::Literal(Value::Str(s)),
+            Token::Int(n) => ExprKind::Literal(Value::Int(n)),
+            Token::Duration(d) => ExprKind::Literal(Value::Duration(d)),
+            Token::LParen => {
+                let inner = self.parse_comparison()?;
+                match self.next()? {
+                    Some((_, Token::RParen)) => return Ok(inner),
+                    _ => return Err(self.error(pos, "unclosed '('")),
+                }
+            }
+            Token::Ident(name) => {
+                if let Some((_, Token::LParen)) = self.peek()? {
+                    let arity = FUNCTIONS
+                        .iter()
+                        .find(|(function, _)| *function == name)
+                        .map(|(_, arity)| *arity)
+                        .ok_or_else(|| self.error(pos, &format!("unknown function '{}'", name)))?;
+                    let args = self.parse_optional_args()?;
+                    if args.len() != arity {
+                        return Err(self.error(pos, &format!("function '{}' takes {} argument(s)", name, arity)));
+                    }
+                    ExprKind::Call(name, args)
+                } else {
+                    match name.as_str() {
+                        "true" => ExprKind::Literal(Value::Bool(true)),
+                        "false" => ExprKind::Literal(Value::Bool(false)),
+                        _ => ExprKind::Path(name.split('.').map(|s| s.to_string()).collect()),
+                    }
+                }
+            }
+            other => return Err(self.error(pos, &format!("unexpected {:?}", other))),
+        };
+        Ok(Expr { pos, kind })
+    }
+}
+
+// ---- Validation ----
+
+fn check_scope(source: &str, nodes: &[Node], scope: &mut Vec<String>) -> Result<(), TemplateError> {
+    for node in nodes {
+        match node {
+            Node::Text(_) => {}
+            Node::Expr(expr) => check_expr(source, expr, scope)?,
+            Node::If { branches, otherwise } => {
+                for (condition, body) in branches {
+                    check_expr(source, condition, scope)?;
+                    check_scope(source, body, scope)?;
+                }
+                check_scope(source, otherwise, scope)?;
+            }
+            Node::For { var, items, body } => {
+                check_expr(source, items, scope)?;
+                scope.push(var.clone());
+                scope.push("loop".to_string());
+                let result = check_scope(source, body, scope);
+                scope.truncate(scope.len() - 2);
+                result?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_expr(source: &str, expr: &Expr, scope: &[String]) -> Result<(), TemplateError> {
+    let mut paths = Vec::new();
+    collect_expr_paths(expr, &mut paths);
+    for (pos, path) in paths {
+        if !scope.contains(&path[0]) {
+            return Err(error_at(
+                source,
+                pos,
+                format!("unknown variable '{}'; available: {}", path[0], scope.join(", ")),
+            ));
+        }
+        if path[0] == "env" && path.get(1).map_or(false, |name| !name.starts_with(ENV_PREFIX)) {
+            return Err(error_at(
+                source,
+                pos,
+                format!(
+                    "environment variable '{}' is not available; only names starting with {} are",
+                    path[1], ENV_PREFIX
+                ),
+            ));
+        }
+    }
+    check_date_formats(source, expr)
+}
+
+// Literal date formats are checked up front, so a typo fails when the task is saved rather
+// than at run time
+fn check_date_formats(source: &str, expr: &Expr) -> Result<(), TemplateError> {
+    match &expr.kind {
+        ExprKind::Path(_) | ExprKind::Literal(_) => Ok(()),
+        ExprKind::Call(_, args) => args.iter().try_for_each(|a| check_date_formats(source, a)),
+        ExprKind::Filter(inner, name, args) => {
+            if name == "format" {
+                if let Some(ExprKind::Literal(Value::Str(format))) = args.first().map(|a| &a.kind) {
+                    if !is_valid_date_format(format) {
+                        return Err(error_at(source, args[0].pos, invalid_date_format(format)));
+                    }
+                }
+            }
+            check_date_formats(source, inner)?;
+            args.iter().try_for_each(|a| check_date_formats(source, a))
+        }
+        ExprKind::Add(a, b) | ExprKind::Sub(a, b) | ExprKind::Eq(a, b) | ExprKind::Ne(a, b) => {
+            check_date_formats(source, a)?;
+            check_date_formats(source, b)
+        }
+        ExprKind::Not(inner) => check_date_formats(source, inner),
+    }
+}
+
+fn is_valid_date_format(format: &str) -> bool {
+    !StrftimeItems::new(format).any(|item| matches!(item, Item::Error))
+}
+
+fn invalid_date_format(format: &str) -> String {
+    format!("invalid date format '{}'", format)
+}
+
+fn collect_node_paths(node: &Node, paths: &mut Vec<Vec<String>>) {
+    let mut positioned = Vec::new();
+    match node {
+        Node::Text(_) => {}
+        Node::Expr(expr) => collect_expr_paths(expr, &mut positioned),
+        Node::If { branches, otherwise } => {
+            for (condition, body) in branches {
+                collect_expr_paths(condition, &mut positioned);
+                body.iter().for_each(|n| collect_node_paths(n, paths));
+            }
+            otherwise.iter().for_each(|n| collect_node_paths(n, paths));
+        }
+        Node::For { items, body, .. } => {
+            collect_expr_paths(items, &mut positioned);
+            body.iter().for_each(|n| collect_node_paths(n, paths));
+        }
+    }
+    paths.extend(positioned.into_iter().map(|(_, path)| path));
+}
+
+fn collect_expr_paths(expr: &Expr, paths: &mut Vec<(usize, Vec<String>)>) {
+    match &expr.kind {
+        ExprKind::Path(path) => paths.push((expr.pos, path.clone())),
+        ExprKind::Literal(_) => {}
+        ExprKind::Call(_, args) => args.iter().for_each(|a| collect_expr_paths(a, paths)),
+        ExprKind::Filter(inner, _, args) => {
+            collect_expr_paths(inner, paths);
+            args.iter().for_each(|a| collect_expr_paths(a, paths));
+        }
+        ExprKind::Add(a, b) | ExprKind::Sub(a, b) | ExprKind::Eq(a, b) | ExprKind::Ne(a, b) => {
+            collect_expr_paths(a, paths);
+            collect_expr_paths(b, paths);
+        }
+        ExprKind::Not(inner) => collect_expr_paths(inner, paths),
+    }
+}
+
+// ---- Rendering ----
+
+struct Renderer<'a> {
+    source: &'a str,
+    context: &'a Context,
+    locals: Vec<(String, Value)>,
+}
+
+impl<'a> Renderer<'a> {
+    fn render_nodes(&mut self, nodes: &[Node], out: &mut String) -> Result<(), TemplateError> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Expr(expr) => {
+                    let value = self.eval(expr)?;
+                    let text = value.to_text().map_err(|e| error_at(self.source, expr.pos, e))?;
+                    out.push_str(&text);
+                }
+                Node::If { branches, otherwise } => {
+                    let mut taken = false;
+                    for (condition, body) in branches {
+                        if self.eval(condition)?.truthy() {
+                            self.render_nodes(body, out)?;
+                            taken = true;
+                            break;
+                        }
+                    }
+                    if !taken {
+                        self.render_nodes(otherwise, out)?;
+                    }
+                }
+                Node::For { var, items, body } => {
+                    let items = match self.eval(items)? {
+                        Value::List(items) => items,
+                        Value::Null => Vec::new(),
+                        other => {
+                            return Err(error_at(
+                                self.source,
+                                items.pos,
+                                format!("can only loop over a list, not {}", other.type_name()),
+                            ))
+                        }
+                    };
+                    let count = items.len();
+                    for (index, item) in items.into_iter().enumerate() {
+                        let info = Value::map(vec![
+                            ("index", Value::Int(index as i64 + 1)),
+                            ("first", Value::Bool(index == 0)),
+                            ("last", Value::Bool(index + 1 == count)),
+                        ]);
+                        self.locals.push((var.clone(), item));
+                        self.locals.push(("loop".to_string(), info));
+                        let result = self.render_nodes(body, out);
+                        self.locals.truncate(self.locals.len() - 2);
+                        result?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn lookup(&self, path: &[String]) -> Value {
+        let root = &path[0];
+        let mut value = if let Some((_, value)) = self.locals.iter().rev().find(|(name, _)| name == root) {
+            value.clone()
+        } else if root == "env" {
+            return match path.get(1) {
+                Some(name) if path.len() == 2 && name.starts_with(ENV_PREFIX) => {
+                    std::env::var(name).map(Value::Str).unwrap_or(Value::Null)
+                }
+                _ => Value::Null,
+            };
+        } else {
+            self.context.vars.get(root).cloned().unwrap_or(Value::Null)
+        };
+
+        for field in &path[1..] {
+            value = match value {
+                Value::Map(mut entries) => entries.remove(field).unwrap_or(Value::Null),
+                _ => Value::Null,
+            };
+        }
+        value
+    }
+
+    fn eval(&self, expr: &Expr) -> Result<Value, TemplateError> {
+        let fail = |message: String| error_at(self.source, expr.pos, message);
+        Ok(match &expr.kind {
+            ExprKind::Path(path) => self.lookup(path),
+            ExprKind::Literal(value) => value.clone(),
+            ExprKind::Not(inner) => Value::Bool(!self.eval(inner)?.truthy()),
+            ExprKind::Eq(a, b) => Value::Bool(self.eval(a)? == self.eval(b)?),
+            ExprKind::Ne(a, b) => Value::Bool(self.eval(a)? != self.eval(b)?),
+            ExprKind::Add(a, b) | ExprKind::Sub(a, b) => {
+                let subtract = matches!(expr.kind, ExprKind::Sub(..));
+                let operation = if subtract { "subtraction" } else { "addition" };
+                let overflow = || fail(format!("result of {} is out of range", operation));
+                match (self.eval(a)?, self.eval(b)?) {
+                    (Value::Date(at, format), Value::Duration(d)) => {
+                        let d = if subtract { d.checked_neg().ok_or_else(overflow)? } else { d };
+                        let result = d.checked_add_to(at);
+                        Value::Date(result.ok_or_else(overflow)?, format)
+                    }
+                    (Value::Int(x), Value::Int(y)) => {
+                        let result = if subtract { x.checked_sub(y) } else { x.checked_add(y) };
+                        Value::Int(result.ok_or_else(overflow)?)
+                    }
+                    (Value::Duration(x), Value::Duration(y)) => {
+                        let y = if subtract { y.checked_neg().ok_or_else(overflow)? } else { y };
+                        Value::Duration(x.checked_add(&y).ok_or_else(overflow)?)
+                    }
+                    (Value::Str(x), Value::Str(y)) if !subtract => Value::Str(x + &y),
+                    (x, y) => {
+                        return Err(fail(format!(
+                            "can't {} {} and {}",
+                            if subtract { "subtract" } else { "add" },
+                            x.type_name(),
+                            y.type_name()
+                        )))
+                    }
+                }
+            }
+            ExprKind::Call(name, args) => {
+                let arg = self.eval(&args[0])?.to_text().map_err(&fail)?;
+                match name.as_str() {
+                    "file" => {
+                        let read_file = self
+                            .context
+                            .read_file
+                            .as_ref()
+                            .ok_or_else(|| fail("file() is not available here".to_string()))?;
+                        Value::Str(read_file(&arg).map_err(|e| fail(format!("file(\"{}\"): {}", arg, e)))?)
+                    }
+                    _ => return Err(fail(format!("unknown function '{}'", name))),
+                }
+            }
+            ExprKind::Filter(inner, name, args) => {
+                let value = self.eval(inner)?;
+                let args = args.iter().map(|a| self.eval(a)).collect::<Result<Vec<Value>, _>>()?;
+                apply_filter(value, name, &args).map_err(fail)?
+            }
+        })
+    }
+}
+
+fn apply_filter(value: Value, name: &str, args: &[Value]) -> Result<Value, String> {
+    let text_arg = |i: usize| args[i].to_text();
+    Ok(match name {
+        "default" => {
+            if value.truthy() {
+                value
+            } else {
+                args[0].clone()
+            }
+        }
+        "format" => match value {
+            Value::Date(at, _) => Value::Date(at, text_arg(0)?),
+            Value::Null => Value::Null,
+            other => return Err(format!("format needs a date, not {}", other.type_name())),
+        },
+        "upper" => Value::Str(value.to_text()?.to_uppercase()),
+        "lower" => Value::Str(value.to_text()?.to_lowercase()),
+        "trim" => Value::Str(value.to_text()?.trim().to_string()),
+        "lines" => Value::List(value.to_text()?.lines().map(|l| Value::Str(l.to_string())).collect()),
+        "split" => {
+            let separator = text_arg(0)?;
+            let text = value.to_text()?;
+            if text.is_empty() {
+                Value::List(Vec::new())
+            } else {
+                Value::List(text.split(separator.as_str()).map(|s| Value::Str(s.to_string())).collect())
+            }
+        }
+        "join" => match value {
+            Value::List(items) => Value::Str(
+                items
+                    .iter()
+                    .map(|item| item.to_text())
+                    .collect::<Result<Vec<String>, String>>()?
+                    .join(&text_arg(0)?),
+            ),
+            other => Value::Str(other.to_text()?),
+        },
+        "truncate" => {
+            let max = match &args[0] {
+                Value::Int(n) if *n >= 0 => *n as usize,
+                _ => return Err("truncate needs a non-negative number".to_string()),
+            };
+            let text = value.to_text()?;
+            match text.char_indices().nth(max) {
+                Some((idx, _)) => Value::Str(format!("{}...", &text[..idx])),
+                None => Value::Str(text),
+            }
+        }
+        "length" => match value {
+            Value::List(items) => Value::Int(items.len() as i64),
+            Value::Null => Value::Int(0),
+            other => Value::Int(other.to_text()?.chars().count() as i64),
+        },
+        _ => return Err(format!("unknown filter '{}'", name)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn context() -> Context {
+        let at = chrono_tz::UTC.with_ymd_and_hms(2024, 3, 11, 4, 30, 0).unwrap();
+        let mut context = Context::new();
+        context.set("date", Value::date(at, "%Y-%m-%d"));
+        context
+    }
+
+    #[test]
+    fn invalid_literal_date_format_fails_validation() {
+        let error = validate("Report for {date | format(\"%Q\")}", &["date"]).unwrap_err();
+        assert!(error.message.contains("invalid date format '%Q'"), "{}", error.message);
+        assert_eq!(error.column, 27);
+        assert!(validate("{date | format(\"%d.%m.%Y\")}", &["date"]).is_ok());
+    }
+
+    #[test]
+    fn invalid_date_format_at_render_time_is_an_error() {
+        let mut context = context();
+        context.set("pattern", Value::Str("%Q".to_string()));
+        let error = render("{date | format(pattern)}", &context).unwrap_err();
+        assert!(error.message.contains("invalid date format '%Q'"), "{}", error.message);
+
+        assert_eq!(render("{date | format(\"%d.%m.%Y\")}", &context).unwrap(), "11.03.2024");
+    }
+
+    #[test]
+    fn day_arithmetic_follows_the_calendar_across_dst_changes() {
+        let new_york = chrono_tz::America::New_York;
+        let mut context = Context::new();
+        // Clocks sprang forward on March 10, so the previous midnight is only 23 hours back
+        let after_spring = new_york.with_ymd_and_hms(2024, 3, 11, 0, 30, 0).unwrap();
+        context.set("date", Value::date(after_spring, "%Y-%m-%d"));
+        context.set("now", Value::date(after_spring, "%Y-%m-%d %H:%M"));
+        assert_eq!(render("{date - 1d}", &context).unwrap(), "2024-03-10");
+        assert_eq!(render("{now - 1d - 1h}", &context).unwrap(), "2024-03-09 23:30");
+        assert_eq!(render("{now - 24h}", &context).unwrap(), "2024-03-09 23:30");
+
+        // Clocks fell back on November 3, a 25-hour day
+        let before_fall = new_york.with_ymd_and_hms(2024, 10, 30, 23, 30, 0).unwrap();
+        context.set("now", Value::date(before_fall, "%Y-%m-%d %H:%M"));
+        assert_eq!(render("{now + 1w}", &context).unwrap(), "2024-11-06 23:30");
+        assert_eq!(render("{#if 1d == 24h}same{/if}", &context).unwrap(), "same");
+    }
+}
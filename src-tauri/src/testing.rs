@@ -0,0 +1,196 @@
+// Helpers for tests that talk to a model server: a local HTTP server with canned replies
+// and settings pointing at it
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use crate::persist;
+use crate::settings::{AppSettings, SettingsManager, SETTINGS_SCHEMA};
+
+// Gap between the pieces of a reply body, long enough for each to arrive as its own chunk
+const PIECE_DELAY: Duration = Duration::from_millis(20);
+
+// A request as the server received it
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.body).unwrap_or_else(|e| panic!("request body isn't JSON ({}): {}", e, self.body))
+    }
+}
+
+pub struct Reply {
+    status: u16,
+    content_type: &'static str,
+    pieces: Vec<String>,
+    // Keeps the connection open without sending anything after the pieces
+    stall: bool,
+}
+
+impl Reply {
+    pub fn json(status: u16, body: serde_json::Value) -> Self {
+        Reply {
+            status,
+            content_type: "application/json",
+            pieces: vec![body.to_string()],
+            stall: false,
+        }
+    }
+
+    // A streamed body sent in `pieces`, which may split lines anywhere
+    pub fn stream(content_type: &'static str, pieces: &[&str]) -> Self {
+        Reply {
+            status: 200,
+            content_type,
+            pieces: pieces.iter().map(|piece| piece.to_string()).collect(),
+            stall: false,
+        }
+    }
+
+    // Sends `pieces` and then nothing more until the client gives up
+    pub fn stalled(content_type: &'static str, pieces: &[&str]) -> Self {
+        Reply {
+            stall: true,
+            ..Reply::stream(content_type, pieces)
+        }
+    }
+}
+
+// Answers each request with the first unused reply queued for its path, or 404 if
+// there is none. Every connection serves one request.
+pub struct MockServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockServer {
+    pub async fn start(routes: Vec<(&str, Reply)>) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let routes: Vec<(String, Reply)> = routes.into_iter().map(|(path, reply)| (path.to_string(), reply)).collect();
+        let routes = Arc::new(Mutex::new(routes));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, routes.clone(), recorded.clone()));
+            }
+        });
+        MockServer { url, requests }
+    }
+
+    // Requests received so far, oldest first
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn serve(mut stream: TcpStream, routes: Arc<Mutex<Vec<(String, Reply)>>>, requests: Arc<Mutex<Vec<Request>>>) {
+    let request = match read_request(&mut stream).await {
+        Some(request) => request,
+        None => return,
+    };
+    let reply = {
+        let mut routes = routes.lock().unwrap();
+        routes
+            .iter()
+            .position(|(path, _)| *path == request.path)
+            .map(|index| routes.remove(index).1)
+    };
+    requests.lock().unwrap().push(request);
+    let reply = reply.unwrap_or_else(|| Reply::json(404, serde_json::json!({ "error": "no reply queued" })));
+
+    let head = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Type: {}\r\nConnection: close\r\n\r\n",
+        reply.status, reply.content_type
+    );
+    if stream.write_all(head.as_bytes()).await.is_err() {
+        return;
+    }
+    for piece in &reply.pieces {
+        if stream.write_all(piece.as_bytes()).await.is_err() || stream.flush().await.is_err() {
+            return;
+        }
+        tokio::time::sleep(PIECE_DELAY).await;
+    }
+    if reply.stall {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    }
+    let _ = stream.shutdown().await;
+}
+
+async fn read_request(stream: &mut TcpStream) -> Option<Request> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    let head_end = loop {
+        if let Some(pos) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        let n = stream.read(&mut buf).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        data.extend_from_slice(&buf[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&data[..head_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next()?.split_whitespace();
+    let method = request_line.next()?.to_string();
+    let path = request_line.next()?.to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let length: usize = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0);
+
+    let mut body = data[head_end..].to_vec();
+    while body.len() < length {
+        let n = stream.read(&mut buf).await.ok()?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&buf[..n]);
+    }
+
+    Some(Request {
+        method,
+        path,
+        headers,
+        body: String::from_utf8_lossy(&body).to_string(),
+    })
+}
+
+// Settings saved in a temporary directory, which lives as long as the returned TempDir
+pub fn settings(settings: AppSettings) -> (TempDir, SettingsManager) {
+    let dir = TempDir::new().unwrap();
+    let path: PathBuf = dir.path().join("settings.json");
+    std::fs::write(&path, persist::to_versioned_json(&SETTINGS_SCHEMA, &settings).unwrap()).unwrap();
+    (dir, SettingsManager::load(path))
+}
+
+// A URL nothing listens on
+pub async fn closed_url() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    format!("http://{}", listener.local_addr().unwrap())
+}
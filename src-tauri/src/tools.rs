@@ -0,0 +1,202 @@
+use serde::{Deserialize, Serialize};
+use crate::error::AppError;
+use crate::filesystem;
+use crate::permissions::PermissionPolicy;
+use crate::ollama::{ChatMessage, GenerationParams, ToolCall, ToolDefinition, ToolFunction};
+use crate::provider::LlmProvider;
+
+pub const DEFAULT_MAX_STEPS: usize = 8;
+
+// Tool output fed back to the model is capped so one large file doesn't fill the context window
+const MAX_TOOL_OUTPUT_CHARS: usize = 16_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AgentStep {
+    pub step: usize,
+    pub tool: String,
+    pub arguments: serde_json::Value,
+    pub output: String,
+    pub success: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AgentRun {
+    pub response: String,
+    pub steps: Vec<AgentStep>,
+    // False when the step limit was reached and the model had to answer without tools
+    pub completed: bool,
+}
+
+fn tool(name: &str, description: &str, parameters: serde_json::Value) -> ToolDefinition {
+    ToolDefinition {
+        kind: "function".to_string(),
+        function: ToolFunction {
+            name: name.to_string(),
+            description: description.to_string(),
+            parameters,
+        },
+    }
+}
+
+fn path_only_schema(description: &str) -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "path": { "type": "string", "description": description }
+        },
+        "required": ["path"]
+    })
+}
+
+// The filesystem module's functions, advertised to the model as tools
+pub fn filesystem_tools() -> Vec<ToolDefinition> {
+    vec![
+        tool(
+            "read_directory",
+            "List the files and folders in a directory",
+            path_only_schema("Absolute path of the directory"),
+        ),
+        tool(
+            "read_file",
+            "Read the text content of a file",
+            path_only_schema("Absolute path of the file"),
+        ),
+        tool(
+            "write_file",
+            "Write text content to a file, replacing it if it exists",
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string", "description": "Absolute path of the file" },
+                    "content": { "type": "string", "description": "Full content to write" }
+                },
+                "required": ["path", "content"]
+            }),
+        ),
+        tool(
+            "create_directory",
+            "Create a directory, including any missing parent directories",
+            path_only_schema("Absolute path of the directory"),
+        ),
+        tool(
+            "delete_file",
+            "Delete a file, or a directory and everything in it",
+            path_only_schema("Absolute path of the file or directory"),
+        ),
+    ]
+}
+
+// Some models send the arguments as a JSON-encoded string instead of an object
+fn parse_arguments(arguments: &serde_json::Value) -> Result<serde_json::Value, String> {
+    match arguments {
+        serde_json::Value::String(raw) => serde_json::from_str(raw)
+            .map_err(|e| format!("Invalid tool arguments: {}", e)),
+        other => Ok(other.clone()),
+    }
+}
+
+fn string_arg(arguments: &serde_json::Value, name: &str) -> Result<String, String> {
+    arguments
+        .get(name)
+        .and_then(|v| v.as_str())
+        .map(|v| v.to_string())
+        .ok_or_else(|| format!("Missing string argument '{}'", name))
+}
+
+// Filesystem calls go through the permission policy on behalf of `agent_id`
+pub fn execute_tool(call: &ToolCall, policy: &PermissionPolicy, agent_id: &str) -> Result<String, String> {
+    let agent_id = Some(agent_id);
+    let arguments = parse_arguments(&call.function.arguments)?;
+    let path = string_arg(&arguments, "path")?;
+
+    let result = match call.function.name.as_str() {
+        "read_directory" => filesystem::read_directory(policy, agent_id, &path)
+            .map(|files| serde_json::to_string_pretty(&files).unwrap_or_default()),
+        "read_file" => filesystem::read_file(policy, agent_id, &path),
+        "write_file" => {
+            let content = string_arg(&arguments, "content")?;
+            filesystem::write_file(policy, agent_id, &path, &content)
+                .map(|_| format!("Wrote {} bytes to {}", content.len(), path))
+        }
+        "create_directory" => filesystem::create_directory(policy, agent_id, &path)
+            .map(|_| format!("Created directory {}", path)),
+        "delete_file" => filesystem::delete_file(policy, agent_id, &path)
+            .map(|_| format!("Deleted {}", path)),
+        other => return Err(format!("Unknown tool '{}'", other)),
+    };
+
+    result.map_err(|e| e.to_string())
+}
+
+pub fn truncate_output(output: &str, max_chars: usize) -> String {
+    match output.char_indices().nth(max_chars) {
+        Some((idx, _)) => format!("{}\n... [truncated]", &output[..idx]),
+        None => output.to_string(),
+    }
+}
+
+// Runs the model with the filesystem tools until it answers without calling a tool.
+// After `max_steps` rounds of tool calls the model is asked once more, without tools,
+// for a final answer. `on_step` is called after every executed tool call.
+pub async fn run_agent_loop<F>(
+    agent_id: &str,
+    provider: &dyn LlmProvider,
+    model: &str,
+    mut messages: Vec<ChatMessage>,
+    params: &GenerationParams,
+    policy: &PermissionPolicy,
+    max_steps: usize,
+    mut on_step: F,
+) -> Result<AgentRun, AppError>
+where
+    F: FnMut(&AgentStep),
+{
+    let tools = filesystem_tools();
+    let mut steps: Vec<AgentStep> = Vec::new();
+
+    for _ in 0..max_steps {
+        let reply = provider.chat(model, messages.clone(), &tools, params).await?;
+        if reply.tool_calls.is_empty() {
+            return Ok(AgentRun {
+                response: reply.content,
+                steps,
+                completed: true,
+            });
+        }
+
+        let tool_calls = reply.tool_calls.clone();
+        messages.push(reply);
+
+        for call in &tool_calls {
+            let result = execute_tool(call, policy, agent_id);
+            let step = AgentStep {
+                step: steps.len() + 1,
+                tool: call.function.name.clone(),
+                arguments: call.function.arguments.clone(),
+                output: match &result {
+                    Ok(output) => truncate_output(output, MAX_TOOL_OUTPUT_CHARS),
+                    Err(e) => format!("Error: {}", e),
+                },
+                success: result.is_ok(),
+            };
+            on_step(&step);
+
+            let mut tool_message = ChatMessage::new("tool", &step.output);
+            tool_message.tool_name = Some(step.tool.clone());
+            tool_message.tool_call_id = call.id.clone();
+            messages.push(tool_message);
+            steps.push(step);
+        }
+    }
+
+    messages.push(ChatMessage::new(
+        "user",
+        "The tool step limit was reached. Give your final answer using the information gathered so far.",
+    ));
+    let reply = provider.chat(model, messages, &[], params).await?;
+    Ok(AgentRun {
+        response: reply.content,
+        steps,
+        completed: false,
+    })
+}
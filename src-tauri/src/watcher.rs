@@ -0,0 +1,178 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::Notify;
+
+// What a FileWatch task watches, as stored on the task
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchSpec {
+    pub path: PathBuf,
+    pub patterns: Vec<String>,
+    pub recursive: bool,
+    pub debounce: Duration,
+}
+
+// Changes seen for one task since its last run
+struct PendingChanges {
+    paths: BTreeSet<PathBuf>,
+    last_event: Instant,
+    debounce: Duration,
+}
+
+impl PendingChanges {
+    fn ready_at(&self) -> Instant {
+        self.last_event + self.debounce
+    }
+}
+
+struct ActiveWatch {
+    spec: WatchSpec,
+    // Dropping the watcher stops it
+    _watcher: RecommendedWatcher,
+}
+
+// Filesystem watchers for FileWatch tasks. Matching changes are collected per task and
+// released once no new change has arrived for the task's debounce period.
+pub struct FileWatcher {
+    watches: Mutex<HashMap<String, ActiveWatch>>,
+    pending: Arc<Mutex<HashMap<String, PendingChanges>>>,
+    // Signalled on every matching change so waiters can recompute their deadline
+    changed: Arc<Notify>,
+}
+
+impl FileWatcher {
+    pub fn new() -> Self {
+        FileWatcher {
+            watches: Mutex::new(HashMap::new()),
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            changed: Arc::new(Notify::new()),
+        }
+    }
+
+    pub fn validate_patterns(patterns: &[String]) -> Result<(), String> {
+        build_glob_set(patterns).map(|_| ())
+    }
+
+    // Starts, restarts or stops watchers so exactly the given tasks are watched
+    pub fn sync(&self, specs: HashMap<String, WatchSpec>) -> Result<(), String> {
+        let mut watches = self.watches.lock().unwrap();
+        watches.retain(|task_id, active| specs.get(task_id) == Some(&active.spec));
+        self.pending.lock().unwrap().retain(|task_id, _| specs.contains_key(task_id));
+
+        let mut errors = Vec::new();
+        for (task_id, spec) in specs {
+            if watches.contains_key(&task_id) {
+                continue;
+            }
+            match self.start(&task_id, &spec) {
+                Ok(watcher) => {
+                    watches.insert(task_id, ActiveWatch { spec, _watcher: watcher });
+                }
+                Err(e) => errors.push(format!("{}: {}", task_id, e)),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Failed to watch: {}", errors.join("; ")))
+        }
+    }
+
+    fn start(&self, task_id: &str, spec: &WatchSpec) -> Result<RecommendedWatcher, String> {
+        let globs = build_glob_set(&spec.patterns)?;
+        let root = spec.path.clone();
+        let task_id = task_id.to_string();
+        let debounce = spec.debounce;
+        let pending = self.pending.clone();
+        let changed = self.changed.clone();
+
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    eprintln!("File watch error for task {}: {}", task_id, e);
+                    return;
+                }
+            };
+            if matches!(event.kind, EventKind::Access(_)) {
+                return;
+            }
+
+            let matching: Vec<PathBuf> = event
+                .paths
+                .into_iter()
+                .filter(|path| matches_patterns(&globs, &root, path))
+                .collect();
+            if matching.is_empty() {
+                return;
+            }
+
+            let mut pending = pending.lock().unwrap();
+            let entry = pending.entry(task_id.clone()).or_insert_with(|| PendingChanges {
+                paths: BTreeSet::new(),
+                last_event: Instant::now(),
+                debounce,
+            });
+            entry.paths.extend(matching);
+            entry.last_event = Instant::now();
+            changed.notify_one();
+        })
+        .map_err(|e| format!("Failed to create file watcher: {}", e))?;
+
+        let mode = if spec.recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+        watcher
+            .watch(&spec.path, mode)
+            .map_err(|e| format!("Failed to watch {}: {}", spec.path.display(), e))?;
+        Ok(watcher)
+    }
+
+    // Takes the changed paths of a task whose debounce period has passed
+    pub fn take_ready(&self, task_id: &str) -> Option<Vec<PathBuf>> {
+        let mut pending = self.pending.lock().unwrap();
+        let ready = pending.get(task_id).map_or(false, |p| p.ready_at() <= Instant::now());
+        if ready {
+            pending.remove(task_id).map(|p| p.paths.into_iter().collect())
+        } else {
+            None
+        }
+    }
+
+    // Pushes a task's pending changes back by its debounce period, e.g. while it is still running
+    pub fn postpone(&self, task_id: &str) {
+        if let Some(changes) = self.pending.lock().unwrap().get_mut(task_id) {
+            changes.last_event = Instant::now();
+        }
+    }
+
+    // When the earliest pending batch of changes becomes ready
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.lock().unwrap().values().map(|p| p.ready_at()).min()
+    }
+
+    pub async fn changed(&self) {
+        self.changed.notified().await
+    }
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|e| format!("Invalid watch pattern '{}': {}", pattern, e))?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|e| format!("Invalid watch patterns: {}", e))
+}
+
+// Patterns match the path relative to the watched directory; no patterns matches everything
+fn matches_patterns(globs: &GlobSet, root: &Path, path: &Path) -> bool {
+    if globs.is_empty() {
+        return true;
+    }
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let candidate = relative.to_string_lossy().replace('\\', "/");
+    globs.is_match(&candidate)
+}
@@ -0,0 +1,411 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::time::Instant;
+use crate::error::AppError;
+use crate::template::{self, Context, Value};
+
+// A set of prompt steps run in dependency order. Step prompts are templates that can use
+// `{input}` (the task's rendered prompt) and `{steps.<id>.output}` for any step they
+// depend on, directly or through other steps.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Workflow {
+    pub steps: Vec<WorkflowStep>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowStep {
+    pub id: String,
+    // Defaults to the task's agent
+    #[serde(default)]
+    pub agent_id: Option<String>,
+    pub prompt_template: String,
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    #[serde(default)]
+    pub on_failure: FailurePolicy,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum FailurePolicy {
+    Halt,                        // stop the workflow; remaining steps are skipped
+    Continue,                    // skip this step's dependents, keep running the rest
+    Fallback { step_id: String }, // run `step_id` instead; its output stands in for this step's
+}
+
+impl Default for FailurePolicy {
+    fn default() -> Self {
+        FailurePolicy::Halt
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepResult {
+    pub step_id: String,
+    pub agent_id: String,
+    pub prompt: String,
+    pub response: String,
+    pub success: bool,
+    pub error: Option<String>,
+    pub skipped: bool,
+    pub duration_ms: u64,
+    // Set on a failed step whose fallback was run
+    #[serde(default)]
+    pub fallback_step: Option<String>,
+}
+
+pub struct WorkflowRun {
+    // Output of the steps nothing depends on, in step order
+    pub response: String,
+    pub steps: Vec<StepResult>,
+    // First unrecovered step error, if any
+    pub error: Option<String>,
+    // What made the step behind `error` fail, if it got as far as calling the model
+    pub cause: Option<AppError>,
+}
+
+impl Workflow {
+    // `variables` are the template variables step prompts may use
+    pub fn validate(&self, variables: &[&str]) -> Result<(), String> {
+        for step in &self.steps {
+            template::validate(&step.prompt_template, variables)
+                .map_err(|e| format!("Invalid workflow: prompt of step '{}': {}", step.id, e))?;
+        }
+        self.execution_order().map(|_| ())
+    }
+
+    // Steps reachable as fallbacks; they only run when the step they cover fails
+    fn fallback_ids(&self) -> HashSet<&str> {
+        self.steps
+            .iter()
+            .filter_map(|step| match &step.on_failure {
+                FailurePolicy::Fallback { step_id } => Some(step_id.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Regular (non-fallback) steps in dependency order, after checking ids, references
+    // and fallbacks and rejecting cycles
+    fn execution_order(&self) -> Result<Vec<&WorkflowStep>, String> {
+        if self.steps.is_empty() {
+            return Err("Invalid workflow: it has no steps".to_string());
+        }
+
+        let mut by_id: HashMap<&str, &WorkflowStep> = HashMap::new();
+        for step in &self.steps {
+            if step.id.is_empty() {
+                return Err("Invalid workflow: every step needs an id".to_string());
+            }
+            if by_id.insert(step.id.as_str(), step).is_some() {
+                return Err(format!("Invalid workflow: duplicate step id '{}'", step.id));
+            }
+        }
+
+        for step in &self.steps {
+            for (i, dep) in step.depends_on.iter().enumerate() {
+                if !by_id.contains_key(dep.as_str()) {
+                    return Err(format!("Invalid workflow: step '{}' depends on unknown step '{}'", step.id, dep));
+                }
+                // The ordering below counts each listed dependency once
+                if step.depends_on[..i].contains(dep) {
+                    return Err(format!("Invalid workflow: step '{}' lists dependency '{}' twice", step.id, dep));
+                }
+            }
+        }
+
+        let fallbacks = self.fallback_ids();
+        for step in &self.steps {
+            if let FailurePolicy::Fallback { step_id } = &step.on_failure {
+                let fallback = by_id.get(step_id.as_str()).ok_or_else(|| {
+                    format!("Invalid workflow: step '{}' falls back to unknown step '{}'", step.id, step_id)
+                })?;
+                if fallback.id == step.id {
+                    return Err(format!("Invalid workflow: step '{}' falls back to itself", step.id));
+                }
+                // The fallback runs in place of the failed step, so it may only need what that step had
+                if let Some(dep) = fallback.depends_on.iter().find(|dep| !step.depends_on.contains(dep)) {
+                    return Err(format!(
+                        "Invalid workflow: fallback step '{}' depends on '{}', which step '{}' does not depend on",
+                        fallback.id, dep, step.id
+                    ));
+                }
+            }
+            if fallbacks.contains(step.id.as_str()) {
+                if let Some(dependent) = self.steps.iter().find(|s| s.depends_on.contains(&step.id)) {
+                    return Err(format!(
+                        "Invalid workflow: step '{}' depends on fallback step '{}'; depend on the step it replaces instead",
+                        dependent.id, step.id
+                    ));
+                }
+            }
+        }
+
+        // Kahn's algorithm; whatever is left over sits on a cycle
+        let mut remaining: HashMap<&str, usize> = self
+            .steps
+            .iter()
+            .map(|step| (step.id.as_str(), step.depends_on.len()))
+            .collect();
+        let mut ready: VecDeque<&str> = self
+            .steps
+            .iter()
+            .filter(|step| step.depends_on.is_empty())
+            .map(|step| step.id.as_str())
+            .collect();
+        let mut order = Vec::new();
+
+        while let Some(id) = ready.pop_front() {
+            order.push(by_id[id]);
+            for step in &self.steps {
+                if step.depends_on.iter().any(|dep| dep == id) {
+                    let count = remaining.get_mut(step.id.as_str()).unwrap();
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.push_back(step.id.as_str());
+                    }
+                }
+            }
+        }
+
+        if order.len() < self.steps.len() {
+            let mut cycle: Vec<&str> = self
+                .steps
+                .iter()
+                .map(|step| step.id.as_str())
+                .filter(|id| !order.iter().any(|step| step.id == *id))
+                .collect();
+            cycle.sort();
+            return Err(format!("Invalid workflow: dependency cycle between steps {}", cycle.join(", ")));
+        }
+
+        for step in &self.steps {
+            let ancestors = ancestors(&by_id, step);
+            let paths = template::variable_paths(&step.prompt_template)
+                .map_err(|e| format!("Invalid workflow: prompt of step '{}': {}", step.id, e))?;
+            let references = paths
+                .into_iter()
+                .filter(|path| path[0] == "steps" && path.len() > 1)
+                .map(|path| path[1].clone());
+            for reference in references {
+                if !ancestors.contains(reference.as_str()) {
+                    return Err(format!(
+                        "Invalid workflow: step '{}' uses the output of '{}' without depending on it",
+                        step.id, reference
+                    ));
+                }
+            }
+        }
+
+        Ok(order.into_iter().filter(|step| !fallbacks.contains(step.id.as_str())).collect())
+    }
+
+    // `execute(agent_id, prompt)` runs one step. Step prompts are rendered with `context`
+    // plus the outputs of the steps that have finished.
+    pub async fn run<F, Fut>(&self, default_agent_id: &str, context: &Context, execute: F) -> WorkflowRun
+    where
+        F: Fn(String, String) -> Fut,
+        Fut: Future<Output = Result<String, AppError>>,
+    {
+        let order = match self.execution_order() {
+            Ok(order) => order,
+            Err(e) => {
+                return WorkflowRun {
+                    response: String::new(),
+                    steps: Vec::new(),
+                    error: Some(e),
+                    cause: None,
+                }
+            }
+        };
+        let by_id: HashMap<&str, &WorkflowStep> = self.steps.iter().map(|s| (s.id.as_str(), s)).collect();
+
+        let mut outputs: HashMap<String, String> = HashMap::new();
+        // Steps that failed without recovering; their dependents are skipped
+        let mut failed: HashSet<String> = HashSet::new();
+        let mut results: Vec<StepResult> = Vec::new();
+        let mut error: Option<String> = None;
+        let mut cause: Option<AppError> = None;
+        let mut halted = false;
+
+        for step in order {
+            let blocked_by = step.depends_on.iter().find(|dep| failed.contains(*dep));
+            if halted || blocked_by.is_some() {
+                let reason = match blocked_by {
+                    Some(dep) => format!("Skipped because step '{}' failed", dep),
+                    None => "Skipped because the workflow was halted".to_string(),
+                };
+                failed.insert(step.id.clone());
+                results.push(skipped_step(step, default_agent_id, reason));
+                continue;
+            }
+
+            let (mut result, step_error) = run_step(step, default_agent_id, context, &outputs, &execute).await;
+            if result.success {
+                outputs.insert(step.id.clone(), result.response.clone());
+                results.push(result);
+                continue;
+            }
+
+            match &step.on_failure {
+                FailurePolicy::Fallback { step_id } => {
+                    let fallback = by_id[step_id.as_str()];
+                    result.fallback_step = Some(fallback.id.clone());
+                    results.push(result);
+
+                    let (fallback_result, fallback_error) =
+                        run_step(fallback, default_agent_id, context, &outputs, &execute).await;
+                    if fallback_result.success {
+                        outputs.insert(step.id.clone(), fallback_result.response.clone());
+                    } else {
+                        // A failed fallback stops the workflow
+                        if error.is_none() {
+                            error = Some(format!(
+                                "Step '{}' and its fallback '{}' failed: {}",
+                                step.id,
+                                fallback.id,
+                                fallback_result.error.clone().unwrap_or_default()
+                            ));
+                            cause = fallback_error;
+                        }
+                        failed.insert(step.id.clone());
+                        halted = true;
+                    }
+                    results.push(fallback_result);
+                }
+                policy => {
+                    if error.is_none() {
+                        error = Some(format!("Step '{}' failed: {}", step.id, result.error.clone().unwrap_or_default()));
+                        cause = step_error;
+                    }
+                    failed.insert(step.id.clone());
+                    halted = matches!(policy, FailurePolicy::Halt);
+                    results.push(result);
+                }
+            }
+        }
+
+        let fallbacks = self.fallback_ids();
+        let response = self
+            .steps
+            .iter()
+            .filter(|step| !fallbacks.contains(step.id.as_str()))
+            .filter(|step| !self.steps.iter().any(|s| s.depends_on.contains(&step.id)))
+            .filter_map(|step| outputs.get(&step.id).cloned())
+            .collect::<Vec<String>>()
+            .join("\n\n");
+
+        WorkflowRun {
+            response,
+            steps: results,
+            error,
+            cause,
+        }
+    }
+}
+
+async fn run_step<F, Fut>(
+    step: &WorkflowStep,
+    default_agent_id: &str,
+    context: &Context,
+    outputs: &HashMap<String, String>,
+    execute: &F,
+) -> (StepResult, Option<AppError>)
+where
+    F: Fn(String, String) -> Fut,
+    Fut: Future<Output = Result<String, AppError>>,
+{
+    let agent_id = step.agent_id.clone().unwrap_or_else(|| default_agent_id.to_string());
+    let started = Instant::now();
+
+    let mut context = context.clone();
+    context.set(
+        "steps",
+        Value::Map(
+            outputs
+                .iter()
+                .map(|(id, output)| (id.clone(), Value::map(vec![("output", Value::Str(output.clone()))])))
+                .collect(),
+        ),
+    );
+    let (prompt, result) = match template::render(&step.prompt_template, &context) {
+        Ok(prompt) => {
+            let result = execute(agent_id.clone(), prompt.clone()).await;
+            (prompt, result)
+        }
+        Err(e) => (
+            step.prompt_template.clone(),
+            Err(AppError::invalid(format!("Failed to render prompt template: {}", e))),
+        ),
+    };
+    let (response, error) = match result {
+        Ok(response) => (response, None),
+        Err(e) => (String::new(), Some(e)),
+    };
+    let step_result = StepResult {
+        step_id: step.id.clone(),
+        agent_id,
+        prompt,
+        success: error.is_none(),
+        response,
+        error: error.as_ref().map(|e| e.to_string()),
+        skipped: false,
+        duration_ms: started.elapsed().as_millis() as u64,
+        fallback_step: None,
+    };
+    (step_result, error)
+}
+
+fn skipped_step(step: &WorkflowStep, default_agent_id: &str, reason: String) -> StepResult {
+    StepResult {
+        step_id: step.id.clone(),
+        agent_id: step.agent_id.clone().unwrap_or_else(|| default_agent_id.to_string()),
+        prompt: String::new(),
+        response: String::new(),
+        success: false,
+        error: Some(reason),
+        skipped: true,
+        duration_ms: 0,
+        fallback_step: None,
+    }
+}
+
+// Ids of every step `step` depends on, directly or transitively
+fn ancestors<'a>(by_id: &HashMap<&'a str, &'a WorkflowStep>, step: &'a WorkflowStep) -> HashSet<&'a str> {
+    let mut seen = HashSet::new();
+    let mut stack: Vec<&str> = step.depends_on.iter().map(|d| d.as_str()).collect();
+    while let Some(id) = stack.pop() {
+        if seen.insert(id) {
+            if let Some(dep) = by_id.get(id) {
+                stack.extend(dep.depends_on.iter().map(|d| d.as_str()));
+            }
+        }
+    }
+    seen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workflow(steps: serde_json::Value) -> Workflow {
+        serde_json::from_value(serde_json::json!({ "steps": steps })).unwrap()
+    }
+
+    #[test]
+    fn duplicate_dependency_is_reported_as_such() {
+        let repeated = workflow(serde_json::json!([
+            { "id": "a", "prompt_template": "fetch" },
+            { "id": "b", "prompt_template": "sum up", "depends_on": ["a", "a"] },
+        ]));
+        let error = repeated.validate(&[]).unwrap_err();
+        assert!(error.contains("step 'b' lists dependency 'a' twice"), "{}", error);
+
+        let cyclic = workflow(serde_json::json!([
+            { "id": "a", "prompt_template": "fetch", "depends_on": ["b"] },
+            { "id": "b", "prompt_template": "sum up", "depends_on": ["a"] },
+        ]));
+        assert!(cyclic.validate(&[]).unwrap_err().contains("cycle"));
+    }
+}
@@ -1,6 +1,11 @@
 import { useState, useEffect } from 'react';
 import { invoke } from '@tauri-apps/api/tauri';
-import { X, Plus, Edit2, Trash2, Save, AlertCircle } from 'lucide-react';
+import { listen } from '@tauri-apps/api/event';
+import { X, Plus, Edit2, Trash2, Save, AlertCircle, Download } from 'lucide-react';
+
+type ProviderConfig =
+  | { type: 'Ollama'; base_url?: string }
+  | { type: 'OpenAiCompatible'; base_url: string; api_key?: string };
 
 interface Agent {
   id: string;
@@ -9,6 +14,14 @@ interface Agent {
   description: string;
   capabilities: string[];
   model: string;
+  provider?: ProviderConfig;
+}
+
+interface PullProgress {
+  model: string;
+  status: string;
+  total?: number;
+  completed?: number;
 }
 
 interface AgentManagerProps {
@@ -22,6 +35,7 @@ export default function AgentManager({ onClose, onAgentsUpdated }: AgentManagerP
   const [editingAgent, setEditingAgent] = useState<Agent | null>(null);
   const [isCreating, setIsCreating] = useState(false);
   const [error, setError] = useState<string | null>(null);
+  const [pullProgress, setPullProgress] = useState<PullProgress | null>(null);
   const [formData, setFormData] = useState<Agent>({
     id: '',
     name: '',
@@ -36,6 +50,8 @@ export default function AgentManager({ onClose, onAgentsUpdated }: AgentManagerP
     loadModels();
   }, []);
 
+  const providerType = formData.provider?.type ?? 'Ollama';
+
   const loadAgents = async () => {
     try {
       const response = await invoke('list_agents') as { success: boolean; data: Agent[] };
@@ -47,9 +63,17 @@ export default function AgentManager({ onClose, onAgentsUpdated }: AgentManagerP
     }
   };
 
-  const loadModels = async () => {
+  // Lists the models of the given provider, or of the local Ollama when none is given
+  const loadModels = async (provider?: ProviderConfig) => {
     try {
-      const response = await invoke('list_ollama_models') as { success: boolean; data: any };
+      const response = (provider
+        ? await invoke('list_provider_models', { provider })
+        : await invoke('list_ollama_models')) as { success: boolean; data: any; error?: string };
+      if (!response.success) {
+        setAvailableModels([]);
+        if (provider) setError(response.error || 'Failed to list models');
+        return;
+      }
       if (response.success && response.data) {
         // Extract model names from the response (could be strings or objects with 'name' property)
         const models = Array.isArray(response.data) 
@@ -83,6 +107,7 @@ export default function AgentManager({ onClose, onAgentsUpdated }: AgentManagerP
   const handleCreateAgent = () => {
     setIsCreating(true);
     setEditingAgent(null);
+    loadModels();
     setFormData({
       id: `agent_${Date.now()}`,
       name: '',
@@ -98,6 +123,44 @@ export default function AgentManager({ onClose, onAgentsUpdated }: AgentManagerP
     setEditingAgent(agent);
     setIsCreating(false);
     setFormData({ ...agent });
+    loadModels(agent.provider);
+  };
+
+  // Pulls the model typed in the form onto the local Ollama, showing progress as it downloads
+  const handlePullModel = async () => {
+    const model = formData.model.trim();
+    if (!model) return;
+    setError(null);
+    setPullProgress({ model, status: 'starting' });
+    const unlisten = await listen<PullProgress>('model-pull-progress', event => {
+      if (event.payload.model === model) setPullProgress(event.payload);
+    });
+    try {
+      const response = await invoke('pull_ollama_model', { model }) as { success: boolean; error?: string };
+      if (response.success) {
+        await loadModels(formData.provider);
+      } else {
+        setError(response.error || 'Failed to pull model');
+      }
+    } catch (err) {
+      setError(`Error pulling model: ${err}`);
+    } finally {
+      unlisten();
+      setPullProgress(null);
+    }
+  };
+
+  const handleProviderTypeChange = (type: ProviderConfig['type']) => {
+    const provider: ProviderConfig = type === 'Ollama'
+      ? { type: 'Ollama' }
+      : { type: 'OpenAiCompatible', base_url: 'http://localhost:8080/v1' };
+    setFormData({ ...formData, provider });
+    loadModels(provider);
+  };
+
+  const handleProviderFieldChange = (field: 'base_url' | 'api_key', value: string) => {
+    const provider = { ...(formData.provider ?? { type: 'Ollama' }), [field]: value || undefined } as ProviderConfig;
+    setFormData({ ...formData, provider });
   };
 
   const handleDeleteAgent = async (agentId: string) => {
@@ -124,6 +187,10 @@ export default function AgentManager({ onClose, onAgentsUpdated }: AgentManagerP
       setError('Model is required');
       return;
     }
+    if (formData.provider?.type === 'OpenAiCompatible' && !formData.provider.base_url?.trim()) {
+      setError('Server URL is required');
+      return;
+    }
 
     let updatedAgents: Agent[];
     if (isCreating) {
@@ -205,7 +272,10 @@ export default function AgentManager({ onClose, onAgentsUpdated }: AgentManagerP
                             </span>
                           ))}
                         </div>
-                        <p className="text-gray-500 text-xs mt-2">Model: {agent.model}</p>
+                        <p className="text-gray-500 text-xs mt-2">
+                          Model: {agent.model}
+                          {agent.provider?.type === 'OpenAiCompatible' && ` • ${agent.provider.base_url}`}
+                        </p>
                       </div>
                       <div className="flex gap-2">
                         <button
@@ -300,6 +370,40 @@ export default function AgentManager({ onClose, onAgentsUpdated }: AgentManagerP
                 />
               </div>
 
+              <div>
+                <label className="block text-sm font-medium text-gray-300 mb-2">
+                  Provider
+                </label>
+                <select
+                  value={providerType}
+                  onChange={e => handleProviderTypeChange(e.target.value as ProviderConfig['type'])}
+                  className="w-full px-4 py-2 bg-white/5 border border-purple-500/30 rounded-lg text-white focus:border-purple-500 focus:outline-none"
+                >
+                  <option value="Ollama" className="bg-gray-900">Ollama</option>
+                  <option value="OpenAiCompatible" className="bg-gray-900">OpenAI-compatible (llama.cpp, vLLM)</option>
+                </select>
+                <div className="grid grid-cols-2 gap-3 mt-3">
+                  <input
+                    type="text"
+                    value={formData.provider?.base_url ?? ''}
+                    onChange={e => handleProviderFieldChange('base_url', e.target.value)}
+                    onBlur={() => loadModels(formData.provider)}
+                    className="w-full px-4 py-2 bg-white/5 border border-purple-500/30 rounded-lg text-white focus:border-purple-500 focus:outline-none"
+                    placeholder={providerType === 'Ollama' ? 'http://localhost:11434' : 'http://localhost:8080/v1'}
+                  />
+                  {formData.provider?.type === 'OpenAiCompatible' && (
+                    <input
+                      type="password"
+                      value={formData.provider.api_key ?? ''}
+                      onChange={e => handleProviderFieldChange('api_key', e.target.value)}
+                      onBlur={() => loadModels(formData.provider)}
+                      className="w-full px-4 py-2 bg-white/5 border border-purple-500/30 rounded-lg text-white focus:border-purple-500 focus:outline-none"
+                      placeholder="API key (optional)"
+                    />
+                  )}
+                </div>
+              </div>
+
               <div>
                 <label className="block text-sm font-medium text-gray-300 mb-2">
                   Model *
@@ -322,6 +426,32 @@ export default function AgentManager({ onClose, onAgentsUpdated }: AgentManagerP
                     ))
                   )}
                 </select>
+                {providerType === 'Ollama' && !formData.provider?.base_url && (
+                  <div className="flex gap-3 mt-3">
+                    <input
+                      type="text"
+                      value={formData.model}
+                      onChange={e => setFormData({ ...formData, model: e.target.value })}
+                      className="flex-1 px-4 py-2 bg-white/5 border border-purple-500/30 rounded-lg text-white focus:border-purple-500 focus:outline-none"
+                      placeholder="Model to download, e.g. llama3.2"
+                    />
+                    <button
+                      type="button"
+                      onClick={handlePullModel}
+                      disabled={pullProgress !== null}
+                      className="px-4 py-2 bg-white/5 hover:bg-white/10 disabled:opacity-50 border border-purple-500/30 rounded-lg font-medium text-white flex items-center gap-2 transition-colors"
+                    >
+                      <Download className="w-4 h-4" />
+                      Pull
+                    </button>
+                  </div>
+                )}
+                {pullProgress && (
+                  <div className="mt-2 text-sm text-gray-400">
+                    {pullProgress.status}
+                    {pullProgress.total ? ` (${Math.round(((pullProgress.completed ?? 0) / pullProgress.total) * 100)}%)` : ''}
+                  </div>
+                )}
               </div>
 
               <div className="flex gap-3 pt-4">
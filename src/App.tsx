@@ -1,10 +1,12 @@
 import React, { useState, useEffect, useRef } from 'react';
 import { Send, Bot, User, Zap, AlertCircle, Folder, FileText, Sparkles, Settings, Brain, Code2, BarChart3 } from 'lucide-react';
 import { invoke } from '@tauri-apps/api/tauri';
+import { listen } from '@tauri-apps/api/event';
 import { open } from '@tauri-apps/api/dialog';
 import TaskScheduler from './TaskScheduler';
 import PermissionDialog from './PermissionDialog';
 import AgentManager from './AgentManager';
+import AppSettings from './AppSettings';
 
 interface Message {
   id: string;
@@ -23,6 +25,15 @@ interface Agent {
   model: string;
 }
 
+interface OllamaStatus {
+  running: boolean;
+  endpoint: string;
+  version?: string | null;
+  latency_ms?: number | null;
+  loaded_models: { name: string }[];
+  error?: { code: string; details?: any } | null;
+}
+
 function App() {
   const [messages, setMessages] = useState<Message[]>([]);
   const [input, setInput] = useState('');
@@ -30,6 +41,7 @@ function App() {
   const [agents, setAgents] = useState<Agent[]>([]);
   const [selectedAgent, setSelectedAgent] = useState<Agent | null>(null);
   const [ollamaRunning, setOllamaRunning] = useState(false);
+  const [ollamaStatus, setOllamaStatus] = useState<OllamaStatus | null>(null);
   const [workingDir, setWorkingDir] = useState<string>('');
   const [showSettings, setShowSettings] = useState(false);
   const [showScheduler, setShowScheduler] = useState(false);
@@ -48,22 +60,52 @@ function App() {
     checkOllama();
     loadAgents();
     loadModels();
+    loadStartupWarnings();
+
+    // The backend re-checks Ollama in the background and reports every change
+    const unlisten = listen<OllamaStatus>('ollama-status', event => applyOllamaStatus(event.payload));
+    return () => {
+      unlisten.then(stop => stop());
+    };
   }, []);
 
   useEffect(() => {
     messagesEndRef.current?.scrollIntoView({ behavior: 'smooth' });
   }, [messages]);
 
+  const applyOllamaStatus = (status: OllamaStatus) => {
+    setOllamaStatus(status);
+    setOllamaRunning(status.running);
+  };
+
   const checkOllama = async () => {
     try {
       const response: any = await invoke('check_ollama');
-      setOllamaRunning(response.data?.running || false);
+      if (response.data) {
+        applyOllamaStatus(response.data);
+      } else {
+        setOllamaRunning(false);
+      }
     } catch (error) {
       console.error('Failed to check Ollama:', error);
       setOllamaRunning(false);
     }
   };
 
+  // Data files that were damaged at startup and restored from backup (or reset)
+  const loadStartupWarnings = async () => {
+    try {
+      const response: any = await invoke('get_startup_warnings');
+      if (response.success && response.data) {
+        response.data.forEach((warning: { file: string; message: string; kind: 'damaged' | 'newer_format' }) => {
+          addSystemMessage(`⚠️ ${warning.message}`);
+        });
+      }
+    } catch (error) {
+      console.error('Failed to load startup warnings:', error);
+    }
+  };
+
   const loadAgents = async () => {
     try {
       const response: any = await invoke('list_agents');
@@ -241,6 +283,14 @@ function App() {
       if (response.success && response.data) {
         addAssistantMessage(response.data.message);
       } else {
+        // Failures carry { code, details } in data
+        switch (response.data?.code) {
+          case 'OllamaUnreachable':
+            setOllamaRunning(false);
+            break;
+          case 'ModelNotFound':
+            throw new Error(`${response.error}. Pick another model or pull it first.`);
+        }
         throw new Error(response.error || 'Failed to get response');
       }
     } catch (error: any) {
@@ -250,6 +300,27 @@ function App() {
     }
   };
 
+  // Runs a filesystem command and, if the permission policy refuses it with a grant token,
+  // asks the user and retries once they allow it
+  const withPermission = (action: 'read' | 'list', path: string, run: () => Promise<any>) =>
+    run().then((response: any) => {
+      const token = response.data?.details?.grant_token;
+      if (response.success || response.data?.code !== 'PathDenied' || !token) {
+        return response;
+      }
+      return new Promise<any>((resolve) => {
+        setPermissionRequest({
+          action,
+          path,
+          onApprove: async () => {
+            setPermissionRequest(null);
+            await invoke('grant_permission', { token, always: false });
+            resolve(await run());
+          },
+        });
+      });
+    });
+
   const handleFileRead = async (fileName: string) => {
     // Use proper path separator for Windows
     const isWindows = workingDir.includes('\\');
@@ -261,35 +332,27 @@ function App() {
     
     console.log('Reading file:', { workingDir, fileName, separator, filePath });
     
-    return new Promise<void>((resolve) => {
-      setPermissionRequest({
-        action: 'read',
-        path: filePath,
-        onApprove: async () => {
-          setPermissionRequest(null);
-          try {
-            const fileResponse: any = await invoke('read_file_content', { path: filePath });
-            if (fileResponse.success) {
-              const context = `File content of ${fileName}:\n\n${fileResponse.data.content}`;
-              const response: any = await invoke('chat_with_agent', {
-                agentId: selectedAgent!.id,
-                message: `Analyze this file:\n\n${context}`,
-                modelOverride: selectedModel || null,
-              });
-
-              if (response.success && response.data) {
-                addAssistantMessage(response.data.message);
-              }
-            } else {
-              addAssistantMessage(`Error reading file: ${fileResponse.error}`);
-            }
-          } catch (error: any) {
-            addAssistantMessage(`Error: ${error.message}`);
-          }
-          resolve();
-        },
-      });
-    });
+    try {
+      const fileResponse: any = await withPermission('read', filePath, () =>
+        invoke('read_file_content', { path: filePath })
+      );
+      if (fileResponse.success) {
+        const context = `File content of ${fileName}:\n\n${fileResponse.data.content}`;
+        const response: any = await invoke('chat_with_agent', {
+          agentId: selectedAgent!.id,
+          message: `Analyze this file:\n\n${context}`,
+          modelOverride: selectedModel || null,
+        });
+
+        if (response.success && response.data) {
+          addAssistantMessage(response.data.message);
+        }
+      } else {
+        addAssistantMessage(`Error reading file: ${fileResponse.error}`);
+      }
+    } catch (error: any) {
+      addAssistantMessage(`Error: ${error.message}`);
+    }
   };
 
   const handleDirectoryList = async (dirPath: string) => {
@@ -300,30 +363,22 @@ function App() {
     // Handle absolute paths
     const isAbsolute = dirPath.includes(':') || dirPath.startsWith('\\') || dirPath.startsWith('/');
     const fullPath = (isAbsolute || dirPath === workingDir) ? dirPath : `${workingDir}${separator}${dirPath}`;
-    return new Promise<void>((resolve) => {
-      setPermissionRequest({
-        action: 'list',
-        path: fullPath,
-        onApprove: async () => {
-          setPermissionRequest(null);
-          try {
-            const dirResponse: any = await invoke('read_directory', { path: fullPath });
-            if (dirResponse.success && dirResponse.data) {
-              const files = dirResponse.data.files;
-              const fileList = files.map((f: any) => 
-                `${f.is_dir ? '📁' : '📄'} ${f.name}`
-              ).join('\n');
-              addSystemMessage(`Contents of ${dirPath}:\n\n${fileList}`);
-            } else {
-              addSystemMessage(`Error listing directory: ${dirResponse.error}`);
-            }
-          } catch (error: any) {
-            addSystemMessage(`Error: ${error.message}`);
-          }
-          resolve();
-        },
-      });
-    });
+    try {
+      const dirResponse: any = await withPermission('list', fullPath, () =>
+        invoke('read_directory', { path: fullPath })
+      );
+      if (dirResponse.success && dirResponse.data) {
+        const files = dirResponse.data.files;
+        const fileList = files.map((f: any) => 
+          `${f.is_dir ? '📁' : '📄'} ${f.name}`
+        ).join('\n');
+        addSystemMessage(`Contents of ${dirPath}:\n\n${fileList}`);
+      } else {
+        addSystemMessage(`Error listing directory: ${dirResponse.error}`);
+      }
+    } catch (error: any) {
+      addSystemMessage(`Error: ${error.message}`);
+    }
   };
 
   const handleSaveSession = async (sessionName: string) => {
@@ -382,6 +437,13 @@ function App() {
           <p className="text-gray-400 mb-8 text-lg">
             Please install and start Ollama to unleash the power of local AI agents.
           </p>
+          {ollamaStatus && (
+            <p className="text-sm text-gray-500 mb-8 break-words">
+              Checked {ollamaStatus.endpoint}
+              {ollamaStatus.error?.details?.reason && `: ${ollamaStatus.error.details.reason}`}
+              {ollamaStatus.error?.details?.message && `: ${ollamaStatus.error.details.message}`}
+            </p>
+          )}
           <div className="glass-effect rounded-2xl p-6 text-left mb-8">
             <p className="text-sm text-gray-300 mb-4 font-semibold">Quick Setup:</p>
             <ol className="text-sm text-gray-400 space-y-3 list-decimal list-inside">
@@ -411,6 +473,13 @@ function App() {
         <div className="p-6 border-b border-white/10 relative">
           <h1 className="text-3xl font-bold mb-2 gradient-text">AgentWorks</h1>
           <p className="text-sm text-gray-400">Your Local AI Agent Platform</p>
+          {ollamaStatus?.version && (
+            <p className="text-xs text-gray-500 mt-1">
+              Ollama {ollamaStatus.version} · {ollamaStatus.latency_ms} ms
+              {ollamaStatus.loaded_models.length > 0 &&
+                ` · loaded: ${ollamaStatus.loaded_models.map(m => m.name).join(', ')}`}
+            </p>
+          )}
         </div>
 
         {/* Working Directory */}
@@ -468,7 +537,10 @@ function App() {
               <div className="w-2 h-2 bg-green-500 rounded-full animate-pulse shadow-lg shadow-green-500/50"></div>
               <span className="text-gray-400">Ollama Active</span>
             </div>
-            <button className="p-2 rounded-lg hover:bg-white/10 transition-colors">
+            <button
+              onClick={() => setShowSettings(true)}
+              className="p-2 rounded-lg hover:bg-white/10 transition-colors"
+            >
               <Settings size={18} className="text-gray-400" />
             </button>
           </div>
@@ -653,6 +725,14 @@ function App() {
         />
       )}
       
+      {/* Settings Modal */}
+      {showSettings && (
+        <AppSettings
+          onClose={() => setShowSettings(false)}
+          onSaved={checkOllama}
+        />
+      )}
+      
       {/* Permission Dialog */}
       {permissionRequest && (
         <PermissionDialog
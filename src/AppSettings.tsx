@@ -0,0 +1,210 @@
+import { useState, useEffect } from 'react';
+import { invoke } from '@tauri-apps/api/tauri';
+import { X, Save, AlertCircle } from 'lucide-react';
+
+interface Settings {
+  ollama_url: string;
+  connect_timeout_seconds: number;
+  read_timeout_seconds: number;
+  auth_header?: { name: string; value: string } | null;
+  proxy?: { url: string; no_proxy?: string | null } | null;
+}
+
+interface AppSettingsProps {
+  onClose: () => void;
+  onSaved: () => void;
+}
+
+export default function AppSettings({ onClose, onSaved }: AppSettingsProps) {
+  const [settings, setSettings] = useState<Settings | null>(null);
+  const [error, setError] = useState<string | null>(null);
+
+  useEffect(() => {
+    loadSettings();
+  }, []);
+
+  const loadSettings = async () => {
+    try {
+      const response = await invoke('get_settings') as { success: boolean; data: Settings };
+      if (response.success && response.data) {
+        setSettings(response.data);
+      }
+    } catch (err) {
+      setError(`Failed to load settings: ${err}`);
+    }
+  };
+
+  const handleSubmit = async (e: React.FormEvent) => {
+    e.preventDefault();
+    if (!settings) return;
+    setError(null);
+
+    // Empty optional sections are sent as null so the backend clears them
+    const payload: Settings = {
+      ...settings,
+      auth_header: settings.auth_header?.name.trim() ? settings.auth_header : null,
+      proxy: settings.proxy?.url.trim()
+        ? { url: settings.proxy.url.trim(), no_proxy: settings.proxy.no_proxy?.trim() || null }
+        : null,
+    };
+
+    try {
+      const response = await invoke('update_settings', { settings: payload }) as { success: boolean; error?: string };
+      if (response.success) {
+        onSaved();
+        onClose();
+      } else {
+        setError(response.error || 'Failed to save settings');
+      }
+    } catch (err) {
+      setError(`Error saving settings: ${err}`);
+    }
+  };
+
+  const inputClass = 'w-full px-4 py-2 bg-white/5 border border-purple-500/30 rounded-lg text-white focus:border-purple-500 focus:outline-none';
+
+  return (
+    <div className="fixed inset-0 bg-black/50 backdrop-blur-sm flex items-center justify-center z-50 p-4">
+      <div className="bg-gradient-to-br from-gray-900/95 to-purple-900/95 rounded-2xl border border-purple-500/30 shadow-2xl max-w-2xl w-full max-h-[90vh] overflow-hidden">
+        {/* Header */}
+        <div className="flex items-center justify-between p-6 border-b border-purple-500/30">
+          <h2 className="text-2xl font-bold text-white">Settings</h2>
+          <button
+            onClick={onClose}
+            className="p-2 hover:bg-white/10 rounded-lg transition-colors"
+          >
+            <X className="w-5 h-5 text-gray-400" />
+          </button>
+        </div>
+
+        {/* Content */}
+        <div className="p-6 overflow-y-auto max-h-[calc(90vh-100px)]">
+          {error && (
+            <div className="mb-4 p-4 bg-red-500/20 border border-red-500/50 rounded-lg flex items-center gap-2 text-red-200">
+              <AlertCircle className="w-5 h-5" />
+              <span>{error}</span>
+            </div>
+          )}
+
+          {settings && (
+            <form onSubmit={handleSubmit} className="space-y-4">
+              <div>
+                <label className="block text-sm font-medium text-gray-300 mb-2">
+                  Ollama URL
+                </label>
+                <input
+                  type="text"
+                  value={settings.ollama_url}
+                  onChange={e => setSettings({ ...settings, ollama_url: e.target.value })}
+                  className={inputClass}
+                  placeholder="http://localhost:11434"
+                  required
+                />
+              </div>
+
+              <div className="grid grid-cols-2 gap-3">
+                <div>
+                  <label className="block text-sm font-medium text-gray-300 mb-2">
+                    Connect timeout <span className="text-gray-500">(seconds)</span>
+                  </label>
+                  <input
+                    type="number"
+                    min={1}
+                    value={settings.connect_timeout_seconds}
+                    onChange={e => setSettings({ ...settings, connect_timeout_seconds: Number(e.target.value) })}
+                    className={inputClass}
+                  />
+                </div>
+                <div>
+                  <label className="block text-sm font-medium text-gray-300 mb-2">
+                    Read timeout <span className="text-gray-500">(seconds)</span>
+                  </label>
+                  <input
+                    type="number"
+                    min={1}
+                    value={settings.read_timeout_seconds}
+                    onChange={e => setSettings({ ...settings, read_timeout_seconds: Number(e.target.value) })}
+                    className={inputClass}
+                  />
+                </div>
+              </div>
+
+              <div>
+                <label className="block text-sm font-medium text-gray-300 mb-2">
+                  Auth header <span className="text-gray-500">(sent with every Ollama request)</span>
+                </label>
+                <div className="grid grid-cols-2 gap-3">
+                  <input
+                    type="text"
+                    value={settings.auth_header?.name ?? ''}
+                    onChange={e => setSettings({
+                      ...settings,
+                      auth_header: { name: e.target.value, value: settings.auth_header?.value ?? '' },
+                    })}
+                    className={inputClass}
+                    placeholder="Authorization"
+                  />
+                  <input
+                    type="password"
+                    value={settings.auth_header?.value ?? ''}
+                    onChange={e => setSettings({
+                      ...settings,
+                      auth_header: { name: settings.auth_header?.name ?? '', value: e.target.value },
+                    })}
+                    className={inputClass}
+                    placeholder="Bearer ..."
+                  />
+                </div>
+              </div>
+
+              <div>
+                <label className="block text-sm font-medium text-gray-300 mb-2">
+                  Proxy <span className="text-gray-500">(localhost is always reached directly)</span>
+                </label>
+                <div className="grid grid-cols-2 gap-3">
+                  <input
+                    type="text"
+                    value={settings.proxy?.url ?? ''}
+                    onChange={e => setSettings({
+                      ...settings,
+                      proxy: { url: e.target.value, no_proxy: settings.proxy?.no_proxy },
+                    })}
+                    className={inputClass}
+                    placeholder="http://proxy.lan:3128"
+                  />
+                  <input
+                    type="text"
+                    value={settings.proxy?.no_proxy ?? ''}
+                    onChange={e => setSettings({
+                      ...settings,
+                      proxy: { url: settings.proxy?.url ?? '', no_proxy: e.target.value },
+                    })}
+                    className={inputClass}
+                    placeholder="No proxy for, e.g. ollama.lan,10.0.0.5"
+                  />
+                </div>
+              </div>
+
+              <div className="flex gap-3 pt-4">
+                <button
+                  type="submit"
+                  className="flex-1 px-4 py-2 bg-gradient-to-r from-purple-600 to-pink-600 hover:from-purple-700 hover:to-pink-700 rounded-lg font-medium text-white flex items-center justify-center gap-2 transition-all"
+                >
+                  <Save className="w-4 h-4" />
+                  Save Settings
+                </button>
+                <button
+                  type="button"
+                  onClick={onClose}
+                  className="px-6 py-2 bg-white/5 hover:bg-white/10 border border-purple-500/30 rounded-lg font-medium text-white transition-colors"
+                >
+                  Cancel
+                </button>
+              </div>
+            </form>
+          )}
+        </div>
+      </div>
+    </div>
+  );
+}
@@ -1,5 +1,5 @@
 import React, { useState, useEffect } from 'react';
-import { Clock, Play, Pause, Trash2, Plus, Calendar, TrendingUp } from 'lucide-react';
+import { Clock, Play, Pause, Trash2, Plus, Calendar, TrendingUp, Zap, Edit2, Moon } from 'lucide-react';
 import { invoke } from '@tauri-apps/api/tauri';
 
 interface Task {
@@ -13,14 +13,66 @@ interface Task {
   last_run: string | null;
   next_run: string | null;
   run_count: number;
+  timezone: string;
+  misfire_policy: MisfirePolicy;
+  timeout_seconds: number;
+  retry_policy: RetryPolicy;
+  workflow: Workflow | null;
+  paused_until: string | null;
+  sinks: OutputSink[];
+}
+
+type OutputSink =
+  | { type: 'File'; path: string }
+  | { type: 'Session'; session_id: string }
+  | { type: 'Notification' }
+  | { type: 'Webhook'; url: string };
+
+interface Workflow {
+  steps: {
+    id: string;
+    agent_id?: string;
+    prompt_template: string;
+    depends_on?: string[];
+    on_failure?: { type: 'Halt' | 'Continue' } | { type: 'Fallback'; step_id: string };
+  }[];
+}
+
+interface StepResult {
+  step_id: string;
+  agent_id: string;
+  prompt: string;
+  response: string;
+  success: boolean;
+  error: string | null;
+  skipped: boolean;
+  duration_ms: number;
+  fallback_step: string | null;
+}
+
+interface RetryPolicy {
+  max_attempts: number;
+  backoff_base_seconds: number;
+  backoff_cap_seconds: number;
+  retry_on: ('Timeout' | 'Unreachable' | 'Ollama' | 'Other')[];
+}
+
+interface MisfirePolicy {
+  type: 'Skip' | 'RunOnce' | 'RunAll';
+  max_runs?: number;
 }
 
 interface ScheduleType {
-  type: 'Interval' | 'Hourly' | 'Daily' | 'Weekly';
+  type: 'Interval' | 'Hourly' | 'Daily' | 'Weekly' | 'Cron' | 'FileWatch';
   minutes?: number;
   at_minute?: number;
   at_hour?: number;
   day?: number;
+  expr?: string;
+  path?: string;
+  patterns?: string[];
+  recursive?: boolean;
+  debounce_seconds?: number;
 }
 
 interface TaskResult {
@@ -32,6 +84,17 @@ interface TaskResult {
   response: string;
   success: boolean;
   error: string | null;
+  scheduled_for: string | null;
+  skipped: boolean;
+  skip_reason?: string;
+  skipped_runs?: number;
+  duration_ms: number;
+  timed_out: boolean;
+  run_id: string;
+  attempt: number;
+  error_class: string | null;
+  steps?: StepResult[];
+  sink_errors?: { sink: string; error: string }[];
 }
 
 interface Agent {
@@ -49,6 +112,9 @@ const TaskScheduler: React.FC<TaskSchedulerProps> = ({ agents, onClose }) => {
   const [tasks, setTasks] = useState<Task[]>([]);
   const [results, setResults] = useState<TaskResult[]>([]);
   const [showCreateForm, setShowCreateForm] = useState(false);
+  const [editingTaskId, setEditingTaskId] = useState<string | null>(null);
+  const [pauseTaskId, setPauseTaskId] = useState<string | null>(null);
+  const [pauseUntil, setPauseUntil] = useState('');
   const [activeTab, setActiveTab] = useState<'tasks' | 'results'>('tasks');
   
   // Form state
@@ -56,11 +122,20 @@ const TaskScheduler: React.FC<TaskSchedulerProps> = ({ agents, onClose }) => {
     name: '',
     agent_id: agents[0]?.id || '',
     prompt_template: '',
-    scheduleType: 'Interval' as 'Interval' | 'Hourly' | 'Daily' | 'Weekly',
+    scheduleType: 'Interval' as ScheduleType['type'],
     minutes: 60,
     at_minute: 0,
     at_hour: 9,
     day: 1, // Monday
+    expr: '0 9 * * MON-FRI',
+    watchPath: '',
+    watchPatterns: '',
+    debounceSeconds: 5,
+    misfirePolicy: 'RunOnce' as MisfirePolicy['type'],
+    maxRuns: 5,
+    notify: false,
+    outputFile: '',
+    webhookUrl: '',
   });
 
   useEffect(() => {
@@ -88,7 +163,7 @@ const TaskScheduler: React.FC<TaskSchedulerProps> = ({ agents, onClose }) => {
     try {
       const response: any = await invoke('get_task_results', { limit: 20 });
       if (response.success && response.data) {
-        setResults(response.data);
+        setResults(response.data.results);
       }
     } catch (error) {
       console.error('Failed to load results:', error);
@@ -115,18 +190,55 @@ const TaskScheduler: React.FC<TaskSchedulerProps> = ({ agents, onClose }) => {
       case 'Weekly':
         schedule_type = { type: 'Weekly', day: formData.day, at_hour: formData.at_hour, at_minute: formData.at_minute };
         break;
+      case 'Cron':
+        schedule_type = { type: 'Cron', expr: formData.expr };
+        break;
+      case 'FileWatch':
+        schedule_type = {
+          type: 'FileWatch',
+          path: formData.watchPath,
+          patterns: formData.watchPatterns.split(',').map((p) => p.trim()).filter((p) => p),
+          debounce_seconds: formData.debounceSeconds,
+        };
+        break;
     }
 
+    const misfire_policy = formData.misfirePolicy === 'RunAll'
+      ? { type: 'RunAll', max_runs: formData.maxRuns }
+      : { type: formData.misfirePolicy };
+
+    // Session sinks aren't edited here, so keep the ones the task already has
+    const editedTask = tasks.find((t) => t.id === editingTaskId);
+    const sinks: OutputSink[] = (editedTask?.sinks ?? []).filter((sink) => sink.type === 'Session');
+    if (formData.notify) sinks.push({ type: 'Notification' });
+    if (formData.outputFile) sinks.push({ type: 'File', path: formData.outputFile });
+    if (formData.webhookUrl) sinks.push({ type: 'Webhook', url: formData.webhookUrl });
+
     try {
-      const response: any = await invoke('create_task', {
-        name: formData.name,
-        agentId: formData.agent_id,
-        promptTemplate: formData.prompt_template,
-        scheduleType: schedule_type,
-      });
+      const response: any = editingTaskId
+        ? await invoke('update_task', {
+            taskId: editingTaskId,
+            update: {
+              name: formData.name,
+              agent_id: formData.agent_id,
+              prompt_template: formData.prompt_template,
+              schedule_type,
+              misfire_policy,
+              sinks,
+            },
+          })
+        : await invoke('create_task', {
+            name: formData.name,
+            agentId: formData.agent_id,
+            promptTemplate: formData.prompt_template,
+            scheduleType: schedule_type,
+            misfirePolicy: misfire_policy,
+            sinks,
+          });
 
       if (response.success) {
         setShowCreateForm(false);
+        setEditingTaskId(null);
         setFormData({
           name: '',
           agent_id: agents[0]?.id || '',
@@ -136,14 +248,77 @@ const TaskScheduler: React.FC<TaskSchedulerProps> = ({ agents, onClose }) => {
           at_minute: 0,
           at_hour: 9,
           day: 1,
+          expr: '0 9 * * MON-FRI',
+          watchPath: '',
+          watchPatterns: '',
+          debounceSeconds: 5,
+          misfirePolicy: 'RunOnce',
+          maxRuns: 5,
+          notify: false,
+          outputFile: '',
+          webhookUrl: '',
         });
         loadTasks();
       } else {
-        alert(`Failed to create task: ${response.error}`);
+        alert(`Failed to ${editingTaskId ? 'update' : 'create'} task: ${response.error}`);
+      }
+    } catch (error) {
+      console.error('Failed to save task:', error);
+      alert('Failed to save task');
+    }
+  };
+
+  const startEdit = (task: Task) => {
+    const schedule = task.schedule_type;
+    setFormData({
+      ...formData,
+      name: task.name,
+      agent_id: task.agent_id,
+      prompt_template: task.prompt_template,
+      scheduleType: schedule.type,
+      minutes: schedule.minutes ?? formData.minutes,
+      at_minute: schedule.at_minute ?? formData.at_minute,
+      at_hour: schedule.at_hour ?? formData.at_hour,
+      day: schedule.day ?? formData.day,
+      expr: schedule.expr ?? formData.expr,
+      watchPath: schedule.path ?? '',
+      watchPatterns: (schedule.patterns ?? []).join(', '),
+      debounceSeconds: schedule.debounce_seconds ?? formData.debounceSeconds,
+      misfirePolicy: task.misfire_policy.type,
+      maxRuns: task.misfire_policy.max_runs ?? formData.maxRuns,
+      notify: task.sinks.some((sink) => sink.type === 'Notification'),
+      outputFile: task.sinks.reduce((path, sink) => (sink.type === 'File' ? sink.path : path), ''),
+      webhookUrl: task.sinks.reduce((url, sink) => (sink.type === 'Webhook' ? sink.url : url), ''),
+    });
+    setEditingTaskId(task.id);
+    setShowCreateForm(true);
+  };
+
+  const runTaskNow = async (taskId: string) => {
+    try {
+      const response: any = await invoke('run_task_now', { taskId });
+      if (!response.success) {
+        alert(`Failed to run task: ${response.error}`);
       }
+      loadTasks();
+    } catch (error) {
+      console.error('Failed to run task:', error);
+    }
+  };
+
+  const pauseTask = async (taskId: string, until: string | null) => {
+    try {
+      const response: any = await invoke('pause_task_until', {
+        taskId,
+        until: until ? new Date(until).toISOString() : null,
+      });
+      if (!response.success) {
+        alert(`Failed to pause task: ${response.error}`);
+      }
+      setPauseTaskId(null);
+      loadTasks();
     } catch (error) {
-      console.error('Failed to create task:', error);
-      alert('Failed to create task');
+      console.error('Failed to pause task:', error);
     }
   };
 
@@ -178,6 +353,10 @@ const TaskScheduler: React.FC<TaskSchedulerProps> = ({ agents, onClose }) => {
       case 'Weekly':
         const days = ['Sunday', 'Monday', 'Tuesday', 'Wednesday', 'Thursday', 'Friday', 'Saturday'];
         return `${days[schedule.day || 0]} at ${String(schedule.at_hour).padStart(2, '0')}:${String(schedule.at_minute).padStart(2, '0')}`;
+      case 'Cron':
+        return `Cron: ${schedule.expr}`;
+      case 'FileWatch':
+        return `On changes in ${schedule.path}${schedule.patterns?.length ? ` (${schedule.patterns.join(', ')})` : ''}`;
       default:
         return 'Unknown';
     }
@@ -254,7 +433,7 @@ const TaskScheduler: React.FC<TaskSchedulerProps> = ({ agents, onClose }) => {
               {/* Create Task Form */}
               {showCreateForm && (
                 <div className="mb-6 glass-effect rounded-xl p-6 border border-blue-500/30">
-                  <h3 className="text-lg font-bold mb-4">Create New Task</h3>
+                  <h3 className="text-lg font-bold mb-4">{editingTaskId ? 'Edit Task' : 'Create New Task'}</h3>
                   
                   <div className="space-y-4">
                     <div>
@@ -287,7 +466,7 @@ const TaskScheduler: React.FC<TaskSchedulerProps> = ({ agents, onClose }) => {
                         value={formData.prompt_template}
                         onChange={(e) => setFormData({ ...formData, prompt_template: e.target.value })}
                         className="w-full bg-black/30 border border-white/10 rounded-lg px-4 py-2 focus:outline-none focus:border-blue-500 h-24 resize-none"
-                        placeholder="Use {date}, {time}, {datetime} as placeholders"
+                        placeholder="Use {date}, {task.name}, {previous.output}, {date - 1d}, {#if ...}...{/if} — see README"
                       />
                     </div>
 
@@ -302,9 +481,114 @@ const TaskScheduler: React.FC<TaskSchedulerProps> = ({ agents, onClose }) => {
                         <option value="Hourly">Hourly</option>
                         <option value="Daily">Daily</option>
                         <option value="Weekly">Weekly</option>
+                        <option value="Cron">Cron</option>
+                        <option value="FileWatch">File Changes</option>
                       </select>
                     </div>
 
+                    <div>
+                      <label className="block text-sm font-semibold mb-2">Missed Runs</label>
+                      <div className="flex gap-2">
+                        <select
+                          value={formData.misfirePolicy}
+                          onChange={(e) => setFormData({ ...formData, misfirePolicy: e.target.value as any })}
+                          className="flex-1 bg-black/30 border border-white/10 rounded-lg px-4 py-2 focus:outline-none focus:border-blue-500"
+                        >
+                          <option value="Skip">Skip missed runs</option>
+                          <option value="RunOnce">Run once to catch up</option>
+                          <option value="RunAll">Run each missed run</option>
+                        </select>
+                        {formData.misfirePolicy === 'RunAll' && (
+                          <input
+                            type="number"
+                            value={formData.maxRuns}
+                            onChange={(e) => setFormData({ ...formData, maxRuns: parseInt(e.target.value) || 1 })}
+                            className="w-24 bg-black/30 border border-white/10 rounded-lg px-4 py-2 focus:outline-none focus:border-blue-500"
+                            min="1"
+                            title="Maximum catch-up runs"
+                          />
+                        )}
+                      </div>
+                    </div>
+
+                    <div>
+                      <label className="block text-sm font-semibold mb-2">Deliver Results</label>
+                      <div className="space-y-2">
+                        <label className="flex items-center gap-2 text-sm">
+                          <input
+                            type="checkbox"
+                            checked={formData.notify}
+                            onChange={(e) => setFormData({ ...formData, notify: e.target.checked })}
+                          />
+                          Desktop notification
+                        </label>
+                        <input
+                          type="text"
+                          value={formData.outputFile}
+                          onChange={(e) => setFormData({ ...formData, outputFile: e.target.value })}
+                          className="w-full bg-black/30 border border-white/10 rounded-lg px-4 py-2 font-mono focus:outline-none focus:border-blue-500"
+                          placeholder="Save to file, e.g. /home/me/reports/{date}-{task.name}.md"
+                        />
+                        <input
+                          type="text"
+                          value={formData.webhookUrl}
+                          onChange={(e) => setFormData({ ...formData, webhookUrl: e.target.value })}
+                          className="w-full bg-black/30 border border-white/10 rounded-lg px-4 py-2 font-mono focus:outline-none focus:border-blue-500"
+                          placeholder="Webhook, e.g. http://localhost:8080/hook"
+                        />
+                      </div>
+                    </div>
+
+                    {formData.scheduleType === 'Cron' && (
+                      <div>
+                        <label className="block text-sm font-semibold mb-2">Cron Expression</label>
+                        <input
+                          type="text"
+                          value={formData.expr}
+                          onChange={(e) => setFormData({ ...formData, expr: e.target.value })}
+                          className="w-full bg-black/30 border border-white/10 rounded-lg px-4 py-2 font-mono focus:outline-none focus:border-blue-500"
+                          placeholder="minute hour day-of-month month day-of-week"
+                        />
+                      </div>
+                    )}
+
+                    {formData.scheduleType === 'FileWatch' && (
+                      <>
+                        <div>
+                          <label className="block text-sm font-semibold mb-2">Watch Directory</label>
+                          <input
+                            type="text"
+                            value={formData.watchPath}
+                            onChange={(e) => setFormData({ ...formData, watchPath: e.target.value })}
+                            className="w-full bg-black/30 border border-white/10 rounded-lg px-4 py-2 font-mono focus:outline-none focus:border-blue-500"
+                            placeholder="/home/me/Documents/inbox"
+                          />
+                        </div>
+                        <div className="grid grid-cols-2 gap-4">
+                          <div>
+                            <label className="block text-sm font-semibold mb-2">File Patterns</label>
+                            <input
+                              type="text"
+                              value={formData.watchPatterns}
+                              onChange={(e) => setFormData({ ...formData, watchPatterns: e.target.value })}
+                              className="w-full bg-black/30 border border-white/10 rounded-lg px-4 py-2 font-mono focus:outline-none focus:border-blue-500"
+                              placeholder="**/*.md, *.txt (empty = all files)"
+                            />
+                          </div>
+                          <div>
+                            <label className="block text-sm font-semibold mb-2">Debounce (seconds)</label>
+                            <input
+                              type="number"
+                              value={formData.debounceSeconds}
+                              onChange={(e) => setFormData({ ...formData, debounceSeconds: parseInt(e.target.value) || 0 })}
+                              className="w-full bg-black/30 border border-white/10 rounded-lg px-4 py-2 focus:outline-none focus:border-blue-500"
+                              min="0"
+                            />
+                          </div>
+                        </div>
+                      </>
+                    )}
+
                     {formData.scheduleType === 'Interval' && (
                       <div>
                         <label className="block text-sm font-semibold mb-2">Interval (minutes)</label>
@@ -388,10 +672,13 @@ const TaskScheduler: React.FC<TaskSchedulerProps> = ({ agents, onClose }) => {
                       onClick={createTask}
                       className="flex-1 px-4 py-2 bg-gradient-to-r from-blue-600 to-purple-600 hover:from-blue-700 hover:to-purple-700 rounded-lg transition-all duration-300 font-semibold"
                     >
-                      Create Task
+                      {editingTaskId ? 'Save Changes' : 'Create Task'}
                     </button>
                     <button
-                      onClick={() => setShowCreateForm(false)}
+                      onClick={() => {
+                        setShowCreateForm(false);
+                        setEditingTaskId(null);
+                      }}
                       className="px-4 py-2 bg-white/5 hover:bg-white/10 rounded-lg transition-all duration-300"
                     >
                       Cancel
@@ -422,6 +709,31 @@ const TaskScheduler: React.FC<TaskSchedulerProps> = ({ agents, onClose }) => {
                           <p className="text-xs text-gray-500 line-clamp-2">{task.prompt_template}</p>
                         </div>
                         <div className="flex items-center gap-2">
+                          <button
+                            onClick={() => runTaskNow(task.id)}
+                            className="p-2 bg-green-500/20 text-green-400 hover:bg-green-500/30 rounded-lg transition-all duration-300"
+                            title="Run now"
+                          >
+                            <Zap size={18} />
+                          </button>
+                          <button
+                            onClick={() => startEdit(task)}
+                            className="p-2 bg-white/5 text-gray-400 hover:bg-white/10 rounded-lg transition-all duration-300"
+                            title="Edit"
+                          >
+                            <Edit2 size={18} />
+                          </button>
+                          <button
+                            onClick={() => (task.paused_until ? pauseTask(task.id, null) : setPauseTaskId(task.id))}
+                            className={`p-2 rounded-lg transition-all duration-300 ${
+                              task.paused_until
+                                ? 'bg-yellow-500/20 text-yellow-400 hover:bg-yellow-500/30'
+                                : 'bg-white/5 text-gray-400 hover:bg-white/10'
+                            }`}
+                            title={task.paused_until ? 'Lift pause' : 'Pause until...'}
+                          >
+                            <Moon size={18} />
+                          </button>
                           <button
                             onClick={() => toggleTask(task.id, !task.enabled)}
                             className={`p-2 rounded-lg transition-all duration-300 ${
@@ -443,6 +755,30 @@ const TaskScheduler: React.FC<TaskSchedulerProps> = ({ agents, onClose }) => {
                         </div>
                       </div>
                       
+                      {pauseTaskId === task.id && (
+                        <div className="flex items-center gap-2 mb-3">
+                          <input
+                            type="datetime-local"
+                            value={pauseUntil}
+                            onChange={(e) => setPauseUntil(e.target.value)}
+                            className="bg-black/30 border border-white/10 rounded-lg px-3 py-1 text-sm focus:outline-none focus:border-blue-500"
+                          />
+                          <button
+                            onClick={() => pauseTask(task.id, pauseUntil)}
+                            disabled={!pauseUntil}
+                            className="px-3 py-1 bg-yellow-500/20 text-yellow-400 hover:bg-yellow-500/30 rounded-lg text-sm disabled:opacity-50"
+                          >
+                            Pause
+                          </button>
+                          <button
+                            onClick={() => setPauseTaskId(null)}
+                            className="px-3 py-1 bg-white/5 hover:bg-white/10 rounded-lg text-sm"
+                          >
+                            Cancel
+                          </button>
+                        </div>
+                      )}
+
                       <div className="flex items-center gap-6 text-xs text-gray-400 pt-3 border-t border-white/10">
                         <div className="flex items-center gap-2">
                           <TrendingUp size={14} />
@@ -450,6 +786,7 @@ const TaskScheduler: React.FC<TaskSchedulerProps> = ({ agents, onClose }) => {
                         </div>
                         <div>Last: {formatDate(task.last_run)}</div>
                         {task.enabled && <div>Next: {formatDate(task.next_run)}</div>}
+                        {task.paused_until && <div className="text-yellow-400">Paused until {formatDate(task.paused_until)}</div>}
                       </div>
                     </div>
                   ))
@@ -470,32 +807,73 @@ const TaskScheduler: React.FC<TaskSchedulerProps> = ({ agents, onClose }) => {
                   <div
                     key={idx}
                     className={`glass-effect rounded-xl p-4 border ${
-                      result.success ? 'border-green-500/30' : 'border-red-500/30'
+                      result.success ? 'border-green-500/30' : result.skipped ? 'border-yellow-500/30' : 'border-red-500/30'
                     }`}
                   >
                     <div className="flex items-start justify-between mb-2">
                       <div>
                         <h4 className="font-bold">{result.task_name}</h4>
-                        <p className="text-xs text-gray-400">{result.agent_name}</p>
+                        <p className="text-xs text-gray-400">
+                          {result.agent_name}
+                          {result.attempt > 1 && ` · attempt ${result.attempt}`}
+                        </p>
+                      </div>
+                      <div className="text-xs text-gray-500 text-right">
+                        <div>{formatDate(result.executed_at)}</div>
+                        {!result.skipped && (
+                          <div>
+                            {(result.duration_ms / 1000).toFixed(1)}s{result.timed_out && ' (timed out)'}
+                          </div>
+                        )}
                       </div>
-                      <div className="text-xs text-gray-500">{formatDate(result.executed_at)}</div>
                     </div>
                     
                     <div className="text-sm text-gray-400 mb-2">
                       <strong>Prompt:</strong> {result.prompt}
                     </div>
+
+                    {result.steps && result.steps.length > 0 && (
+                      <div className="text-xs mb-2 space-y-1">
+                        {result.steps.map((step, stepIdx) => (
+                          <div key={stepIdx} className="flex items-center gap-2">
+                            <span className={step.success ? 'text-green-400' : step.skipped ? 'text-yellow-400' : 'text-red-400'}>
+                              {step.success ? '✓' : step.skipped ? '–' : '✗'}
+                            </span>
+                            <span className="font-mono">{step.step_id}</span>
+                            {step.fallback_step && <span className="text-gray-500">→ fallback {step.fallback_step}</span>}
+                            {!step.success && step.error && <span className="text-gray-500 truncate">{step.error}</span>}
+                          </div>
+                        ))}
+                      </div>
+                    )}
                     
                     {result.success ? (
                       <div className="text-sm bg-green-500/10 rounded-lg p-3 border border-green-500/20">
                         <strong className="text-green-400">Response:</strong>
                         <p className="mt-1 text-gray-300">{result.response}</p>
                       </div>
+                    ) : result.skipped ? (
+                      <div className="text-sm bg-yellow-500/10 rounded-lg p-3 border border-yellow-500/20">
+                        <strong className="text-yellow-400">Skipped:</strong>
+                        {/* Older entries kept the reason in error */}
+                        <p className="mt-1 text-gray-300">{result.skip_reason ?? result.error}</p>
+                      </div>
                     ) : (
                       <div className="text-sm bg-red-500/10 rounded-lg p-3 border border-red-500/20">
                         <strong className="text-red-400">Error:</strong>
                         <p className="mt-1 text-gray-300">{result.error}</p>
                       </div>
                     )}
+
+                    {result.sink_errors && result.sink_errors.length > 0 && (
+                      <div className="text-xs text-red-400 mt-2 space-y-1">
+                        {result.sink_errors.map((failure, failureIdx) => (
+                          <div key={failureIdx}>
+                            Delivery to {failure.sink} failed: {failure.error}
+                          </div>
+                        ))}
+                      </div>
+                    )}
                   </div>
                 ))
               )}